@@ -0,0 +1,299 @@
+use crate::parser::*;
+use crate::random::RANDOM_FUNCTIONS;
+use crate::stdlib::{CLOCK_FUNCTIONS, INPUT_FUNCTIONS, is_builtin};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+// ways a program can reach outside of itself, other than printing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    // reads the program's input
+    Input,
+    // reads environment variables
+    Environment,
+    // reads the time or pauses
+    Clock,
+    // draws random numbers, so runs without a seed can differ
+    Random,
+}
+
+impl Capability {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Input => "input",
+            Capability::Environment => "environment",
+            Capability::Clock => "clock",
+            Capability::Random => "random",
+        }
+    }
+
+    // gets what calling a built-in function can do
+    fn of_builtin(name: &str) -> Option<Capability> {
+        if INPUT_FUNCTIONS.contains(&name) {
+            return Some(Capability::Input);
+        }
+        if CLOCK_FUNCTIONS.contains(&name) {
+            return Some(Capability::Clock);
+        }
+        if RANDOM_FUNCTIONS.contains(&name) {
+            return Some(Capability::Random);
+        }
+        if name == "GetEnv" {
+            return Some(Capability::Environment);
+        }
+        return None;
+    }
+}
+
+// what a program could do when run, found without running it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub capabilities: BTreeSet<Capability>,
+    // built-in functions the program calls
+    pub builtins: BTreeSet<String>,
+    // names of every variable and parameter
+    pub variables: BTreeSet<String>,
+    // number of statements, not counting function declarations
+    pub statements: usize,
+    // most loops inside each other
+    pub max_loop_depth: usize,
+    // some function can end up calling itself
+    pub recursion: bool,
+}
+
+#[derive(Debug, Default)]
+struct Walker {
+    capabilities: BTreeSet<Capability>,
+    builtins: BTreeSet<String>,
+    variables: BTreeSet<String>,
+    statements: usize,
+    max_loop_depth: usize,
+    // functions of the program called by the function being walked
+    calls: BTreeSet<String>,
+}
+
+impl Walker {
+    fn call(&mut self, func: &String, functions: &HashMap<String, ASTNode>) {
+        // functions of the program are called instead of built-ins of the same name
+        if functions.contains_key(func) {
+            self.calls.insert(func.clone());
+        } else if is_builtin(func) {
+            self.builtins.insert(func.clone());
+            if let Some(capability) = Capability::of_builtin(func) {
+                self.capabilities.insert(capability);
+            }
+        }
+    }
+
+    fn walk(&mut self, body: &[ASTNode], loop_depth: usize, functions: &HashMap<String, ASTNode>) {
+        use ASTNode::*;
+        for node in body {
+            self.statements += 1;
+            match node {
+                Let(_, name) => {
+                    self.variables.insert(name.clone());
+                }
+                If(_, _, inner) => self.walk(inner, loop_depth, functions),
+                While(_, _, inner) => {
+                    self.max_loop_depth = self.max_loop_depth.max(loop_depth + 1);
+                    self.walk(inner, loop_depth + 1, functions);
+                }
+                ForEach(_, name, index, _, inner) => {
+                    self.variables.insert(name.clone());
+                    self.variables.extend(index.iter().cloned());
+                    self.max_loop_depth = self.max_loop_depth.max(loop_depth + 1);
+                    self.walk(inner, loop_depth + 1, functions);
+                }
+                Try(_, inner, name, _, handler) => {
+                    self.variables.insert(name.clone());
+                    self.walk(inner, loop_depth, functions);
+                    self.walk(handler, loop_depth, functions);
+                }
+                Capture(_, _, inner) => self.walk(inner, loop_depth, functions),
+                Sleep(_, _) => {
+                    self.capabilities.insert(Capability::Clock);
+                }
+                Run(_, func, _) | RunAssign(_, _, func, _) => self.call(func, functions),
+                _ => (),
+            }
+        }
+    }
+}
+
+// checks if a function can be reached again by following the calls from it
+fn reaches(start: &String, calls: &HashMap<String, BTreeSet<String>>) -> bool {
+    let mut seen: BTreeSet<&String> = BTreeSet::new();
+    let mut stack: Vec<&String> = calls[start].iter().collect();
+    while let Some(func) = stack.pop() {
+        if func == start {
+            return true;
+        }
+        if seen.insert(func) {
+            stack.extend(calls[func].iter());
+        }
+    }
+    return false;
+}
+
+// finds what a parsed program could do without running it
+pub fn capabilities(functions: &HashMap<String, ASTNode>) -> Capabilities {
+    let mut walker = Walker::default();
+    let mut calls: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for (name, node) in functions {
+        if let ASTNode::Function(_, _, args, _, body) = node {
+            walker.variables.extend(args.iter().cloned());
+            walker.walk(body, 0, functions);
+        }
+        calls.insert(name.clone(), std::mem::take(&mut walker.calls));
+    }
+    let recursion = calls.keys().any(|name| reaches(name, &calls));
+    return Capabilities {
+        capabilities: walker.capabilities,
+        builtins: walker.builtins,
+        variables: walker.variables,
+        statements: walker.statements,
+        max_loop_depth: walker.max_loop_depth,
+        recursion,
+    };
+}
+
+// lists names separated by commas, or none
+fn list<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    let names: Vec<&str> = names.collect();
+    if names.is_empty() {
+        return String::from("none");
+    }
+    return names.join(", ");
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Capabilities: {}", list(self.capabilities.iter().map(|capability| capability.name())))?;
+        writeln!(f, "Built-in functions: {}", list(self.builtins.iter().map(|name| &name[..])))?;
+        writeln!(f, "Variables: {}", list(self.variables.iter().map(|name| &name[..])))?;
+        writeln!(f, "Statements: {}", self.statements)?;
+        writeln!(f, "Deepest loop nesting: {}", self.max_loop_depth)?;
+        write!(f, "Recursion: {}", if self.recursion { "yes" } else { "no" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn of(src: &str) -> Capabilities {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        return capabilities(&Parser::new(tokens).parse().unwrap());
+    }
+
+    // a program calling a built-in function with one argument and storing what it returns
+    fn calling(func: &str) -> String {
+        return format!(
+            "[Chorus]\nNever gonna let a down\nNever gonna give a \"HOME\"\n(Ooh give you a) Never gonna run {} and desert a\n",
+            func
+        );
+    }
+
+    #[test]
+    fn straight_line_arithmetic() {
+        let found = of("[Chorus]\nNever gonna let a down\nNever gonna give a 6 * 7\nNever gonna say a + 1\n");
+        assert!(found.capabilities.is_empty());
+        assert!(found.builtins.is_empty());
+        assert_eq!(found.variables, BTreeSet::from([String::from("a")]));
+        assert_eq!(found.statements, 3);
+        assert_eq!(found.max_loop_depth, 0);
+        assert!(!found.recursion);
+        assert_eq!(
+            found.to_string(),
+            "Capabilities: none\nBuilt-in functions: none\nVariables: a\nStatements: 3\nDeepest loop nesting: 0\nRecursion: no"
+        );
+    }
+
+    #[test]
+    fn capability_of_each_feature() {
+        let cases = [
+            ("ReadLine", Capability::Input),
+            ("ReadToken", Capability::Input),
+            ("ReadKey", Capability::Input),
+            ("GetEnv", Capability::Environment),
+            ("Now", Capability::Clock),
+            ("Elapsed", Capability::Clock),
+            ("Random", Capability::Random),
+            ("Shuffle", Capability::Random),
+            ("Sample", Capability::Random),
+        ];
+        for (func, capability) in cases {
+            let found = of(&calling(func));
+            assert_eq!(found.capabilities, BTreeSet::from([capability]), "{}", func);
+            assert_eq!(found.builtins, BTreeSet::from([String::from(func)]), "{}", func);
+        }
+        let found = of("[Chorus]\nWe've been waiting for 10\n");
+        assert_eq!(found.capabilities, BTreeSet::from([Capability::Clock]));
+        // built-in functions that stay inside the program can do nothing more
+        let found = of(&calling("ArrayLength"));
+        assert!(found.capabilities.is_empty());
+        assert_eq!(found.builtins, BTreeSet::from([String::from("ArrayLength")]));
+    }
+
+    #[test]
+    fn capabilities_found_in_every_block() {
+        let src = "\
+[Verse Pick]
+(Ooh give you xs)
+Never gonna let res down
+Inside we both know TRUE
+    Never gonna make you cry
+        (Ooh give you res) Never gonna run Shuffle and desert xs
+    Never gonna tell a lie and hurt err
+        (Ooh give you res) Never gonna run ReadLine and desert you
+    Gotta make you understand
+Your heart's been aching but you're too shy to say it
+(Ooh) Never gonna give, never gonna give (give you res)
+[Chorus]
+Never gonna say 1
+";
+        let found = of(src);
+        // whether or not the function is ever called
+        assert_eq!(found.capabilities, BTreeSet::from([Capability::Input, Capability::Random]));
+        let names: Vec<&str> = found.variables.iter().map(|name| &name[..]).collect();
+        assert_eq!(names, ["err", "res", "xs"]);
+    }
+
+    #[test]
+    fn functions_shadow_builtins() {
+        let src = "[Verse Random]\n(Ooh give you up)\nNever gonna say 4\n[Chorus]\nNever gonna run Random and desert you\n";
+        let found = of(src);
+        assert!(found.capabilities.is_empty());
+        assert!(found.builtins.is_empty());
+    }
+
+    #[test]
+    fn loops_and_recursion() {
+        let src = "\
+[Verse Count]
+(Ooh give you n)
+Inside we both know n > 0
+    Never gonna let m down
+    Never gonna give m n - 1
+    Never gonna run Count and desert m
+Your heart's been aching but you're too shy to say it
+[Chorus]
+Never gonna let i down
+Never gonna give i 0
+Inside we both know i < 3
+    We've known each x in \"ab\" for so long
+        Never gonna say x
+    We know the game and we're gonna play it
+    Never gonna give i i + 1
+We know the game and we're gonna play it
+";
+        let found = of(src);
+        assert_eq!(found.max_loop_depth, 2);
+        assert!(found.recursion);
+        assert!(found.to_string().ends_with("Deepest loop nesting: 2\nRecursion: yes"));
+        let without = of(&src.replace("    Never gonna run Count and desert m\n", ""));
+        assert!(!without.recursion);
+    }
+}
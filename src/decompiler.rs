@@ -0,0 +1,231 @@
+use crate::error::*;
+use crate::expr::*;
+use crate::parser::*;
+
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+// renders parsed functions back into source code with the same behaviour
+// functions are written in the order they were declared
+pub fn decompile(functions: &HashMap<String, ASTNode>) -> Result<String, Error> {
+    let mut sorted: Vec<&ASTNode> = functions.values().collect();
+    sorted.sort_by_key(|node| node.get_line());
+    let mut res = Vec::new();
+    for node in sorted {
+        res.push(decompile_function(node)?);
+    }
+    return Ok(res.join("\n"));
+}
+
+fn decompile_function(node: &ASTNode) -> Result<String, Error> {
+    let mut res = String::new();
+    if let ASTNode::Function(_, name, args, defaults, body) = node {
+        match &name[..] {
+            "[CHORUS]" => res += "[Chorus]\n",
+            "[INTRO]" => res += "[Intro]\n",
+            _ => {
+                res += &format!("[Verse {}]\n", name);
+                let mut params = Vec::new();
+                for (arg, default) in args.iter().zip(defaults.iter()) {
+                    match default {
+                        Some(expr) => params.push(format!("{} = {}", arg, render_expr(expr)?)),
+                        None => params.push(arg.clone()),
+                    }
+                }
+                if params.is_empty() {
+                    res += "(Ooh give you up)\n";
+                } else {
+                    res += &format!("(Ooh give you {})\n", params.join(", "));
+                }
+            }
+        }
+        for statement in body {
+            decompile_statement(statement, 0, &mut res)?;
+        }
+        return Ok(res);
+    }
+    panic!("decompile_function called with non-function");
+}
+
+// renders the arguments of a function call
+fn call_args(args: &[String]) -> String {
+    if args.is_empty() {
+        return String::from("you");
+    }
+    return args.join(", ");
+}
+
+fn decompile_block(body: &[ASTNode], depth: usize, res: &mut String) -> Result<(), Error> {
+    for statement in body {
+        decompile_statement(statement, depth + 1, res)?;
+    }
+    return Ok(());
+}
+
+fn decompile_statement(statement: &ASTNode, depth: usize, res: &mut String) -> Result<(), Error> {
+    use ASTNode::*;
+    let indent = INDENT.repeat(depth);
+    let line = |text: String, res: &mut String| {
+        *res += &indent;
+        *res += &text;
+        res.push('\n');
+    };
+    match statement {
+        Say(_, expr) => line(format!("Never gonna say {}", render_expr(expr)?), res),
+        Let(_, name) => line(format!("Never gonna let {} down", name), res),
+        Assign(_, name, expr) => line(format!("Never gonna give {} {}", name, render_expr(expr)?), res),
+        Expect(_, name, type_name) => line(format!("You know the rules and {} is {}", name, type_name), res),
+        If(_, cond, body) => {
+            line(format!("Inside we both know {}", render_expr(cond)?), res);
+            decompile_block(body, depth, res)?;
+            line(String::from("Your heart's been aching but you're too shy to say it"), res);
+        }
+        While(_, cond, body) => {
+            line(format!("Inside we both know {}", render_expr(cond)?), res);
+            decompile_block(body, depth, res)?;
+            line(String::from("We know the game and we're gonna play it"), res);
+        }
+        ForEach(_, name, index, array, body) => {
+            let names = match index {
+                Some(index) => format!("{}, {}", name, index),
+                None => name.clone(),
+            };
+            line(format!("We've known each {} in {} for so long", names, render_expr(array)?), res);
+            decompile_block(body, depth, res)?;
+            line(String::from("We know the game and we're gonna play it"), res);
+        }
+        Try(_, body, name, filter, handler) => {
+            line(String::from("Never gonna make you cry"), res);
+            decompile_block(body, depth, res)?;
+            let caught = match filter {
+                Some(err) => err.name(),
+                None => String::from("lie"),
+            };
+            line(format!("Never gonna tell a {} and hurt {}", caught, name), res);
+            decompile_block(handler, depth, res)?;
+            line(String::from("Gotta make you understand"), res);
+        }
+        Capture(_, name, body) => {
+            line(format!("I just wanna tell {} how I'm feeling", name), res);
+            decompile_block(body, depth, res)?;
+            line(String::from("Don't tell me you're too blind to see"), res);
+        }
+        Return(_, expr) => line(
+            format!("(Ooh) Never gonna give, never gonna give (give you {})", render_expr(expr)?),
+            res,
+        ),
+        Sleep(_, expr) => line(format!("We've been waiting for {}", render_expr(expr)?), res),
+        Raise(_, err, expr) => {
+            let kind = if *err == ErrorType::UserError { String::from("guy") } else { err.name() };
+            line(format!("You wouldn't get this from any other {} {}", kind, render_expr(expr)?), res)
+        }
+        Explain(_, expr) => line(format!("And if you ask me how {} is feeling", render_expr(expr)?), res),
+        SayConstants(_, values) => {
+            for (_, value) in values {
+                line(format!("Never gonna say {}", render_expr(&Expr::Value(value.clone()))?), res);
+            }
+        }
+        Run(_, func, args) => line(format!("Never gonna run {} and desert {}", func, call_args(args)), res),
+        RunAssign(_, var, func, args) => line(
+            format!("(Ooh give you {}) Never gonna run {} and desert {}", var, func, call_args(args)),
+            res,
+        ),
+        Function(ln, _, _, _, _) => {
+            return Err(Error::from_message(
+                ErrorType::SyntaxError,
+                "nested-function",
+                &[],
+                Some(*ln),
+            ));
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::optimizer::Optimizer;
+
+    use std::io::Cursor;
+
+    fn parse(src: &str) -> HashMap<String, ASTNode> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut parsed = Parser::new(tokens).parse().unwrap();
+        Optimizer::new().run(&mut parsed);
+        return parsed;
+    }
+
+    // runs parsed functions, giving what they printed followed by the error they failed with
+    fn run(functions: HashMap<String, ASTNode>, input: &str) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        let res = Interpreter::new(functions).run(&mut output, &mut Cursor::new(input.as_bytes()));
+        let mut res_text = String::from_utf8(output).unwrap();
+        if let Err(err) = res {
+            res_text += &err.to_string();
+        }
+        return res_text;
+    }
+
+    // decompiles the program and checks that the source runs the same way on every input
+    fn round_trip(src: &str, inputs: &[&str]) -> String {
+        let source = decompile(&parse(src)).unwrap();
+        for input in inputs {
+            assert_eq!(run(parse(&source), input), run(parse(src), input), "decompiled:\n{}", source);
+        }
+        return source;
+    }
+
+    const PROGRAM: &str = "\
+[Verse check]
+(Ooh give you n, limit = 10)
+Inside we both know n > limit
+    You wouldn't get this from any other ValueError \"too big\"
+Your heart's been aching but you're too shy to say it
+(Ooh) Never gonna give, never gonna give (give you n * 2)
+
+[Chorus]
+Never gonna say \"numbers\"
+Never gonna say 0 - 5
+Never gonna say 0 - 2147483647 - 1
+Never gonna say ~1.5
+Never gonna let n down
+Never gonna let sum down
+Never gonna give sum 0
+(Ooh give you n) Never gonna run ReadToken and desert you
+Inside we both know n != UNDEFINED
+    Never gonna make you cry
+        (Ooh give you n) Never gonna run check and desert n
+        Never gonna give sum sum + n
+    Never gonna tell a ValueError and hurt err
+        Never gonna say err
+    Gotta make you understand
+    (Ooh give you n) Never gonna run ReadToken and desert you
+We know the game and we're gonna play it
+We've known each c, i in \"ab\" for so long
+    Never gonna say i
+    Never gonna say c
+We know the game and we're gonna play it
+Never gonna say sum
+";
+
+    #[test]
+    fn decompiled_programs_run_the_same() {
+        let source = round_trip(PROGRAM, &["", "1 2 3", "4 20 -6", "x"]);
+        // the first prints are joined by the optimizer and come back as separate statements
+        assert!(source.contains("Never gonna say ~5\nNever gonna say (~2147483647 - 1)\nNever gonna say ~1.5\n"), "{}", source);
+        // decompiling the decompiled program changes nothing
+        assert_eq!(decompile(&parse(&source)).unwrap(), source);
+    }
+
+    #[test]
+    fn decompiled_user_errors() {
+        let src = "[Chorus]\nNever gonna say 1\nYou wouldn't get this from any other guy 1 + 2\n";
+        let source = round_trip(src, &[""]);
+        assert!(source.ends_with("You wouldn't get this from any other guy 3\n"), "{}", source);
+        assert!(run(parse(&source), "").ends_with("User Error on line 3: 3"));
+    }
+}
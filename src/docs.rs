@@ -0,0 +1,631 @@
+use crate::lexer::*;
+use crate::parser::*;
+use crate::util::*;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+// documentation of a statement, operator, built-in function or name in a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocEntry {
+    pub name: String,
+    pub syntax: String,
+    pub description: String,
+    pub example: String,
+}
+
+impl DocEntry {
+    fn new(name: &str, syntax: &str, description: &str, example: &str) -> DocEntry {
+        DocEntry {
+            name: String::from(name),
+            syntax: String::from(syntax),
+            description: String::from(description),
+            example: String::from(example),
+        }
+    }
+}
+
+// keyword, pattern, syntax, description, example
+// the groups of each pattern are the parts of the statement written by the programmer
+// statements are matched in order, so RUN_ASSIGN comes before ARGS
+// GUARD comes before CHECK, which would match it too
+const STATEMENTS: [(&str, &str, &str, &str, &str); 24] = [
+    (
+        "SAY",
+        r"(?s)^Never gonna say (.+)$",
+        "Never gonna say EXPR",
+        "Prints the value of an expression followed by a newline.",
+        "Never gonna say 1 + 2",
+    ),
+    (
+        "LET",
+        r"^Never gonna let (\w+) down$",
+        "Never gonna let VAR down",
+        "Declares a variable in the current block. Its value starts as UNDEFINED.",
+        "Never gonna let a down",
+    ),
+    (
+        "ASSIGN",
+        r"(?s)^Never gonna give (\w+) (.+)$",
+        "Never gonna give VAR EXPR",
+        "Sets a variable to the value of an expression.",
+        "Never gonna give a 3 * 4",
+    ),
+    (
+        "EXPECT",
+        r"^You know the rules and (\w+) is (\w+)$",
+        "You know the rules and VAR is TYPE",
+        "Throws a type error unless the variable has the given type.",
+        "You know the rules and a is INT",
+    ),
+    (
+        "GUARD",
+        r"(?s)^Inside we both know (.+), never gonna give \(give you (.+)\)$",
+        "Inside we both know EXPR, never gonna give (give you EXPR)",
+        "Returns the value of the second expression from a function if the BOOL condition is TRUE, and does nothing otherwise.",
+        "Inside we both know n < 2, never gonna give (give you n)",
+    ),
+    (
+        "CHECK",
+        r"(?s)^Inside we both know (.+)$",
+        "Inside we both know EXPR",
+        "Starts an if statement or while loop with a BOOL condition. The statement that ends the block decides which one it is.",
+        "Inside we both know a < 10",
+    ),
+    (
+        "FOREACH",
+        r"(?s)^We've known each (\w+)(?:, (\w+))? in (.+) for so long$",
+        "We've known each VAR in EXPR for so long",
+        "Starts a loop running its block once for each element of an array, which is stored in the variable. \
+            A second variable, as in We've known each VAR, INDEX in EXPR for so long, holds the element's index. \
+            The loop ends with We know the game and we're gonna play it.",
+        "We've known each name in names for so long",
+    ),
+    (
+        "WHILE_END",
+        r"^We know the game and we're gonna play it$",
+        "We know the game and we're gonna play it",
+        "Ends a while loop, running the block again while its condition is TRUE.",
+        "We know the game and we're gonna play it",
+    ),
+    (
+        "IF_END",
+        r"^Your heart's been aching but you're too shy to say it$",
+        "Your heart's been aching but you're too shy to say it",
+        "Ends an if statement, whose block runs once if its condition is TRUE.",
+        "Your heart's been aching but you're too shy to say it",
+    ),
+    (
+        "TRY",
+        r"^Never gonna make you cry$",
+        "Never gonna make you cry",
+        "Starts a block whose errors are handled instead of stopping the program.",
+        "Never gonna make you cry",
+    ),
+    (
+        "CATCH",
+        r"^Never gonna tell a (\w+) and hurt (\w+)$",
+        "Never gonna tell a lie and hurt VAR",
+        "Starts the handler of an error handling block. The error message is stored in the variable. \
+            A lie handles every error, and the name of an error type, such as TypeError, handles only that type.",
+        "Never gonna tell a lie and hurt err",
+    ),
+    (
+        "TRY_END",
+        r"^Gotta make you understand$",
+        "Gotta make you understand",
+        "Ends an error handling block.",
+        "Gotta make you understand",
+    ),
+    (
+        "CAPTURE",
+        r"^I just wanna tell (\w+) how I'm feeling$",
+        "I just wanna tell VAR how I'm feeling",
+        "Starts a block whose output is stored in the variable as a string instead of being printed.",
+        "I just wanna tell out how I'm feeling",
+    ),
+    (
+        "CAPTURE_END",
+        r"^Don't tell me you're too blind to see$",
+        "Don't tell me you're too blind to see",
+        "Ends an output capture block.",
+        "Don't tell me you're too blind to see",
+    ),
+    (
+        "CHORUS",
+        r"^\[Chorus\]$",
+        "[Chorus]",
+        "Starts the main block of the program, which runs after the intro.",
+        "[Chorus]",
+    ),
+    (
+        "INTRO",
+        r"^\[Intro\]$",
+        "[Intro]",
+        "Starts the block that runs before the chorus. Its variables are global.",
+        "[Intro]",
+    ),
+    (
+        "VERSE",
+        r"^\[Verse (\w+)\]$",
+        "[Verse NAME]",
+        "Starts a function. The next line lists its parameters.",
+        "[Verse add]",
+    ),
+    (
+        "RUN_ASSIGN",
+        r"^\(Ooh give you (\w+)\) Never gonna run (\w+) and desert (.+)$",
+        "(Ooh give you VAR) Never gonna run FUNC and desert ARGS",
+        "Calls a function and stores what it returns in the variable. The arguments must be variables, or you for none.",
+        "(Ooh give you sum) Never gonna run add and desert a, b",
+    ),
+    (
+        "ARGS",
+        r"^\(Ooh give you (.+)\)$",
+        "(Ooh give you PARAMS)",
+        "Lists the parameters of a function, or up for none. Parameters at the end can have constant default values.",
+        "(Ooh give you a, b = 1)",
+    ),
+    (
+        "RUN",
+        r"^Never gonna run (\w+) and desert (.+)$",
+        "Never gonna run FUNC and desert ARGS",
+        "Calls a function. The arguments must be variables, or you for none.",
+        "Never gonna run add and desert a, b",
+    ),
+    (
+        "RETURN",
+        r"(?s)^\(Ooh\) Never gonna give, never gonna give \(give you (.+)\)$",
+        "(Ooh) Never gonna give, never gonna give (give you EXPR)",
+        "Returns the value of an expression from a function.",
+        "(Ooh) Never gonna give, never gonna give (give you a + b)",
+    ),
+    (
+        "SLEEP",
+        r"(?s)^We've been waiting for (.+)$",
+        "We've been waiting for EXPR",
+        "Pauses for a number of milliseconds. Not available in sandbox mode.",
+        "We've been waiting for 500",
+    ),
+    (
+        "RAISE",
+        r"(?s)^You wouldn't get this from any other (\w+) (.+)$",
+        "You wouldn't get this from any other guy EXPR",
+        "Raises a UserError with the value of an expression as its message. An error type in place of guy raises that type.",
+        "You wouldn't get this from any other guy \"Invalid input\"",
+    ),
+    (
+        "EXPLAIN",
+        r"(?s)^And if you ask me how (.+) is feeling$",
+        "And if you ask me how EXPR is feeling",
+        "Prints the value of an expression followed by its type in parentheses.",
+        "And if you ask me how 7 / 2 is feeling",
+    ),
+];
+
+// name, syntax, description
+const BUILTINS: [(&str, &str, &str); 48] = [
+    ("ArrayOf", "ArrayOf [elements...]", "Returns an array of the arguments."),
+    ("ArrayPop", "ArrayPop [array] [index]", "Returns the array without the element at the index."),
+    ("ArrayPush", "ArrayPush [array] [index] [value]", "Returns the array with the value inserted at the index."),
+    ("ArrayReplace", "ArrayReplace [array] [index] [value]", "Returns the array with the element at the index replaced."),
+    ("ArrayLength", "ArrayLength [array]", "Returns the length of an array."),
+    ("Length", "Length [container]", "Returns the length of an array or string."),
+    ("ArrayRange", "ArrayRange [start] [end] [step]", "Returns the numbers from start up to but not including end."),
+    ("ArrayRangeInclusive", "ArrayRangeInclusive [start] [end] [step]", "Returns the numbers from start up to and including end."),
+    ("ArraySlice", "ArraySlice [array] [start] [end]", "Returns the elements from start up to but not including end."),
+    ("ArraySort", "ArraySort [array]", "Returns the array sorted in ascending order."),
+    (
+        "ArraySortBy",
+        "ArraySortBy [array] [index]",
+        "Returns the array of arrays sorted in ascending order of their elements at the index.",
+    ),
+    ("ArrayUnique", "ArrayUnique [array]", "Returns the array without repeated elements."),
+    ("ArrayReverse", "ArrayReverse [array]", "Returns the array in reverse order."),
+    ("ArrayRepeat", "ArrayRepeat [array] [count]", "Returns the elements of the array repeated count times."),
+    ("Min", "Min [value] [value] ...", "Returns the smallest of the values, or of the elements of one array."),
+    ("Max", "Max [value] [value] ...", "Returns the largest of the values, or of the elements of one array."),
+    ("Between", "Between [value] [low] [high]", "Returns whether the value is at least low and at most high."),
+    ("Abs", "Abs [number]", "Returns the absolute value of a number."),
+    ("Floor", "Floor [number]", "Rounds a number down to an INT."),
+    ("Ceil", "Ceil [number]", "Rounds a number up to an INT."),
+    ("Round", "Round [number]", "Rounds a number to the nearest INT."),
+    ("Sqrt", "Sqrt [number]", "Returns the square root of a number as a FLOAT."),
+    ("Pow", "Pow [base] [exponent]", "Raises a number to a power."),
+    ("FormatNumber", "FormatNumber [number] [sign] [group] [separator]", "Returns a number as a string, with a + sign and grouped digits if asked."),
+    ("TypeOf", "TypeOf [value]", "Returns the name of the type of a value."),
+    ("IsInt", "IsInt [value]", "Checks if a value is an INT."),
+    ("IsFloat", "IsFloat [value]", "Checks if a value is a FLOAT."),
+    ("IsBool", "IsBool [value]", "Checks if a value is a BOOL."),
+    ("IsArray", "IsArray [value]", "Checks if a value is an ARRAY."),
+    ("IsChar", "IsChar [value]", "Checks if a value is a CHAR."),
+    ("IsUndefined", "IsUndefined [value]", "Checks if a value is UNDEFINED."),
+    ("PutChar", "PutChar [char]", "Writes a character without a newline."),
+    ("ReadLine", "ReadLine", "Reads a line of input as a string."),
+    ("ReadToken", "ReadToken", "Reads the next word of input, as a number if it is written as one."),
+    ("ReadKey", "ReadKey", "Reads one character of input."),
+    ("ParseCsv", "ParseCsv [string] [separator]", "Splits delimited text into an array of rows, each an array of fields."),
+    ("GetEnv", "GetEnv [array]", "Returns the value of an environment variable."),
+    ("AssertEqual", "AssertEqual [value] [value]", "Throws an error showing where two values differ unless they are equal."),
+    ("Now", "Now", "Returns the number of seconds since the start of 1970."),
+    ("Elapsed", "Elapsed", "Returns the number of seconds since the program started."),
+    ("Random", "Random [low] [high]", "Returns a random INT from low to high, including both."),
+    ("Shuffle", "Shuffle [array]", "Returns the elements of the array in a random order."),
+    ("Sample", "Sample [array] [count]", "Returns count different elements of the array chosen at random."),
+    ("Push", "Push [array] [value]", "Appends a value to the array variable."),
+    ("Pop", "Pop [array]", "Removes and returns the last element of the array variable."),
+    ("Shift", "Shift [array]", "Removes and returns the first element of the array variable."),
+    ("Unshift", "Unshift [array] [value]", "Inserts a value at the front of the array variable."),
+    ("Increment", "Increment [array] [index] [amount]", "Adds 1, or the amount, to an element of the array variable."),
+];
+
+// functions returning UNDEFINED instead of failing on an empty array
+const TRY_FUNCTIONS: [(&str, &str); 2] = [("TryPop", "Pop"), ("TryShift", "Shift")];
+
+const OPERATORS: [Operator; 16] = [
+    Operator::ArrayAccess,
+    Operator::Add,
+    Operator::Subtract,
+    Operator::Multiply,
+    Operator::Divide,
+    Operator::Modulo,
+    Operator::UnaryMinus,
+    Operator::And,
+    Operator::Or,
+    Operator::Not,
+    Operator::Greater,
+    Operator::Less,
+    Operator::GreaterEquals,
+    Operator::LessEquals,
+    Operator::Equals,
+    Operator::NotEquals,
+];
+
+const OPERATOR_CHARS: &str = ":+-*/%~&|!<>=";
+
+lazy_static! {
+    static ref STATEMENT_PATTERNS: Vec<Regex> = STATEMENTS
+        .iter()
+        .map(|entry| Regex::new(entry.1).unwrap())
+        .collect();
+}
+
+// matches a trimmed line against every statement
+// returns the statement's keyword and the parts written by the programmer
+pub(crate) fn match_statement(curln: &str) -> Option<(&'static str, Captures<'_>)> {
+    for (ind, pattern) in STATEMENT_PATTERNS.iter().enumerate() {
+        if let Some(captures) = pattern.captures(curln) {
+            return Some((STATEMENTS[ind].0, captures));
+        }
+    }
+    return None;
+}
+
+// gets the example of every statement
+pub(crate) fn statement_examples() -> impl Iterator<Item = &'static str> {
+    STATEMENTS.iter().map(|entry| entry.4)
+}
+
+// gets the documentation of a statement by its keyword, such as SAY
+pub fn statement(keyword: &str) -> Option<DocEntry> {
+    let (name, _, syntax, description, example) = STATEMENTS.iter().find(|entry| entry.0 == keyword)?;
+    return Some(DocEntry::new(name, syntax, description, example));
+}
+
+// gets the documentation of a built-in function
+pub fn builtin(name: &str) -> Option<DocEntry> {
+    if let Some((_, base)) = TRY_FUNCTIONS.iter().find(|entry| entry.0 == name) {
+        let mut entry = builtin(base)?;
+        entry.syntax = entry.syntax.replacen(base, name, 1);
+        entry.description += " Returns UNDEFINED if the array is empty.";
+        entry.name = String::from(name);
+        entry.example = builtin_example(name);
+        return Some(entry);
+    }
+    let (name, syntax, description) = BUILTINS.iter().find(|entry| entry.0 == name)?;
+    return Some(DocEntry::new(name, syntax, description, &builtin_example(name)));
+}
+
+fn builtin_example(name: &str) -> String {
+    let args = builtin_arg_count(name);
+    if args == 0 {
+        return format!("(Ooh give you x) Never gonna run {} and desert you", name);
+    }
+    let names: Vec<&str> = ["a", "b", "c"].iter().take(args).copied().collect();
+    return format!("(Ooh give you x) Never gonna run {} and desert {}", name, names.join(", "));
+}
+
+// counts the arguments in the syntax of a built-in function, at most 3
+fn builtin_arg_count(name: &str) -> usize {
+    let name = TRY_FUNCTIONS
+        .iter()
+        .find(|entry| entry.0 == name)
+        .map_or(name, |entry| entry.1);
+    match BUILTINS.iter().find(|entry| entry.0 == name) {
+        Some((_, syntax, _)) => syntax.matches('[').count().min(3),
+        None => 0,
+    }
+}
+
+// gets the documentation of an operator
+pub fn operator(op: Operator) -> DocEntry {
+    use Operator::*;
+    let (syntax, description, example) = match op {
+        ArrayAccess => ("ARRAY : INDEX", "Gets the element of an array at an index, starting from 0.", "a : 0"),
+        Add => ("A + B", "Adds two numbers.", "1 + 2"),
+        Subtract => ("A - B", "Subtracts a number from another.", "5 - 3"),
+        Multiply => ("A * B", "Multiplies two numbers.", "2 * 3"),
+        Divide => ("A / B", "Divides a number by another. Dividing two INTs rounds towards zero.", "7 / 2"),
+        Modulo => ("A % B", "Gets the remainder of dividing a number by another.", "7 % 2"),
+        UnaryMinus => ("~A", "Negates a number.", "~5"),
+        And => ("A && B", "Checks if both BOOLs are TRUE.", "a > 0 && a < 10"),
+        Or => ("A || B", "Checks if either BOOL is TRUE.", "a < 0 || a > 10"),
+        Not => ("!A", "Negates a BOOL.", "!done"),
+        Greater => ("A > B", "Checks if a value is greater than another.", "a > 1"),
+        Less => ("A < B", "Checks if a value is less than another.", "a < 1"),
+        GreaterEquals => ("A >= B", "Checks if a value is greater than or equal to another.", "a >= 1"),
+        LessEquals => ("A <= B", "Checks if a value is less than or equal to another.", "a <= 1"),
+        Equals => ("A == B", "Checks if two values are equal.", "a == 1"),
+        NotEquals => ("A != B", "Checks if two values are not equal.", "a != 1"),
+    };
+    return DocEntry::new(op.symbol(), syntax, description, example);
+}
+
+// splits a run of operator characters into operators, longest first, with their byte offsets
+fn split_operators(run: &str) -> Vec<(usize, Operator)> {
+    let mut res = Vec::new();
+    let mut ind = 0;
+    while ind < run.len() {
+        let two = OPERATORS.iter().find(|op| op.symbol().len() == 2 && run[ind..].starts_with(op.symbol()));
+        let one = OPERATORS.iter().find(|op| op.symbol().len() == 1 && run[ind..].starts_with(op.symbol()));
+        match two.or(one) {
+            Some(op) => {
+                res.push((ind, *op));
+                ind += op.symbol().len();
+            }
+            // a lone & or | isn't an operator
+            None => ind += 1,
+        }
+    }
+    return res;
+}
+
+// checks if a byte offset is inside a string or character literal
+pub(crate) fn in_literal(text: &str, pos: usize) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for (ind, chr) in text.char_indices() {
+        if ind >= pos {
+            break;
+        }
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if chr == '\\' => escaped = true,
+            Some(open) if chr == open => quote = None,
+            Some(_) => (),
+            None if chr == '"' || chr == '\'' => quote = Some(chr),
+            None => (),
+        }
+    }
+    return quote.is_some();
+}
+
+pub(crate) fn is_word_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '_'
+}
+
+// finds what is under a byte offset in the part of a statement written by the programmer
+fn lookup_in(text: &str, pos: usize, source: &str, line: usize) -> Option<DocEntry> {
+    if in_literal(text, pos) {
+        return None;
+    }
+    let chr = text[pos..].chars().next()?;
+    if is_word_char(chr) {
+        let start = text[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_word_char(*c))
+            .last()
+            .map_or(pos, |(ind, _)| ind);
+        let end = text[pos..]
+            .char_indices()
+            .find(|(_, c)| !is_word_char(*c))
+            .map_or(text.len(), |(ind, _)| pos + ind);
+        let word = &text[start..end];
+        // numbers and constants have nothing to document
+        if word.starts_with(|c: char| c.is_ascii_digit()) || from_constant(&String::from(word)).is_some() {
+            return None;
+        }
+        return lookup_name(word, source, line);
+    }
+    if OPERATOR_CHARS.contains(chr) {
+        let start = text[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| OPERATOR_CHARS.contains(*c))
+            .last()
+            .map_or(pos, |(ind, _)| ind);
+        let end = text[pos..]
+            .char_indices()
+            .find(|(_, c)| !OPERATOR_CHARS.contains(*c))
+            .map_or(text.len(), |(ind, _)| pos + ind);
+        for (ind, op) in split_operators(&text[start..end]) {
+            if start + ind <= pos && pos < start + ind + op.symbol().len() {
+                return Some(operator(op));
+            }
+        }
+    }
+    return None;
+}
+
+// documents a variable or function from where it is declared
+// user-defined functions shadow built-in ones, so the program is checked first
+fn lookup_name(name: &str, source: &str, line: usize) -> Option<DocEntry> {
+    let tokens = Lexer::new(String::from(source)).parse().ok();
+    let functions = tokens.clone().and_then(|tokens| Parser::new(tokens).parse().ok());
+    let (tokens, functions) = match (tokens, functions) {
+        (Some(tokens), Some(functions)) => (tokens, functions),
+        // only built-in functions can be documented without a compiling program
+        _ => return builtin(name),
+    };
+    if let Some(ASTNode::Function(ln, _, args, _, _)) = functions.get(name) {
+        let (params, passed, description) = if args.is_empty() {
+            (String::from("up"), String::from("you"), format!("Function {} declared on line {}.", name, ln))
+        } else {
+            let params = args.join(", ");
+            let description = format!("Function {} declared on line {}, with parameters {}.", name, ln, params);
+            (params.clone(), params, description)
+        };
+        return Some(DocEntry::new(
+            name,
+            &format!("Never gonna run {} and desert {}", name, passed),
+            &description,
+            &format!("[Verse {}]\n(Ooh give you {})", name, params),
+        ));
+    }
+    if let Some(entry) = builtin(name) {
+        return Some(entry);
+    }
+    // the closest declaration at or before the line
+    let mut declared = None;
+    for pair in tokens.windows(2) {
+        if let [Token::Statement(ln, kw), Token::Name(_, var)] = pair {
+            if kw == "LET" && var == name && *ln <= line {
+                declared = Some(format!("Variable {} declared on line {}.", name, ln));
+            }
+        }
+    }
+    for node in functions.values() {
+        if let ASTNode::Function(ln, func, args, _, _) = node {
+            if args.iter().any(|arg| arg == name) && *ln <= line && declared.is_none() {
+                declared = Some(format!("Parameter {} of function {} declared on line {}.", name, func, ln));
+            }
+        }
+    }
+    return Some(DocEntry::new(
+        name,
+        "VAR",
+        &declared?,
+        &format!("Never gonna let {} down", name),
+    ));
+}
+
+// finds the documentation of whatever is at a line and column of a program
+// lines and columns start at 1, and columns count characters
+pub fn lookup_at(source: &str, line: usize, col: usize) -> Option<DocEntry> {
+    let raw = source.split('\n').nth(line.checked_sub(1)?)?;
+    let pos = raw.char_indices().nth(col.checked_sub(1)?)?.0;
+    // the lexer ignores spaces around statements
+    let start = raw.len() - raw.trim_start().len();
+    let curln = raw.trim();
+    if pos < start || pos >= start + curln.len() {
+        return None;
+    }
+    let pos = pos - start;
+    let (keyword, captures) = match_statement(curln)?;
+    for group in captures.iter().skip(1).flatten() {
+        if group.start() <= pos && pos < group.end() {
+            return lookup_in(group.as_str(), pos - group.start(), source, line);
+        }
+    }
+    return statement(keyword);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::RANDOM_FUNCTIONS;
+    use crate::stdlib::{BUILTIN_FUNCTIONS, CLOCK_FUNCTIONS, IN_PLACE_FUNCTIONS};
+
+    #[test]
+    fn every_statement_documented() {
+        for (keyword, _, syntax, _, example) in STATEMENTS {
+            // an example matched by an earlier pattern would be documented as another statement
+            let (matched, _) = match_statement(example).unwrap();
+            assert_eq!(matched, keyword, "{}", example);
+            let entry = statement(keyword).unwrap();
+            assert_eq!(entry.syntax, syntax);
+            assert!(!entry.description.is_empty(), "{}", keyword);
+        }
+    }
+
+    #[test]
+    fn every_builtin_documented() {
+        let names = BUILTIN_FUNCTIONS
+            .keys()
+            .chain(IN_PLACE_FUNCTIONS.keys())
+            .map(|name| name.as_str())
+            .chain(CLOCK_FUNCTIONS)
+            .chain(RANDOM_FUNCTIONS);
+        for name in names {
+            let entry = builtin(name).unwrap_or_else(|| panic!("{} is not documented", name));
+            assert_eq!(entry.name, name);
+            assert!(entry.syntax.starts_with(name), "{}", entry.syntax);
+            assert!(entry.example.contains(&format!("Never gonna run {} and desert", name)), "{}", entry.example);
+        }
+    }
+
+    #[test]
+    fn every_operator_documented() {
+        for op in OPERATORS {
+            assert_eq!(operator(op).name, op.symbol());
+            assert_eq!(split_operators(op.symbol()), vec![(0, op)]);
+        }
+    }
+
+    const SAMPLE: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a a * 2 >= 10 || a < 0
+(Ooh give you a) Never gonna run ReadToken and desert you
+Never gonna say \"a + b\"
+Never gonna say ~a
+";
+
+    fn name_at(line: usize, col: usize) -> Option<String> {
+        return lookup_at(SAMPLE, line, col).map(|entry| entry.name);
+    }
+
+    #[test]
+    fn lookup_positions() {
+        // the statement itself
+        assert_eq!(lookup_at(SAMPLE, 3, 1), statement("ASSIGN"));
+        assert_eq!(lookup_at(SAMPLE, 3, 16), statement("ASSIGN"));
+        // the variable being assigned and the one in the expression
+        for col in [18, 20] {
+            let entry = lookup_at(SAMPLE, 3, col).unwrap();
+            assert_eq!(entry.name, "a");
+            assert_eq!(entry.description, "Variable a declared on line 2.");
+        }
+        assert_eq!(lookup_at(SAMPLE, 3, 22), Some(operator(Operator::Multiply)));
+        // both characters of a two-character operator
+        assert_eq!(lookup_at(SAMPLE, 3, 26), Some(operator(Operator::GreaterEquals)));
+        assert_eq!(lookup_at(SAMPLE, 3, 27), Some(operator(Operator::GreaterEquals)));
+        assert_eq!(lookup_at(SAMPLE, 3, 32), Some(operator(Operator::Or)));
+        assert_eq!(lookup_at(SAMPLE, 3, 37), Some(operator(Operator::Less)));
+        // numbers, spaces and the end of the line
+        assert_eq!(name_at(3, 24), None);
+        assert_eq!(name_at(3, 29), None);
+        assert_eq!(name_at(3, 21), None);
+        assert_eq!(name_at(3, 40), None);
+        assert_eq!(lookup_at(SAMPLE, 4, 1), statement("RUN_ASSIGN"));
+        assert_eq!(lookup_at(SAMPLE, 4, 34), builtin("ReadToken"));
+        assert_eq!(lookup_at(SAMPLE, 4, 42), builtin("ReadToken"));
+        // nothing inside a string literal
+        assert_eq!(name_at(5, 19), None);
+        assert_eq!(name_at(5, 20), None);
+        assert_eq!(lookup_at(SAMPLE, 6, 17), Some(operator(Operator::UnaryMinus)));
+        // lines and columns start at 1
+        assert_eq!(name_at(0, 1), None);
+        assert_eq!(name_at(3, 0), None);
+        assert_eq!(name_at(10, 1), None);
+    }
+
+    #[test]
+    fn lookup_without_compiling_program() {
+        // the variable is never declared, so the program doesn't compile
+        let src = "[Chorus]\n(Ooh give you b) Never gonna run ReadToken and desert you\n";
+        assert_eq!(lookup_at(src, 2, 15), None);
+        assert_eq!(lookup_at(src, 2, 34), builtin("ReadToken"));
+    }
+}
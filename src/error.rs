@@ -1,7 +1,11 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::io;
+use strum_macros::EnumIter;
 
 // all native error types
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
 pub enum ErrorType {
     IllegalCharError,
     RuntimeError,
@@ -13,65 +17,251 @@ pub enum ErrorType {
     NameError,
     Traceback,
     StackOverflowError,
+    BrokenPipeError,
+    TypeError,
+    ResourceError,
+    PermissionError,
+    ValueError,
+    AssertionError,
+    // raised by the program itself
+    UserError,
 }
 
+// error types that can be named in a program, by the name of their enum member
+// a closed output stream is never handled, so it isn't named
+const NAMED_TYPES: [ErrorType; 15] = [
+    ErrorType::IllegalCharError,
+    ErrorType::RuntimeError,
+    ErrorType::IllegalArgumentError,
+    ErrorType::SyntaxError,
+    ErrorType::IllegalCastError,
+    ErrorType::IndexOutOfBoundsError,
+    ErrorType::FileError,
+    ErrorType::NameError,
+    ErrorType::StackOverflowError,
+    ErrorType::TypeError,
+    ErrorType::ResourceError,
+    ErrorType::PermissionError,
+    ErrorType::ValueError,
+    ErrorType::AssertionError,
+    ErrorType::UserError,
+];
+
 // get name from enum member
 impl ErrorType {
-    fn as_string(&self) -> String {
+    // gets the name used for the error type in programs
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    // finds the error type with the name used in programs
+    // tracebacks aren't an error of their own, so they have no name
+    pub fn from_name(name: &str) -> Option<ErrorType> {
+        NAMED_TYPES.iter().find(|err| err.name() == name).copied()
+    }
+
+    // gets the id of the message naming the error type
+    fn message_id(&self) -> MessageId {
         use ErrorType::*;
         match self {
-            IllegalCharError => "Illegal Character",
-            RuntimeError => "Runtime Error",
-            IllegalArgumentError => "Illegal Argument",
-            SyntaxError => "Syntax Error",
-            IllegalCastError => "Illegal Cast",
-            IndexOutOfBoundsError => "Index Out of Bounds",
-            FileError => "File Error",
-            NameError => "Name Error",
-            Traceback => "Traceback",
-            StackOverflowError => "Stack Overflow",
+            IllegalCharError => "illegal-char-error",
+            RuntimeError => "runtime-error",
+            IllegalArgumentError => "illegal-argument-error",
+            SyntaxError => "syntax-error",
+            IllegalCastError => "illegal-cast-error",
+            IndexOutOfBoundsError => "index-out-of-bounds-error",
+            FileError => "file-error",
+            NameError => "name-error",
+            Traceback => "traceback",
+            StackOverflowError => "stack-overflow-error",
+            BrokenPipeError => "broken-pipe-error",
+            TypeError => "type-error",
+            ResourceError => "resource-error",
+            PermissionError => "permission-error",
+            ValueError => "value-error",
+            AssertionError => "assertion-error",
+            UserError => "user-error",
         }
-        .to_string()
+    }
+
+    fn as_string(&self) -> String {
+        render_message(self.message_id(), &[])
+    }
+
+    // describes what usually causes the error and how to fix it
+    pub fn explanation(&self) -> String {
+        use ErrorType::*;
+        let id = match self {
+            IllegalCharError => "illegal-char-error-explanation",
+            RuntimeError => "runtime-error-explanation",
+            IllegalArgumentError => "illegal-argument-error-explanation",
+            SyntaxError => "syntax-error-explanation",
+            IllegalCastError => "illegal-cast-error-explanation",
+            IndexOutOfBoundsError => "index-out-of-bounds-error-explanation",
+            FileError => "file-error-explanation",
+            NameError => "name-error-explanation",
+            Traceback => "traceback-explanation",
+            StackOverflowError => "stack-overflow-error-explanation",
+            BrokenPipeError => "broken-pipe-error-explanation",
+            TypeError => "type-error-explanation",
+            ResourceError => "resource-error-explanation",
+            PermissionError => "permission-error-explanation",
+            ValueError => "value-error-explanation",
+            AssertionError => "assertion-error-explanation",
+            UserError => "user-error-explanation",
+        };
+        render_message(id, &[])
     }
 }
 
+// explanations of specific errors, chosen by the message of the error
+// these are shown instead of the explanation of the error type
+const MESSAGE_EXPLANATIONS: [(MessageId, MessageId); 4] = [
+    ("illegal-statement", "illegal-statement-explanation"),
+    ("illegal-statement-confusable", "illegal-statement-explanation"),
+    ("unclosed-block", "unclosed-block-explanation"),
+    ("unterminated-multi-line-string", "unterminated-multi-line-string-explanation"),
+];
+
+// what an error says, as text or as a message in the catalog with its parameters by name
+// the message is boxed so errors stay small
+#[derive(Debug)]
+enum Description {
+    Text(String),
+    Message(Box<(MessageId, Vec<(String, String)>)>),
+}
+
 #[derive(Debug)]
 pub struct Error {
     err: ErrorType,
-    desc: String,
+    desc: Description,
     // line could not exist
     line: Option<usize>,
     // child could not exist
     child: Box<Option<Error>>,
+    // statements executed right before the error, oldest first
+    history: Vec<String>,
 }
 
 impl Error {
     // make a new error
     pub fn new(err: ErrorType, desc: &str, line: Option<usize>) -> Error {
         // error can't be traceback
-        assert_ne!(
-            err.as_string(),
-            String::from("Traceback"),
-            "Error::new() can't make tracebacks"
-        );
+        assert_ne!(err, ErrorType::Traceback, "Error::new() can't make tracebacks");
         Error {
             err,
-            desc: String::from(desc),
+            desc: Description::Text(String::from(desc)),
             line,
             child: Box::new(None),
+            history: Vec::new(),
         }
     }
 
+    // make a new error from a message in the catalog
+    // the description is rendered when the error is shown, in the catalog set at that time
+    pub fn from_message(
+        err: ErrorType,
+        id: MessageId,
+        params: &[(&str, &dyn Display)],
+        line: Option<usize>,
+    ) -> Error {
+        let params = params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut res = Error::new(err, "", line);
+        res.desc = Description::Message(Box::new((id, params)));
+        res
+    }
+
     pub fn traceback(child: Error, line: Option<usize>) -> Error {
         Error {
             err: ErrorType::Traceback,
-            desc: String::from(""),
+            desc: Description::Text(String::from("")),
             line,
             child: Box::new(Some(child)),
+            history: Vec::new(),
         }
     }
 }
 
+impl Error {
+    // gets the error at the bottom of a traceback
+    pub fn root(&self) -> &Error {
+        match self.child.as_ref() {
+            Some(child) => child.root(),
+            None => self,
+        }
+    }
+
+    // gets the type of the original error
+    pub fn kind(&self) -> ErrorType {
+        self.root().err
+    }
+
+    // converts a failed write to the output into an error
+    // a closed output stream is reported separately so the program can stop quietly
+    pub fn from_write(err: io::Error) -> Error {
+        if err.kind() == io::ErrorKind::BrokenPipe {
+            return Error::from_message(ErrorType::BrokenPipeError, "output-closed", &[], None);
+        }
+        Error::from_message(ErrorType::RuntimeError, "write-failed", &[("reason", &err)], None)
+    }
+
+    // attaches the recent execution history to the error
+    pub fn with_history(mut self, history: Vec<String>) -> Error {
+        self.history = history;
+        self
+    }
+
+    // gets the recent execution history, which is empty if it wasn't recorded
+    pub fn history(&self) -> &Vec<String> {
+        &self.history
+    }
+
+    // describes what usually causes the original error and how to fix it
+    pub fn explanation(&self) -> String {
+        let root = self.root();
+        for (id, explanation) in MESSAGE_EXPLANATIONS.iter() {
+            if root.message_id() == Some(*id) {
+                return render_message(explanation, &[]);
+            }
+        }
+        root.err.explanation()
+    }
+
+    // gets the id of the message the error was made from
+    // tools can use it with params() to translate errors themselves
+    pub fn message_id(&self) -> Option<MessageId> {
+        match &self.desc {
+            Description::Message(message) => Some(message.0),
+            Description::Text(_) => None,
+        }
+    }
+
+    // gets the parameters filled into the message, by name
+    pub fn params(&self) -> &[(String, String)] {
+        match &self.desc {
+            Description::Message(message) => &message.1,
+            Description::Text(_) => &[],
+        }
+    }
+
+    // gets the description in the current catalog
+    pub fn description(&self) -> String {
+        match &self.desc {
+            Description::Message(message) => fill_message(&template(message.0), &message.1),
+            Description::Text(text) => text.clone(),
+        }
+    }
+
+    // gets the name and description of the original error
+    pub fn message(&self) -> String {
+        let root = self.root();
+        format!("{}: {}", root.err.as_string(), root.description())
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut res = String::new();
@@ -85,12 +275,578 @@ impl Display for Error {
         res += &self.err.as_string()[..];
         // error line if exists
         if self.line.is_some() {
-            res = format!("{} on line {}", res, self.line.unwrap());
+            res = render_message("on-line", &[("error", &res), ("line", &self.line.unwrap())]);
         }
         // error description if not traceback
         if self.child.is_none() {
-            res = format!("{}: {}", res, self.desc);
+            res = format!("{}: {}", res, self.description());
+        }
+        if !self.history.is_empty() {
+            res += &format!("\n{}", render_message("execution-history", &[]));
+            for entry in &self.history {
+                res += &format!("\n  {}", entry);
+            }
         }
         write!(f, "{}", res)
     }
 }
+
+// stable names of user-facing messages, so they can be translated
+pub type MessageId = &'static str;
+
+// english text of every message, written like a format string with named parameters
+// {name} is replaced by the parameter called name, and {{ and }} stand for { and }
+const MESSAGES: &[(MessageId, &str)] = &[
+    // names of error types
+    ("illegal-char-error", "Illegal Character"),
+    ("runtime-error", "Runtime Error"),
+    ("illegal-argument-error", "Illegal Argument"),
+    ("syntax-error", "Syntax Error"),
+    ("illegal-cast-error", "Illegal Cast"),
+    ("index-out-of-bounds-error", "Index Out of Bounds"),
+    ("file-error", "File Error"),
+    ("name-error", "Name Error"),
+    ("traceback", "Traceback"),
+    ("stack-overflow-error", "Stack Overflow"),
+    ("broken-pipe-error", "Broken Pipe"),
+    ("type-error", "Type Error"),
+    ("resource-error", "Resource Limit"),
+    ("permission-error", "Permission Denied"),
+    ("value-error", "Value Error"),
+    ("assertion-error", "Assertion Failed"),
+    ("user-error", "User Error"),
+    // explanations of error types and of specific errors
+    (
+        "illegal-char-error-explanation",
+        "A character that rickroll doesn't understand was found. \
+            Variable names can only contain letters, digits and underscores, \
+            and string and character literals must be closed with a matching quote.",
+    ),
+    (
+        "runtime-error-explanation",
+        "Something went wrong while running the program, \
+            most often a built-in function called with the wrong number of arguments, \
+            or a value that isn't a BOOL used as the condition of an if statement or while loop. \
+            Check the function's documentation for its arguments. \
+            Conditions must be BOOLs, for example: Inside we both know a == 1",
+    ),
+    (
+        "illegal-argument-error-explanation",
+        "A function was called with arguments it doesn't accept. \
+            Arguments to functions must be variables, for example: \
+            Never gonna run f and desert a, b",
+    ),
+    (
+        "syntax-error-explanation",
+        "A line doesn't match the form of any statement. \
+            Every statement has a fixed form, for example: \
+            Never gonna say 1 + 2",
+    ),
+    (
+        "illegal-cast-error-explanation",
+        "A value couldn't be converted to another type. \
+            Rickroll itself doesn't raise this error, so it comes from a program raising it, \
+            for example: You wouldn't get this from any other IllegalCastError \"not a number\"",
+    ),
+    (
+        "index-out-of-bounds-error-explanation",
+        "An array was indexed past its end. \
+            Indices start at 0, so the last element of an array of length n is at index n - 1.",
+    ),
+    (
+        "file-error-explanation",
+        "The program file couldn't be read. \
+            Check that the path is correct and that the file is readable.",
+    ),
+    (
+        "name-error-explanation",
+        "A variable or function was used without being declared, or declared twice. \
+            Variables are declared in the block they are used in, for example: \
+            Never gonna let a down",
+    ),
+    (
+        "traceback-explanation",
+        "The error happened inside a function call. \
+            The lines of the calls that led to the error are listed below the original error.",
+    ),
+    (
+        "stack-overflow-error-explanation",
+        "Functions called each other too deeply, usually because \
+            a recursive function never reaches the case that stops the recursion.",
+    ),
+    (
+        "broken-pipe-error-explanation",
+        "The program's output was closed before it finished writing, \
+            for example when it is piped into a command that exits early.",
+    ),
+    (
+        "type-error-explanation",
+        "An operator or function was used with values of the wrong types, \
+            such as adding an ARRAY and an INT. TypeOf can be used to check a value's type.",
+    ),
+    (
+        "resource-error-explanation",
+        "The program went over a limit set when rickroll was run, \
+            such as the longest allowed line, the number of statements, \
+            or the work a single statement can do.",
+    ),
+    (
+        "permission-error-explanation",
+        "A built-in function that accesses the environment was called \
+            while rickroll was run with --sandbox.",
+    ),
+    (
+        "value-error-explanation",
+        "A value has the right type but can't be used, \
+            such as the maximum of an empty array or a float too large to round to an INT.",
+    ),
+    (
+        "assertion-error-explanation",
+        "An assertion in the program didn't hold. \
+            The two values and where they first differ are shown in the error.",
+    ),
+    (
+        "user-error-explanation",
+        "The program raised this error itself, with You wouldn't get this from any other guy \
+            and a message. The message says what went wrong.",
+    ),
+    (
+        "illegal-statement-explanation",
+        "This line isn't a rickroll statement. Statements are lines of the lyrics, \
+            for example: Never gonna let a down, Never gonna give a 1 or Never gonna say a. \
+            The most common cause is a typo, such as a missing word or wrong capitalization.",
+    ),
+    (
+        "unclosed-block-explanation",
+        "A block was opened but never closed. An if statement ends with \
+            Your heart's been aching but you're too shy to say it, a while loop with \
+            We know the game and we're gonna play it, and error handling with Gotta make you understand.",
+    ),
+    (
+        "unterminated-multi-line-string-explanation",
+        "A string starting with \"\"\" must end with \"\"\" on the same or a later line.",
+    ),
+    // reading programs
+    ("undefined-placeholder", "Placeholder ${{{name}}} is not defined"),
+    ("line-length-limit", "Line is {length} bytes long, more than the limit of {max}"),
+    ("unterminated-multi-line-string", "Unterminated multi-line string"),
+    ("invalid-prefix", "Invalid prefix pattern: {reason}"),
+    (
+        "prefix-removes-statement",
+        "Prefix pattern {pattern} would remove part of the statement {statement}",
+    ),
+    ("illegal-variable-char", "Illegal character \"{char}\" in variable"),
+    ("default-not-constant", "Default value of parameter {name} must be a constant"),
+    ("missing-default", "Parameter {name} must have a default value since it follows one"),
+    ("empty-say", "Say requires an expression"),
+    ("missing-expression", "{statement} requires an expression"),
+    ("illegal-statement", "Illegal statement"),
+    ("mixed-indentation", "Indentation mixes tabs and spaces in the block opened at line {opener}"),
+    ("dedented-line", "Line is indented less than the block opened at line {opener}"),
+    ("missing-argument-list", "No argument specification for function {func}"),
+    ("leading-comma", "Leading comma in list"),
+    ("doubled-comma", "Doubled comma in list"),
+    (
+        "illegal-statement-confusable",
+        "Illegal statement, it contains {char} ({code} {name}), did you mean {suggestion}?",
+    ),
+    ("unknown-char", "Unknown character '{char}'"),
+    ("bad-float-literal", "Improper floating point literal"),
+    ("bad-int-literal", "Improper integer literal"),
+    // expressions
+    ("nested-function", "Functions can't be nested"),
+    ("unexpected-end", "Unexpected end of statement"),
+    ("trailing-char-literal", "Trailing character literal"),
+    ("empty-literal", "Empty literal"),
+    ("long-char-literal", "More than one character in literal"),
+    ("unterminated-string", "Unterminated string literal"),
+    ("unknown-operator", "Operator {operator} not found"),
+    ("array-without-literal", "Array {array} has no literal form"),
+    ("wrong-operand-count", "Operation {operation} has the wrong number of operands"),
+    ("no-such-variable", "No such variable {name}"),
+    ("illegal-expression", "Illegal expression"),
+    ("trailing-characters", "Unexpected trailing characters starting at column {column}"),
+    ("unclosed-parenthesis", "Parenthesis opened at column {column} is never closed"),
+    ("illegal-char", "Illegal character {char} in expression at column {column}"),
+    ("illegal-unicode-char", "Illegal character {char} ({code}) in expression at column {column}"),
+    (
+        "illegal-confusable-char",
+        "Illegal character {char} ({code} {name}) in expression at column {column}, did you mean {suggestion}?",
+    ),
+    // checking programs
+    ("unclosed-block", "Unclosed block opened at line {line}"),
+    ("mismatched-block", "{terminator} doesn't match the block opened at line {line}"),
+    ("duplicate-variable", "Variable name {name} already exists"),
+    ("unknown-error-type", "Error type {name} doesn't exist"),
+    (
+        "function-argument-count",
+        "Function {func} takes {expected} arguments but {given} were given",
+    ),
+    ("statement-count-limit", "Program has more than {max} statements"),
+    ("undeclared-variable", "Variable name {name} doesn't exist"),
+    ("unknown-type", "Type name {name} doesn't exist"),
+    ("unmatched-terminator", "{terminator} without a block to close"),
+    ("unknown-function", "Function name {name} doesn't exist"),
+    // checking functions that weren't parsed
+    ("function-key-mismatch", "Function {name} is stored under the name {key}"),
+    ("default-count-mismatch", "Function {name} has {params} parameters but {defaults} default values"),
+    ("not-a-function", "{name} is stored as a statement instead of a function"),
+    ("nested-function-named", "Function {name} is declared inside another function"),
+    ("raised-traceback", "A traceback can't be raised, only one of the error types"),
+    ("duplicate-function", "Function named {name} already exists"),
+    ("invalid-function-start", "Invalid start of function"),
+    ("statement-outside-function", "Statement not in function"),
+    ("invalid-variable-name", "{name} is not a valid variable name"),
+    ("reserved-word", "{name} is a reserved word"),
+    // running programs
+    ("operator-type-mismatch", "Cannot apply '{operator}' to {operands} in {expression}"),
+    (
+        "statement-work-limit",
+        "{func} would make or go over {work} elements, more than the limit of {max} for a single statement",
+    ),
+    ("array-length-limit", "{func} would make an array of {length} elements, more than the limit of {max}"),
+    ("wrong-argument-count", "Wrong number of arguments for {func}"),
+    ("missing-variable", "Variable {name} doesn't exist"),
+    ("index-out-of-bounds", "Index {index} is out of bounds for an array of length {length}"),
+    ("division-by-zero", "Division by zero"),
+    ("non-finite-result", "Operation {operation} on {left} and {right} produced {result}"),
+    ("illegal-operation", "Illegal operation"),
+    ("expectation-failed", "Expected {name} to be {type} but found {found}"),
+    ("condition-not-boolean", "While condition is not boolean"),
+    ("infinite-loop", "Infinite loop: the while loop ran again without changing any variables"),
+    ("for-each-not-array", "For-each loop needs an ARRAY but found {found}"),
+    ("pause-not-int", "Pause needs an INT number of milliseconds but found {found}"),
+    ("pause-negative", "Pause must not be negative, got {millis} milliseconds"),
+    ("pause-sandboxed", "Pausing is not available in sandbox mode"),
+    ("time-too-large", "Current time is too large for an INT"),
+    ("function-sandboxed", "{func} is not available in sandbox mode"),
+    ("replay-diverged", "Replay diverged at step {step}: the program called {func}, but the recording has {recorded}"),
+    (
+        "replay-exhausted",
+        "Replay ran out at step {step}: the program called {func}, but the recording has only {steps} steps",
+    ),
+    ("bad-recording-line", "Line {line} of the recording is not a function and a value"),
+    ("recursion-limit", "Maximum recursion depth of {max} exceeded in function {func}"),
+    ("too-many-arguments", "Too many arguments for function {func}"),
+    ("missing-argument", "Missing argument {name} for function {func}"),
+    ("no-main", "No main function found"),
+    // built-in functions
+    ("random-empty-range", "Random needs low to be at most high, got {low} and {high}"),
+    ("sample-too-large", "Sample can't take {count} elements from an array of length {length}"),
+    ("argument-type-mismatch", "{func} expects {expected} as argument {position}, got {found}"),
+    ("wrong-argument-types", "Wrong type of arguments for {func}"),
+    ("array-index-out-of-bounds", "Array Index out of Bounds"),
+    ("nonpositive-step", "Step of {func} must be positive"),
+    ("sort-incomparable", "{func} can't compare {left} and {right}"),
+    ("sort-key-not-array", "ArraySortBy needs an array of arrays, but element {position} is {found}"),
+    ("repeat-negative", "ArrayRepeat count must not be negative, got {count}"),
+    ("repeat-too-long", "ArrayRepeat of {length} elements {count} times is too long"),
+    ("empty-array", "{func} of an empty array"),
+    ("incomparable", "{func} can't compare {left} and {right}"),
+    ("between-incomparable", "Between can't compare {left} and {right}"),
+    ("int-out-of-range", "{func} of {value} is out of range for INT"),
+    ("sqrt-negative", "Sqrt of negative number {value}"),
+    ("pow-negative", "Pow of an INT to the negative power {power} is not an INT"),
+    ("negative-group-size", "FormatNumber group size must not be negative, got {size}"),
+    ("csv-bad-separator", "ParseCsv can't use {separator} as the separator"),
+    ("read-failed", "Failed to read input: {reason}"),
+    ("pop-empty", "Pop from empty array"),
+    ("shift-empty", "Shift from empty array"),
+    ("increment-type-mismatch", "Increment can't add {amount} to {current}"),
+    ("csv-after-quoted-field", "Row {row} has {char} after the end of a quoted field"),
+    ("csv-quote-in-field", "Row {row} has a quote in the middle of a field"),
+    ("csv-unclosed-quote", "Row {row} has a quoted field that is never closed"),
+    ("csv-row-length", "Row {row} has {fields} fields instead of the {expected} of row 1"),
+    ("not-equal", "{left} is not equal to {right}"),
+    ("not-equal-difference", "{left} is not equal to {right} ({difference})"),
+    ("not-equal-types", "{left} is not equal to {right} ({left_type} vs {right_type})"),
+    ("strings-differ", "strings differ at index {index}: {left} vs {right}"),
+    (
+        "string-lengths-differ",
+        "lengths differ ({left} vs {right} characters), first extra character at index {index}",
+    ),
+    ("elements-differ", "element {index} differs: {left} vs {right}"),
+    ("array-lengths-differ", "lengths differ ({left} vs {right} elements)"),
+    // command line and output
+    ("invalid-wrap-width", "Wrap width must be a positive number or auto, got {value}"),
+    ("unknown-pass", "Optimizer pass {name} doesn't exist"),
+    ("on-line", "{error} on line {line}"),
+    ("execution-history", "Recent execution history:"),
+    ("output-closed", "Output stream was closed"),
+    ("write-failed", "Failed to write output: {reason}"),
+    ("warning", "Warning: {message}"),
+    ("summary", "--- Summary ---"),
+    ("summary-failed", "--- Summary (program failed) ---"),
+    ("summary-statements", "Statements executed: {count}"),
+    ("summary-lines", "Lines of output: {count}"),
+    ("summary-bytes", "Bytes of output: {count}"),
+    ("summary-truncated", "Output was truncated"),
+    ("summary-depth", "Max recursion depth: {depth}"),
+    ("summary-time", "Wall time: {millis} ms"),
+    ("summary-globals", "Global variables:"),
+    ("audit-log", "Audit log:"),
+    ("executed-lines", "Executed lines: {lines}"),
+    ("catalog-missing-tab", "Message catalog line has no tab between the id and the text"),
+    ("catalog-unknown-id", "Message catalog has a message {name} that doesn't exist"),
+    (
+        "division-remainder",
+        "Line {line}: {left} / {right} gives {result}, dropping the remainder {remainder}",
+    ),
+];
+
+thread_local! {
+    // translations set with set_locale_catalog, english is used for messages missing from it
+    static CATALOG: RefCell<HashMap<MessageId, String>> = RefCell::new(HashMap::new());
+}
+
+// replaces the translations of messages, keyed by their id
+// messages made after this, and errors shown after this, use the new text
+pub fn set_locale_catalog(catalog: HashMap<MessageId, String>) {
+    CATALOG.with(|current| *current.borrow_mut() = catalog);
+}
+
+// gets the english text of every message, for starting a translation
+pub fn english_catalog() -> HashMap<MessageId, String> {
+    MESSAGES.iter().map(|(id, text)| (*id, text.to_string())).collect()
+}
+
+// finds the id of a message by its name, for catalogs read from files
+pub fn find_message_id(name: &str) -> Option<MessageId> {
+    MESSAGES.iter().map(|(id, _)| *id).find(|id| *id == name)
+}
+
+// reads a catalog written with one message on each line, as its id, a tab, and its text
+// empty lines and lines starting with # are skipped
+pub fn parse_catalog(text: &str) -> Result<HashMap<MessageId, String>, Error> {
+    let mut catalog = HashMap::new();
+    for (ind, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, text) = match line.split_once('\t') {
+            Some(entry) => entry,
+            None => return Err(Error::from_message(ErrorType::ValueError, "catalog-missing-tab", &[], Some(ind + 1))),
+        };
+        match find_message_id(name) {
+            Some(id) => catalog.insert(id, text.to_string()),
+            None => {
+                return Err(Error::from_message(
+                    ErrorType::ValueError,
+                    "catalog-unknown-id",
+                    &[("name", &name)],
+                    Some(ind + 1),
+                ))
+            }
+        };
+    }
+    Ok(catalog)
+}
+
+// gets the text of a message in the current catalog
+// an id that isn't in the english messages is shown as it is
+fn template(id: MessageId) -> String {
+    if let Some(text) = CATALOG.with(|catalog| catalog.borrow().get(id).cloned()) {
+        return text;
+    }
+    match MESSAGES.iter().find(|(other, _)| *other == id) {
+        Some((_, text)) => text.to_string(),
+        None => id.to_string(),
+    }
+}
+
+fn fill_message(template: &str, params: &[(String, String)]) -> String {
+    let mut res = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if (chr == '{' || chr == '}') && chars.peek() == Some(&chr) {
+            chars.next();
+            res.push(chr);
+        } else if chr == '{' {
+            let name: String = chars.by_ref().take_while(|&next| next != '}').collect();
+            match params.iter().find(|(key, _)| *key == name) {
+                Some((_, value)) => res += value,
+                // a parameter the message doesn't have is left for the reader to notice
+                None => res += &format!("{{{}}}", name),
+            }
+        } else {
+            res.push(chr);
+        }
+    }
+    res
+}
+
+// renders a message in the current catalog, filling in its parameters
+pub fn render_message(id: MessageId, params: &[(&str, &dyn Display)]) -> String {
+    let params: Vec<(String, String)> = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    fill_message(&template(id), &params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::testing::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn every_error_type_is_explained() {
+        for err in ErrorType::iter() {
+            let explanation = err.explanation();
+            assert!(!explanation.is_empty(), "{:?} has no explanation", err);
+            // an explanation missing from the messages would be shown as its id
+            assert!(!explanation.ends_with("-explanation"), "{:?} has no explanation", err);
+        }
+    }
+
+    #[test]
+    fn illegal_statement_explanation() {
+        let err = Lexer::new(String::from("[Chorus]\nNever gonna sya 1\n")).parse().unwrap_err();
+        assert_eq!(err.to_string(), "Syntax Error on line 2: Illegal statement");
+        assert_eq!(
+            err.explanation(),
+            "This line isn't a rickroll statement. Statements are lines of the lyrics, \
+                for example: Never gonna let a down, Never gonna give a 1 or Never gonna say a. \
+                The most common cause is a typo, such as a missing word or wrong capitalization."
+        );
+    }
+
+    #[test]
+    fn condition_explanation() {
+        let err = run_source("[Chorus]\nInside we both know 1\nYour heart's been aching but you're too shy to say it\n", "")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorType::RuntimeError);
+        assert!(err.explanation().contains("Conditions must be BOOLs"));
+    }
+
+    // every file that makes errors or shows messages
+    const SOURCES: [(&str, &str); 16] = [
+        ("capabilities.rs", include_str!("capabilities.rs")),
+        ("decompiler.rs", include_str!("decompiler.rs")),
+        ("docs.rs", include_str!("docs.rs")),
+        ("expr.rs", include_str!("expr.rs")),
+        ("interpreter.rs", include_str!("interpreter.rs")),
+        ("lexer.rs", include_str!("lexer.rs")),
+        ("lib.rs", include_str!("lib.rs")),
+        ("main.rs", include_str!("main.rs")),
+        ("optimizer.rs", include_str!("optimizer.rs")),
+        ("parser.rs", include_str!("parser.rs")),
+        ("random.rs", include_str!("random.rs")),
+        ("refactor.rs", include_str!("refactor.rs")),
+        ("replay.rs", include_str!("replay.rs")),
+        ("stdlib.rs", include_str!("stdlib.rs")),
+        ("testing.rs", include_str!("testing.rs")),
+        ("util.rs", include_str!("util.rs")),
+    ];
+
+    #[test]
+    fn every_message_is_in_the_catalog() {
+        let uses = regex::Regex::new(r#"(?:from_message\(\s*[^,]+,|render_message\()\s*"([^"]*)""#).unwrap();
+        let mut found = 0;
+        for (file, src) in SOURCES {
+            for captures in uses.captures_iter(src) {
+                let id = &captures[1];
+                assert!(find_message_id(id).is_some(), "{} uses message {}, which isn't in MESSAGES", file, id);
+                found += 1;
+            }
+        }
+        // the pattern still finds the messages
+        assert!(found > 150, "only {} messages found", found);
+        let mut ids: Vec<MessageId> = MESSAGES.iter().map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        let len = ids.len();
+        ids.dedup();
+        assert_eq!(ids.len(), len, "a message id is in MESSAGES twice");
+    }
+
+    #[test]
+    fn no_stray_error_text() {
+        // the only error made from text is one raised by a program, whose text is its own
+        let raised = "Error::new(*err, &desc[..], Some(*ln))";
+        for (file, src) in SOURCES {
+            for (ind, line) in src.lines().enumerate() {
+                if line.contains("Error::new(") && !line.contains("io::Error::new(") {
+                    let ln = ind + 1;
+                    assert!(line.contains(raised), "{} line {} makes an error without a message id", file, ln);
+                }
+            }
+        }
+        // warnings are messages too
+        let warning = regex::Regex::new(r#"warnings\.borrow_mut\(\)\.push\(\s*format!"#).unwrap();
+        for (file, src) in SOURCES {
+            assert!(!warning.is_match(src), "{} makes a warning without a message id", file);
+        }
+    }
+
+    #[test]
+    fn errors_have_message_ids() {
+        let programs = [
+            "[Chorus]\nNever gonna sya 1\n",
+            "[Chorus]\nNever gonna say $\n",
+            "[Chorus]\nNever gonna say x\n",
+            "[Chorus]\nNever gonna let a down\nNever gonna let a down\n",
+            "[Chorus]\nInside we both know 1 < 2\n",
+            "[Chorus]\nNever gonna say 1 / 0\n",
+            "[Chorus]\nNever gonna say 1 + TRUE\n",
+            "[Chorus]\nNever gonna say \"ab\":5\n",
+            "[Chorus]\nNever gonna run Nothing and desert you\n",
+            "[Chorus]\nNever gonna let a down\n(Ooh give you a) Never gonna run ArrayLength and desert a\n",
+            "[Chorus]\nYou know the rules and a is INT\n",
+        ];
+        for src in programs {
+            let err = run_source(src, "").unwrap_err();
+            assert!(err.root().message_id().is_some(), "{:?} has no message id: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn translated_rendering() {
+        set_locale_catalog(HashMap::from([
+            ("index-out-of-bounds", String::from("Indice {index} fuera de rango (longitud {length})")),
+            ("index-out-of-bounds-error", String::from("Fuera de rango")),
+            ("traceback", String::from("Rastreo")),
+            ("on-line", String::from("{error} en la l\u{ed}nea {line}")),
+        ]));
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a \"abc\"
+Never gonna say a:7
+";
+        let err = run_source(src, "").unwrap_err();
+        let translated = err.to_string();
+        let params = err.root().params().to_vec();
+        // messages missing from the catalog are in english
+        let untranslated = run_source("[Chorus]\nNever gonna say 1 / 0\n", "").unwrap_err().to_string();
+        set_locale_catalog(HashMap::new());
+        assert_eq!(translated, "Fuera de rango: Indice 7 fuera de rango (longitud 3)\nRastreo en la l\u{ed}nea 4");
+        assert_eq!(err.root().message_id(), Some("index-out-of-bounds"));
+        assert_eq!(
+            params,
+            [(String::from("index"), String::from("7")), (String::from("length"), String::from("3"))]
+        );
+        assert_eq!(untranslated, "Runtime Error: Division by zero\nRastreo en la l\u{ed}nea 2");
+        // the same error shown after the catalog is removed is in english again
+        assert_eq!(
+            err.to_string(),
+            "Index Out of Bounds: Index 7 is out of bounds for an array of length 3\nTraceback on line 4"
+        );
+    }
+
+    #[test]
+    fn catalog_from_text() {
+        let catalog = parse_catalog("# spanish\n\nillegal-statement\tInstrucci\u{f3}n no v\u{e1}lida\n").unwrap();
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog["illegal-statement"], "Instrucci\u{f3}n no v\u{e1}lida");
+        let err = parse_catalog("illegal-statement Illegal\n").unwrap_err();
+        assert_eq!(err.root().message_id(), Some("catalog-missing-tab"));
+        let err = parse_catalog("not-a-message\tText\n").unwrap_err();
+        assert_eq!(err.root().message_id(), Some("catalog-unknown-id"));
+        assert_eq!(
+            err.to_string(),
+            "Value Error on line 1: Message catalog has a message not-a-message that doesn't exist"
+        );
+    }
+}
@@ -2,6 +2,8 @@ use crate::error::*;
 use crate::lexer::Token;
 use crate::util::*;
 
+use std::rc::Rc;
+
 // special operator characters
 const OP_CHARS: &str = "!&|<>=~";
 
@@ -18,19 +20,71 @@ pub struct ExprLexer {
     ptr: usize,
     tokens: Vec<Token>,
     line: usize,
+    // number of tokens right after the last string literal
+    string_end: Option<usize>,
+    // 0-based column each token starts at
+    columns: Vec<usize>,
+    // columns of the expression in its line, before its first character
+    column_offset: usize,
+}
+
+// quotes a character for an error message
+pub fn quote_char(chr: char) -> String {
+    if chr == '"' {
+        return String::from("'\"'");
+    }
+    return format!("\"{}\"", chr);
+}
+
+// makes the error for a character the tokenizer doesn't understand, at a 1-based column of the line
+// non-ASCII characters are shown with their code point, and confusable ones with a suggestion
+fn illegal_char_err(chr: char, column: usize) -> Error {
+    let quoted = quote_char(chr);
+    let code = format!("U+{:04X}", chr as u32);
+    if let Some((name, ascii)) = confusable(chr) {
+        return Error::from_message(
+            ErrorType::IllegalCharError,
+            "illegal-confusable-char",
+            &[("char", &quoted), ("code", &code), ("name", &name), ("column", &column), ("suggestion", &quote_char(ascii))],
+            None,
+        );
+    }
+    if !chr.is_ascii() {
+        return Error::from_message(
+            ErrorType::IllegalCharError,
+            "illegal-unicode-char",
+            &[("char", &quoted), ("code", &code), ("column", &column)],
+            None,
+        );
+    }
+    return Error::from_message(
+        ErrorType::IllegalCharError,
+        "illegal-char",
+        &[("char", &quoted), ("column", &column)],
+        None,
+    );
 }
 
 impl ExprLexer {
     // makes a new tokenizer from the raw string
-    pub fn new(string: String, line: usize) -> ExprLexer {
+    pub fn new(string: &str, line: usize) -> ExprLexer {
         ExprLexer {
             raw: string.trim().chars().collect(),
             ptr: 0,
             tokens: Vec::new(),
             line,
+            string_end: None,
+            columns: Vec::new(),
+            column_offset: 0,
         }
     }
 
+    // counts the columns in errors from the start of the line instead of the expression,
+    // for an expression starting this many characters into its line
+    pub fn set_column_offset(&mut self, offset: usize) {
+        self.column_offset = offset;
+    }
+
     // whether tokenizer has more characters to parse
     fn has_more(&self) -> bool {
         self.ptr < self.raw.len()
@@ -40,13 +94,18 @@ impl ExprLexer {
     pub fn make_tokens(mut self) -> Result<Vec<Token>, Error> {
         // empty expression cannot be parsed
         if self.raw.is_empty() {
-            return Err(Error::new(
+            return Err(Error::from_message(
                 ErrorType::SyntaxError,
-                "Unexpected end of statement",
+                "unexpected-end",
+                &[],
                 None,
             ));
         }
+        let mut start = 0;
         while self.ptr < self.raw.len() {
+            // tokens made in the last iteration started where it did
+            self.columns.resize(self.tokens.len(), start);
+            start = self.ptr;
             let mut chr = self.raw[self.ptr]; // cur char
                                               // make number
             if chr.is_ascii_digit() {
@@ -66,23 +125,30 @@ impl ExprLexer {
                 self.tokens.push(operator);
                 continue;
             }
+            // string literal
+            if chr == '"' {
+                self.make_string()?;
+                continue;
+            }
             // character literal
             if chr == '\'' {
                 self.ptr += 1;
                 // expected more characters in expression
                 if !self.has_more() {
-                    return Err(Error::new(
+                    return Err(Error::from_message(
                         ErrorType::IllegalCharError,
-                        "Trailing character literal",
+                        "trailing-char-literal",
+                        &[],
                         None,
                     ));
                 }
                 let mut chrlit = self.raw[self.ptr]; // value of char literal
                                                      // empty char literal ('')
                 if chrlit == '\'' {
-                    return Err(Error::new(
+                    return Err(Error::from_message(
                         ErrorType::IllegalCharError,
-                        "Empty literal",
+                        "empty-literal",
+                        &[],
                         None,
                     ));
                 }
@@ -90,9 +156,10 @@ impl ExprLexer {
                 if chrlit == '\\' {
                     self.ptr += 1;
                     if !self.has_more() {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::IllegalCharError,
-                            "Trailing character literal",
+                            "trailing-char-literal",
+                            &[],
                             None,
                         ));
                     }
@@ -104,18 +171,20 @@ impl ExprLexer {
                 }
                 self.ptr += 1;
                 if !self.has_more() {
-                    return Err(Error::new(
+                    return Err(Error::from_message(
                         ErrorType::IllegalCharError,
-                        "Trailing character literal",
+                        "trailing-char-literal",
+                        &[],
                         None,
                     ));
                 }
                 // make sure last character closes off the literal
                 chr = self.raw[self.ptr];
                 if chr != '\'' {
-                    return Err(Error::new(
+                    return Err(Error::from_message(
                         ErrorType::IllegalCharError,
-                        "More than one character in literal",
+                        "long-char-literal",
+                        &[],
                         None,
                     ));
                 }
@@ -134,71 +203,120 @@ impl ExprLexer {
                 '(' => self.tokens.push(Token::Punc(self.line, String::from("("))),
                 ')' => self.tokens.push(Token::Punc(self.line, String::from(")"))),
                 _ => {
-                    return Err(Error::new(
-                        ErrorType::IllegalCharError,
-                        "Illegal character in expression",
-                        None,
-                    ));
+                    return Err(illegal_char_err(chr, self.column_offset + self.ptr + 1));
                 }
             }
             self.ptr += 1;
         }
+        self.columns.resize(self.tokens.len(), start);
+        self.check_trailing()?;
         return Ok(self.tokens);
     }
 
+    // finds tokens left over after a whole expression, such as the 2 in 1 2 or the ) in 1 )
+    // these are reported where they start, since the expression before them is fine
+    // a "(" that is never closed is reported where it opens
+    fn check_trailing(&self) -> Result<(), Error> {
+        // columns of the parentheses that are still open
+        let mut open: Vec<usize> = Vec::new();
+        // whether the tokens so far end with a value that an operator could follow
+        let mut complete = false;
+        for (token, column) in self.tokens.iter().zip(self.columns.iter()) {
+            // values, unary operators and "(" start a new value, which can't follow a whole one
+            let starts_value = match token {
+                Token::Operator(_, op) => matches!(get_operator(op), Ok(op) if op.is_unary()),
+                Token::Punc(_, punc) => punc == "(",
+                _ => true,
+            };
+            let closing = matches!(token, Token::Punc(_, punc) if punc == ")");
+            if open.is_empty() && ((complete && starts_value) || closing) {
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    "trailing-characters",
+                    &[("column", &(self.column_offset + column + 1))],
+                    None,
+                ));
+            }
+            match token {
+                Token::Punc(_, punc) if punc == "(" => {
+                    open.push(*column);
+                    complete = false;
+                }
+                Token::Punc(_, _) => {
+                    open.pop();
+                    complete = true;
+                }
+                Token::Operator(_, _) => complete = false,
+                _ => complete = true,
+            }
+        }
+        if let Some(column) = open.last() {
+            return Err(Error::from_message(
+                ErrorType::SyntaxError,
+                "unclosed-parenthesis",
+                &[("column", &(self.column_offset + column + 1))],
+                None,
+            ));
+        }
+        return Ok(());
+    }
+
     // parses a number starting at self.ptr
     fn make_number(&mut self) -> Result<Token, Error> {
-        let mut float = false;
         let mut raw = String::new();
-        let mut chr = self.raw[self.ptr]; // cur char
+        // digits and '.' are part of the number
+        while self.has_more() && (self.raw[self.ptr].is_ascii_digit() || self.raw[self.ptr] == '.') {
+            raw.push(self.raw[self.ptr]);
+            self.ptr += 1;
+        }
+        return Ok(Token::Value(self.line, parse_number(&raw)?));
+    }
+
+    // makes a string literal starting at ptr, either "..." or """...""" spanning lines
+    // adjacent string literals are merged into one
+    fn make_string(&mut self) -> Result<(), Error> {
+        let triple = self.raw[self.ptr..].starts_with(&['"', '"', '"']);
+        self.ptr += if triple { 3 } else { 1 };
+        let mut chars = Vec::new();
         loop {
-            // '.' means number is floating point
-            if chr == '.' {
-                // only one '.' can exist in a number
-                if float {
-                    return Err(Error::new(
-                        ErrorType::IllegalCharError,
-                        "Unknown character '.'",
-                        None,
-                    ));
-                }
-                float = true;
+            if !self.has_more() {
+                return Err(Error::from_message(
+                    ErrorType::IllegalCharError,
+                    "unterminated-string",
+                    &[],
+                    None,
+                ));
             }
-            raw.push(chr);
-            self.ptr += 1;
-            // check if still part of number
-            if self.has_more() {
-                let cur = self.raw[self.ptr];
-                if cur.is_ascii_digit() || cur == '.' {
-                    chr = cur;
-                } else {
-                    break;
-                }
-            } else {
+            let mut chr = self.raw[self.ptr];
+            if triple && self.raw[self.ptr..].starts_with(&['"', '"', '"']) {
+                self.ptr += 3;
                 break;
             }
-        }
-        if float {
-            let res = raw.parse::<f32>();
-            match res {
-                Ok(val) => return Ok(Token::Value(self.line, RickrollObject::Float(val))),
-                Err(_) => return Err(Error::new(
-                    ErrorType::IllegalArgumentError,
-                    "Improper floating point literal",
-                    None,
-                )),
+            if !triple && chr == '"' {
+                self.ptr += 1;
+                break;
             }
-        } else {
-            let res = raw.parse::<i32>();
-            match res {
-                Ok(val) => return Ok(Token::Value(self.line, RickrollObject::Int(val))),
-                Err(_) => return Err(Error::new(
-                    ErrorType::IllegalArgumentError,
-                    "Improper integer literal",
-                    None,
-                )),
+            // possible escape sequence
+            if chr == '\\' && self.ptr + 1 < self.raw.len() {
+                self.ptr += 1;
+                chr = match self.raw[self.ptr] {
+                    'n' => '\n', // new line
+                    other => other,
+                };
+            }
+            chars.push(RickrollObject::Char(chr));
+            self.ptr += 1;
+        }
+        if self.string_end == Some(self.tokens.len()) {
+            if let Some(Token::Value(_, RickrollObject::Array(prev))) = self.tokens.last_mut() {
+                Rc::make_mut(prev).append(&mut chars);
+                return Ok(());
             }
         }
+        self.tokens
+            .push(Token::Value(self.line, RickrollObject::Array(Rc::new(chars))));
+        self.string_end = Some(self.tokens.len());
+        return Ok(());
     }
 
     // makes a variable/constant starting at ptr
@@ -253,9 +371,10 @@ impl ExprLexer {
             "&&" | "||" | ">" | "<" | ">=" | "<=" | "==" | "!=" | "!" | "~" => {
                 Ok(Token::Operator(self.line, opname))
             }
-            _ => Err(Error::new(
+            _ => Err(Error::from_message(
                 ErrorType::RuntimeError,
-                &format!("Operator {} not found", opname).to_string(),
+                "unknown-operator",
+                &[("operator", &opname)],
                 None,
             )),
         };
@@ -282,14 +401,132 @@ pub fn get_operator(str: &String) -> Result<Operator, Error> {
         ":" => Ok(ArrayAccess),
         "!" => Ok(Not),
         "~" => Ok(UnaryMinus),
-        _ => Err(Error::new(
+        _ => Err(Error::from_message(
             ErrorType::SyntaxError,
-            &format!("Operator {} not found", str)[..],
+            "unknown-operator",
+            &[("operator", &str)],
             None,
         )),
     };
 }
 
+// escapes a character for use inside a literal delimited by quote
+fn escape_char(chr: char, quote: char) -> String {
+    match chr {
+        '\n' => String::from("\\n"),
+        '\\' => String::from("\\\\"),
+        _ if chr == quote => format!("\\{}", chr),
+        _ => chr.to_string(),
+    }
+}
+
+// renders a value as an expression that evaluates back to it
+pub fn render_value(obj: &RickrollObject) -> Result<String, Error> {
+    use RickrollObject::*;
+    return match obj {
+        // there are no negative literals, and the minimum can't be negated
+        Int(i32::MIN) => Ok(format!("(~{} - 1)", i32::MAX)),
+        Int(x) if *x < 0 => Ok(format!("~{}", -x)),
+        Int(x) => Ok(x.to_string()),
+        Float(x) if x.is_nan() => Ok(String::from("(0.0 / 0.0)")),
+        Float(x) if x.is_infinite() => Ok(String::from(if *x > 0.0 { "(1.0 / 0.0)" } else { "(~1.0 / 0.0)" })),
+        Float(x) => {
+            // literals need a decimal point to be floats
+            let mut res = format!("{}", x.abs());
+            if !res.contains('.') {
+                res += ".0";
+            }
+            if x.is_sign_negative() {
+                res = format!("~{}", res);
+            }
+            Ok(res)
+        }
+        Bool(true) => Ok(String::from("TRUE")),
+        Bool(false) => Ok(String::from("FALSE")),
+        Undefined => Ok(String::from("UNDEFINED")),
+        Char(x) => Ok(format!("'{}'", escape_char(*x, '\''))),
+        Array(arr) if arr.is_empty() => Ok(String::from("ARRAY")),
+        Array(_) => match from_char_array(obj) {
+            Some(string) => Ok(format!("\"{}\"", string.chars().map(|chr| escape_char(chr, '"')).collect::<String>())),
+            None => Err(Error::from_message(
+                ErrorType::IllegalArgumentError,
+                "array-without-literal",
+                &[("array", &obj)],
+                None,
+            )),
+        },
+    };
+}
+
+// renders an operand, in parentheses if it would otherwise bind to the wrong operator
+fn render_operand(expr: &Expr, parent: &Operator, right: bool, strict: bool) -> Result<String, Error> {
+    let res = render_with(expr, strict)?;
+    if let Expr::Operation(op, _) = expr {
+        // unary operators bind to the operand right after them
+        let wrap = if parent.is_unary() {
+            !op.is_unary()
+        } else if op.is_unary() {
+            false
+        } else if right {
+            // all operators are left-associative
+            precedence_of(op) <= precedence_of(parent)
+        } else {
+            precedence_of(op) < precedence_of(parent)
+        };
+        if wrap {
+            return Ok(format!("({})", res));
+        }
+    }
+    return Ok(res);
+}
+
+// renders values without a literal form as they are printed, unless strict
+fn render_with(expr: &Expr, strict: bool) -> Result<String, Error> {
+    return match expr {
+        Expr::Value(obj) => match render_value(obj) {
+            Err(_) if !strict => Ok(obj.to_string()),
+            res => res,
+        },
+        Expr::Name(name) => Ok(name.clone()),
+        Expr::Operation(op, args) if op.is_unary() && args.len() == 1 => {
+            let operand = render_operand(&args[0], op, true, strict)?;
+            // adjacent operator characters would be read as a single operator
+            if operand.starts_with(|chr| OP_CHARS.contains(chr)) {
+                Ok(format!("{} {}", op.symbol(), operand))
+            } else {
+                Ok(format!("{}{}", op.symbol(), operand))
+            }
+        }
+        // operands are stored in reverse
+        Expr::Operation(op, args) if args.len() == 2 => Ok(format!(
+            "{} {} {}",
+            render_operand(&args[1], op, false, strict)?,
+            op.symbol(),
+            render_operand(&args[0], op, true, strict)?
+        )),
+        Expr::Operation(op, _) => Err(Error::from_message(
+            ErrorType::SyntaxError,
+            "wrong-operand-count",
+            &[("operation", &format!("{:?}", op))],
+            None,
+        )),
+    };
+}
+
+// renders an expression as source code, with parentheses only where precedence needs them
+// lexing the result gives back the same expression
+pub fn render_expr(expr: &Expr) -> Result<String, Error> {
+    render_with(expr, true)
+}
+
+// renders an expression for messages
+pub fn render(expr: &Expr) -> String {
+    match render_with(expr, false) {
+        Ok(res) => res,
+        Err(_) => String::from("<malformed expression>"),
+    }
+}
+
 // get precedence of operator
 pub fn precedence_of(op: &Operator) -> usize {
     use Operator::*;
@@ -406,9 +643,10 @@ impl ExprParser {
                     if self.scope.has_var(name.clone()) {
                         self.output_stack.push(token);
                     } else {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::NameError,
-                            &(format!("No such variable {}", name))[..],
+                            "no-such-variable",
+                            &[("name", &name)],
                             None,
                         ));
                     }
@@ -432,9 +670,10 @@ impl ExprParser {
             } else if let Token::Value(_, val) = tok {
                 return Ok(Expr::Value(val));
             } else {
-                return Err(Error::new(
+                return Err(Error::from_message(
                     ErrorType::SyntaxError,
-                    "Illegal expression",
+                    "illegal-expression",
+                    &[],
                     None,
                 ));
             }
@@ -481,6 +720,8 @@ impl ExprParser {
                         } else {
                             panic!("ExprParser::parse: Found non-operation in return stack");
                         }
+                    } else {
+                        break; // top operation still needs operands
                     }
                 } else {
                     panic!("ExprParser::parse: Found non-operation in return stack");
@@ -488,9 +729,10 @@ impl ExprParser {
             }
         }
         if stack.len() != 1 {
-            return Err(Error::new(
+            return Err(Error::from_message(
                 ErrorType::SyntaxError,
-                "Illegal expression",
+                "illegal-expression",
+                &[],
                 None,
             ));
         } else {
@@ -498,3 +740,197 @@ impl ExprParser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    // runs a chorus printing the expression, which starts at column 17 of its line
+    fn say_err(expr: &str) -> Error {
+        return run_source(&format!("[Chorus]\nNever gonna say {}\n", expr), "").unwrap_err();
+    }
+
+    fn column(err: &Error) -> &str {
+        let (_, column) = err.root().params().iter().find(|(name, _)| name == "column").unwrap();
+        return column;
+    }
+
+    #[test]
+    fn trailing_characters() {
+        let err = say_err("(1))");
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.root().message_id(), Some("trailing-characters"));
+        assert_eq!(column(&err), "20");
+        assert_eq!(column(&say_err("1 2")), "19");
+    }
+
+    #[test]
+    fn unclosed_parenthesis() {
+        let err = say_err("1 + (2");
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.root().message_id(), Some("unclosed-parenthesis"));
+        assert_eq!(column(&err), "21");
+        // the innermost parenthesis left open
+        assert_eq!(column(&say_err("((1) + (2")), "24");
+        assert_eq!(say_err("(1 + 2").root().message_id(), Some("unclosed-parenthesis"));
+        // parentheses in literals aren't counted
+        run_and_expect("[Chorus]\nNever gonna say (\"(\")\n", "", "[(]\n");
+    }
+
+    #[test]
+    fn illegal_char_column() {
+        let err = say_err("1 + $");
+        assert_eq!(err.kind(), ErrorType::IllegalCharError);
+        assert_eq!(column(&err), "21");
+    }
+
+    fn param<'a>(err: &'a Error, wanted: &str) -> &'a str {
+        let (_, value) = err.root().params().iter().find(|(name, _)| name == wanted).unwrap();
+        return value;
+    }
+
+    #[test]
+    fn confusable_chars() {
+        let mut chars: Vec<(char, String, char)> =
+            CONFUSABLES.iter().map(|(chr, name, ascii)| (*chr, String::from(*name), *ascii)).collect();
+        chars.push(('\u{FF17}', String::from("FULLWIDTH DIGIT SEVEN"), '7'));
+        for (chr, name, ascii) in chars {
+            // spaces between tokens are skipped like any other whitespace
+            if ascii == ' ' {
+                let err = run_source(&format!("[Chorus]\nNever{}gonna say 1\n", chr), "").unwrap_err();
+                assert_eq!(err.root().message_id(), Some("illegal-statement-confusable"), "{}", name);
+                assert_eq!(param(&err, "name"), name);
+                continue;
+            }
+            let err = say_err(&format!("1 {} 2", chr));
+            assert_eq!(err.kind(), ErrorType::IllegalCharError, "{}", name);
+            assert_eq!(err.root().message_id(), Some("illegal-confusable-char"), "{}", name);
+            assert_eq!(param(&err, "name"), name);
+            assert_eq!(param(&err, "code"), format!("U+{:04X}", chr as u32));
+            assert_eq!(param(&err, "suggestion"), quote_char(ascii));
+            assert_eq!(column(&err), "19");
+        }
+        assert_eq!(
+            say_err("1 \u{2212} 2").to_string(),
+            "Illegal Character: Illegal character \"\u{2212}\" (U+2212 MINUS SIGN) in expression at column 19, did you mean \"-\"?\nTraceback on line 2"
+        );
+    }
+
+    #[test]
+    fn unknown_chars() {
+        let err = say_err("1 \u{2603} 2");
+        assert_eq!(err.root().message_id(), Some("illegal-unicode-char"));
+        assert_eq!(param(&err, "code"), "U+2603");
+        assert_eq!(say_err("1 $ 2").root().message_id(), Some("illegal-char"));
+    }
+
+    #[test]
+    fn fixing_confusables() {
+        let src = "[Chorus]\nNever gonna say \u{201C}a\u{2019}b\u{201D}\nNever gonna say 7 \u{2013} \u{FF12}\u{00A0}\u{00D7} 3\n";
+        let fixed = fix_confusables(src);
+        // characters inside strings are kept
+        assert_eq!(fixed, "[Chorus]\nNever gonna say \"a\u{2019}b\"\nNever gonna say 7 - 2 * 3\n");
+        assert_eq!(fix_confusables(&fixed), fixed);
+        run_and_expect(&fixed, "", "[a, \u{2019}, b]\n1\n");
+    }
+
+    // lexes and parses an expression using the variables a and b
+    fn parse(source: &str) -> Expr {
+        let mut scope = Scope::new();
+        scope.add_var(String::from("a"));
+        scope.add_var(String::from("b"));
+        let tokens = ExprLexer::new(source, 1).make_tokens().unwrap();
+        return ExprParser::new(tokens, scope).parse().unwrap();
+    }
+
+    // small generator of pseudo-random numbers, so the corpus is the same on every run
+    struct Generator(u64);
+
+    impl Generator {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            return self.0 % n;
+        }
+    }
+
+    const OPERATORS: [Operator; 16] = [
+        Operator::ArrayAccess,
+        Operator::Add,
+        Operator::Subtract,
+        Operator::Multiply,
+        Operator::Divide,
+        Operator::Modulo,
+        Operator::UnaryMinus,
+        Operator::And,
+        Operator::Or,
+        Operator::Not,
+        Operator::Greater,
+        Operator::Less,
+        Operator::GreaterEquals,
+        Operator::LessEquals,
+        Operator::Equals,
+        Operator::NotEquals,
+    ];
+
+    fn leaf(gen: &mut Generator) -> Expr {
+        use RickrollObject::*;
+        return match gen.below(8) {
+            0 => Expr::Name(String::from("a")),
+            1 => Expr::Name(String::from("b")),
+            2 => Expr::Value(Int(gen.below(1000) as i32)),
+            3 => Expr::Value(Float(gen.below(100) as f32 / 4.0)),
+            4 => Expr::Value(Bool(gen.below(2) == 0)),
+            5 => Expr::Value(Char(['x', '\'', '"', '\\', '\n', ' ', 'é'][gen.below(7) as usize])),
+            6 => Expr::Value(to_char_array(["", "hi", "a\"b", "\\n", "x y\n", "'"][gen.below(6) as usize])),
+            _ => Expr::Value(Undefined),
+        };
+    }
+
+    fn random_expr(gen: &mut Generator, depth: usize) -> Expr {
+        if depth == 0 || gen.below(4) == 0 {
+            return leaf(gen);
+        }
+        let op = OPERATORS[gen.below(OPERATORS.len() as u64) as usize];
+        // operands are stored in reverse
+        let args = if op.is_unary() {
+            vec![random_expr(gen, depth - 1)]
+        } else {
+            vec![random_expr(gen, depth - 1), random_expr(gen, depth - 1)]
+        };
+        return Expr::Operation(op, args);
+    }
+
+    #[test]
+    fn render_round_trip() {
+        let mut gen = Generator(0x5eed);
+        for _ in 0..2000 {
+            let expr = random_expr(&mut gen, 5);
+            let source = render_expr(&expr).unwrap();
+            assert_eq!(format!("{:?}", parse(&source)), format!("{:?}", expr), "{}", source);
+        }
+    }
+
+    #[test]
+    fn render_tricky_expressions() {
+        let cases = [
+            ("~ ~a", "~ ~a"),
+            ("~(~a)", "~ ~a"),
+            ("~(~1)", "~ ~1"),
+            ("~(a - b)", "~(a - b)"),
+            ("!(!(a > b))", "! !(a > b)"),
+            ("a - (b - 1)", "a - (b - 1)"),
+            ("(a - b) - 1", "a - b - 1"),
+            ("(a + b) * 2", "(a + b) * 2"),
+            ("a + (b * 2)", "a + b * 2"),
+            ("\"a\\\"b\\\\c\\n\"", "\"a\\\"b\\\\c\\n\""),
+            ("'\\''", "'\\''"),
+            ("'\"'", "'\"'"),
+        ];
+        for (source, rendered) in cases {
+            assert_eq!(render_expr(&parse(source)).unwrap(), rendered, "{}", source);
+        }
+    }
+}
@@ -1,33 +1,618 @@
 use crate::error::*;
 use crate::expr::*;
 use crate::parser::*;
+use crate::random::{run_random, Rng, RANDOM_FUNCTIONS};
+use crate::replay::{Recording, Replay};
 use crate::util::*;
-use crate::stdlib::BUILTIN_FUNCTIONS;
+use crate::stdlib::{growth_of, work_of, BUILTIN_FUNCTIONS, CLOCK_FUNCTIONS, INPUT_FUNCTIONS, IN_PLACE_FUNCTIONS, PRIVILEGED_FUNCTIONS};
 
-use std::collections::HashMap;
-use std::io::{BufRead, Write};
+use log::trace;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub const MAX_RECURSION_DEPTH: usize = 10000;
+
+// the interpreter recurses on every function call, so programs need to run on a thread with room for
+// MAX_RECURSION_DEPTH calls
+// a call takes a few times more stack in unoptimized builds
+pub const STACK_SIZE: usize = if cfg!(debug_assertions) {
+    1024 * 1024 * 1024
+} else {
+    256 * 1024 * 1024
+};
+
 pub const MAX_UNWIND_LIMIT: usize = 8;
+// longest value shown in the execution history
+pub const HISTORY_VALUE_LIMIT: usize = 40;
+// most entries kept in the audit log, older ones are dropped
+pub const AUDIT_LOG_SIZE: usize = 64;
+
+// how output past a number of bytes is handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputLimit {
+    Unlimited,
+    // drop the extra output but keep running
+    TruncateAndContinue(usize),
+    // drop the extra output and raise an error
+    Error(usize),
+}
+
+// output bytes shared by every writer of a run, including captured output
+#[derive(Debug)]
+struct OutputBudget {
+    limit: OutputLimit,
+    used: Cell<usize>,
+    notified: Cell<bool>,
+}
+
+impl OutputBudget {
+    fn new(limit: OutputLimit) -> OutputBudget {
+        OutputBudget {
+            limit,
+            used: Cell::new(0),
+            notified: Cell::new(false),
+        }
+    }
+}
+
+// writer that stops writing once the output budget is used up
+struct LimitedWriter<'a> {
+    inner: &'a mut dyn Write,
+    budget: Rc<OutputBudget>,
+}
+
+impl Write for LimitedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let cap = match self.budget.limit {
+            OutputLimit::Unlimited => usize::MAX,
+            OutputLimit::TruncateAndContinue(cap) | OutputLimit::Error(cap) => cap,
+        };
+        let used = self.budget.used.get();
+        if buf.len() <= cap - used {
+            let written = self.inner.write(buf)?;
+            self.budget.used.set(used + written);
+            return Ok(written);
+        }
+        // write what still fits, then a single notice
+        if used < cap {
+            self.inner.write_all(&buf[..(cap - used)])?;
+            self.budget.used.set(cap);
+        }
+        if !self.budget.notified.get() {
+            self.budget.notified.set(true);
+            write!(self.inner, "\n[output truncated after {} bytes]\n", cap)?;
+        }
+        return match self.budget.limit {
+            OutputLimit::Error(_) => Err(io::Error::other(format!(
+                "output limit of {} bytes exceeded",
+                cap
+            ))),
+            _ => Ok(buf.len()),
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// writer breaking lines longer than width characters, at the last space if there is one
+// widths are counted in characters, so a character is never split
+struct WrappingWriter<'a> {
+    inner: &'a mut dyn Write,
+    width: usize,
+    // characters of the current line already written
+    col: usize,
+    // rest of the current line, held back until it is known where it breaks
+    pending: String,
+    // bytes of a character split between writes
+    partial: Vec<u8>,
+}
+
+impl<'a> WrappingWriter<'a> {
+    fn new(inner: &'a mut dyn Write, width: usize) -> WrappingWriter<'a> {
+        WrappingWriter {
+            inner,
+            width: width.max(1),
+            col: 0,
+            pending: String::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, chr: char) -> io::Result<()> {
+        if chr == '\n' {
+            self.pending.push(chr);
+            self.inner.write_all(self.pending.as_bytes())?;
+            self.pending.clear();
+            self.col = 0;
+            return Ok(());
+        }
+        self.pending.push(chr);
+        // a carriage return before a newline takes no room
+        let len = self.pending.chars().filter(|chr| *chr != '\r').count();
+        if self.col + len <= self.width {
+            return Ok(());
+        }
+        match self.pending.rfind(char::is_whitespace) {
+            // the space is replaced by the line break
+            Some(pos) if pos > 0 || self.col > 0 => {
+                let space = self.pending[pos..].chars().next().unwrap().len_utf8();
+                let rest = self.pending.split_off(pos + space);
+                self.pending.truncate(pos);
+                writeln!(self.inner, "{}", self.pending)?;
+                self.pending = rest;
+            }
+            _ if self.col > 0 => {
+                self.inner.write_all(b"\n")?;
+            }
+            _ => {
+                let rest = self.pending.split_off(self.pending.len() - chr.len_utf8());
+                writeln!(self.inner, "{}", self.pending)?;
+                self.pending = rest;
+            }
+        }
+        self.col = 0;
+        return Ok(());
+    }
+}
+
+impl Write for WrappingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let bytes = std::mem::take(&mut self.partial);
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(text) => text.len(),
+            // only an unfinished character at the end is kept for later
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => bytes.len(),
+        };
+        for chr in String::from_utf8_lossy(&bytes[..valid]).chars() {
+            self.push(chr)?;
+        }
+        self.partial = bytes[valid..].to_vec();
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.col += self.pending.chars().filter(|chr| *chr != '\r').count();
+        self.inner.write_all(self.pending.as_bytes())?;
+        self.pending.clear();
+        self.inner.flush()
+    }
+}
+
+// line ending written after each Say
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Newline {
+    Lf,
+    CrLf,
+}
+
+impl Newline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+// where Explain prints values with their types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplainStream {
+    Stdout,
+    Stderr,
+}
+
+// which calls to privileged functions are kept in the audit log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditMode {
+    Denials,
+    Everything,
+}
+
+// a call to a privileged function and whether the sandbox allowed it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub function: String,
+    // the arguments, each cut off after HISTORY_VALUE_LIMIT characters
+    pub args: Vec<String>,
+    pub line: usize,
+    pub allowed: bool,
+}
+
+impl std::fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let decision = if self.allowed { "allowed" } else { "denied" };
+        write!(f, "line {}: {} {}({})", self.line, decision, self.function, self.args.join(", "))
+    }
+}
+
+// counters describing a run, whether or not it succeeded
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStats {
+    pub statements: usize,
+    // bytes written, counting captured output
+    pub output_bytes: usize,
+    // whether output was dropped because of the output limit
+    pub output_truncated: bool,
+    // the chorus and intro are at depth 1
+    pub max_depth: usize,
+    pub elapsed: Duration,
+}
+
+// a statement kept in the execution history
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    index: usize,
+    line: usize,
+    // variable and value written by an assignment
+    written: Option<(String, String)>,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} line {}", self.index, self.line)?;
+        if let Some((name, value)) = &self.written {
+            write!(f, ": {} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// source of the time read by Now and Elapsed, which also does the waiting of pauses
+pub trait Clock: std::fmt::Debug {
+    // gets the time since the Unix epoch
+    fn now(&self) -> Duration;
+    // waits for a duration, after which now should have moved on by at least as much
+    fn sleep(&self, duration: Duration);
+}
+
+// clock reading the system time
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        // a system clock set before 1970 reads as the epoch
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
 
 #[derive(Debug)]
 pub struct Interpreter {
     functions: HashMap<String, ASTNode>,
+    // the program only prints, so the chorus can run without a function call
+    say_only: bool,
+    // words Say prints for booleans and UNDEFINED
+    words: PrintWords,
+    newline: Newline,
+    explain_stream: ExplainStream,
+    // raise an error when arithmetic produces NaN or infinity
+    float_trap: bool,
+    // forbid built-in functions that reach outside the program
+    sandbox: bool,
+    // number of statements executed
+    statements: usize,
+    // global variables at the end of the last run
+    globals: Context,
+    // the last history_size statements executed, reported with errors
+    history: VecDeque<HistoryEntry>,
+    history_size: usize,
+    // current and deepest function call depth of the current run
+    depth: usize,
+    max_depth: usize,
+    output_limit: OutputLimit,
+    // output written so far by the current run
+    budget: Rc<OutputBudget>,
+    // lines of the statements executed by the last run, if recorded
+    coverage: Option<BTreeSet<usize>>,
+    // values printed by Say during the last run with their lines, if collected
+    say_values: Option<Vec<(usize, RickrollObject)>>,
+    // what the last run got from outside, if it is recorded
+    recording: Option<Recording>,
+    // a recording whose values are given to the program instead of reading them
+    replay: Option<Replay>,
+    // hold back all output until the run succeeds, and drop it if it fails
+    atomic_output: bool,
+    audit_mode: AuditMode,
+    // the last AUDIT_LOG_SIZE calls to privileged functions of the last run
+    audit_log: VecDeque<AuditEntry>,
+    // line of the statement being executed
+    line: usize,
+    clock: Box<dyn Clock>,
+    // time the current run started, according to the clock
+    start: Duration,
+    // raise an error when a while loop runs again without anything having changed
+    loop_detection: bool,
+    // number of calls to built-in functions that read from outside the program
+    outside_reads: usize,
+    // most work a single call to a built-in function can do
+    max_statement_work: Option<usize>,
+    // longest array a built-in function can make
+    max_array_length: Option<usize>,
+    // warn when an integer division drops a remainder
+    division_warnings: bool,
+    // warnings of the last run, oldest first
+    warnings: RefCell<Vec<String>>,
+    // width lines written to the output are wrapped at
+    wrap_width: Option<usize>,
+    // seed of the random number generator, or none to seed it from the clock
+    seed: Option<u64>,
+    rng: Rng,
+}
+
+// checks if two sets of variable values are all the same
+fn same_values(a: &[RickrollObject], b: &[RickrollObject]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| equals(x, y))
 }
 
-fn eval_err(op: &Operator) -> Error {
-    Error::new(
-        ErrorType::IllegalArgumentError,
-        &format!("Illegal types for operation {:?}", op)[..],
+// makes an error naming the operator and the types of its operands
+// operands are in the order they were written, unlike args
+fn type_err(op: &Operator, args: &[Expr], operands: &[RickrollObject]) -> Error {
+    let mut described = Vec::new();
+    for (expr, val) in args.iter().rev().zip(operands.iter()) {
+        match expr {
+            Expr::Name(name) => described.push(format!("{} (variable {})", describe_value(val), name)),
+            _ => described.push(describe_value(val)),
+        }
+    }
+    Error::from_message(
+        ErrorType::TypeError,
+        "operator-type-mismatch",
+        &[("operator", &op.symbol()), ("operands", &described.join(" and ")), ("expression", &render(&Expr::Operation(*op, args.to_vec())))],
         None,
     )
 }
 
+fn has_names(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(_) => false,
+        Expr::Name(_) => true,
+        Expr::Operation(_, args) => args.iter().any(has_names),
+    }
+}
+
+// checks if a program is a chorus that only prints expressions without variables
+fn is_say_only(functions: &HashMap<String, ASTNode>) -> bool {
+    if functions.len() != 1 {
+        return false;
+    }
+    match functions.get("[CHORUS]") {
+        Some(ASTNode::Function(_, _, _, _, body)) => body
+            .iter()
+            .all(|node| matches!(node, ASTNode::Say(_, expr) if !has_names(expr)) || matches!(node, ASTNode::SayConstants(..))),
+        _ => false,
+    }
+}
+
 impl Interpreter {
+    // makes an interpreter for functions built by a program embedding rickroll instead of parsed
+    // they are checked first, since functions the parser would reject can make the run panic
+    pub fn from_functions(functions: HashMap<String, ASTNode>) -> Result<Interpreter, Error> {
+        verify(&functions)?;
+        return Ok(Interpreter::new(functions));
+    }
+
     pub fn new(functions: HashMap<String, ASTNode>) -> Interpreter {
         Interpreter {
+            say_only: is_say_only(&functions),
             functions,
+            words: PrintWords::default(),
+            newline: Newline::Lf,
+            explain_stream: ExplainStream::Stdout,
+            float_trap: false,
+            sandbox: false,
+            statements: 0,
+            globals: Context::new(),
+            history: VecDeque::new(),
+            history_size: 0,
+            depth: 0,
+            max_depth: 0,
+            output_limit: OutputLimit::Unlimited,
+            budget: Rc::new(OutputBudget::new(OutputLimit::Unlimited)),
+            coverage: None,
+            say_values: None,
+            recording: None,
+            replay: None,
+            atomic_output: false,
+            audit_mode: AuditMode::Denials,
+            audit_log: VecDeque::new(),
+            line: 0,
+            clock: Box::new(SystemClock),
+            start: Duration::ZERO,
+            loop_detection: false,
+            outside_reads: 0,
+            max_statement_work: None,
+            max_array_length: None,
+            division_warnings: false,
+            warnings: RefCell::new(Vec::new()),
+            wrap_width: None,
+            seed: None,
+            rng: Rng::new(0),
+        }
+    }
+
+    pub fn set_float_trap(&mut self, float_trap: bool) {
+        self.float_trap = float_trap;
+    }
+
+    // changes how booleans and UNDEFINED are printed, without changing their values
+    pub fn set_print_words(&mut self, words: PrintWords) {
+        self.words = words;
+    }
+
+    // changes the line ending written by Say, which is "\n" by default on every platform
+    pub fn set_newline(&mut self, newline: Newline) {
+        self.newline = newline;
+    }
+
+    // chooses where Explain prints, which is with the rest of the output by default
+    // on stderr, its lines aren't captured, limited or held back like the output of Say
+    pub fn set_explain_stream(&mut self, explain_stream: ExplainStream) {
+        self.explain_stream = explain_stream;
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: bool) {
+        self.sandbox = sandbox;
+    }
+
+    // keeps the last history_size statements to report with errors, 0 turns it off
+    pub fn set_history_size(&mut self, history_size: usize) {
+        self.history_size = history_size;
+        self.history = VecDeque::with_capacity(history_size);
+    }
+
+    // limits the number of bytes a run can write, counting captured output too
+    pub fn set_output_limit(&mut self, output_limit: OutputLimit) {
+        self.output_limit = output_limit;
+    }
+
+    // wraps lines of the output longer than width characters, at a space where possible
+    // only the writer passed to run is wrapped, so captured output keeps its long lines
+    pub fn set_wrap_output(&mut self, wrap_width: Option<usize>) {
+        self.wrap_width = wrap_width;
+    }
+
+    // writes the output only once the whole program has run without an error
+    pub fn set_atomic_output(&mut self, atomic_output: bool) {
+        self.atomic_output = atomic_output;
+    }
+
+    // chooses which calls to privileged functions are kept in the audit log
+    pub fn set_audit_mode(&mut self, audit_mode: AuditMode) {
+        self.audit_mode = audit_mode;
+    }
+
+    // stops a while loop whose condition is checked again with every variable unchanged,
+    // which would run forever unless the loop reads input or the clock
+    pub fn set_loop_detection(&mut self, loop_detection: bool) {
+        self.loop_detection = loop_detection;
+    }
+
+    // limits the work of each statement calling a built-in function, counted in elements
+    // the work is estimated before the call, so an oversized one fails without allocating
+    pub fn set_max_statement_work(&mut self, max_statement_work: Option<usize>) {
+        self.max_statement_work = max_statement_work;
+    }
+
+    // limits the length of arrays made or grown by built-in functions
+    // a function that would grow an array past the limit fails without changing it
+    pub fn set_max_array_length(&mut self, max_array_length: Option<usize>) {
+        self.max_array_length = max_array_length;
+    }
+
+    // fails if an array would have more elements than arrays may have
+    fn check_length(&self, func: &str, length: usize) -> Result<(), Error> {
+        if let Some(max) = self.max_array_length {
+            if length > max {
+                return Err(Error::from_message(
+                    ErrorType::ResourceError,
+                    "array-length-limit",
+                    &[("func", &func), ("length", &length), ("max", &max)],
+                    None,
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    // warns about integer divisions that drop a remainder, such as 7 / 2 giving 3
+    // the warnings are collected instead of stopping the run
+    pub fn set_division_warnings(&mut self, division_warnings: bool) {
+        self.division_warnings = division_warnings;
+    }
+
+    // gets the warnings of the last run, oldest first
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.borrow().clone()
+    }
+
+    // removes and returns the warnings so far
+    pub(crate) fn take_warnings(&self) -> Vec<String> {
+        std::mem::take(&mut *self.warnings.borrow_mut())
+    }
+
+    // fails if a call to a built-in function would do more work than a statement may
+    fn check_work(&self, func: &str, args: &[RickrollObject]) -> Result<(), Error> {
+        if let Some(max) = self.max_statement_work {
+            let work = work_of(func, args);
+            if work > max {
+                return Err(Error::from_message(
+                    ErrorType::ResourceError,
+                    "statement-work-limit",
+                    &[("func", &func), ("work", &work), ("max", &max)],
+                    None,
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    // seeds the random number generator at the start of each run, so runs give the same results
+    // without a seed, it is seeded from the clock
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    // replaces the clock read by Now and Elapsed and used to pause
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // records the lines of executed statements during each run
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled { Some(BTreeSet::new()) } else { None };
+    }
+
+    // keeps the value of each Say during each run, as well as printing it
+    pub fn collect_say_values(&mut self, enabled: bool) {
+        self.say_values = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    // records every call reading input, the time, random numbers or environment variables,
+    // with the value it returned
+    pub fn set_recording(&mut self, enabled: bool) {
+        self.recording = if enabled { Some(Recording::new()) } else { None };
+    }
+
+    // gives the calls recorded by the last run
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    // makes every run give the program the values in the recording, in order, instead of reading
+    // them, so the run does what the recorded run did
+    // a call the recording doesn't have at that point is a runtime error
+    pub fn set_replay(&mut self, recording: Option<Recording>) {
+        self.replay = recording.map(Replay::new);
+    }
+
+    fn record(&mut self, line: usize) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.insert(line);
         }
+        if self.history_size == 0 {
+            return;
+        }
+        if self.history.len() == self.history_size {
+            self.history.pop_front();
+        }
+        self.history.push_back(HistoryEntry {
+            index: self.statements,
+            line,
+            written: None,
+        });
     }
 
     // wraps a traceback around a possible error
@@ -38,16 +623,78 @@ impl Interpreter {
         return res;
     }
 
-    fn eval(&self, expr: &Expr, scope: &Scope) -> Result<RickrollObject, Error> {
+    // checks if a call should modify its first argument in place
+    fn is_in_place(&self, func: &String) -> bool {
+        !self.functions.contains_key(func) && IN_PLACE_FUNCTIONS.contains_key(func)
+    }
+
+    // runs an in-place built-in function on the variable binding of its first argument
+    fn run_in_place(
+        &self,
+        func: &String,
+        args: &[String],
+        scope: &mut Scope,
+    ) -> Result<RickrollObject, Error> {
+        if args.is_empty() {
+            return Err(Error::from_message(
+                ErrorType::RuntimeError,
+                "wrong-argument-count",
+                &[("func", &func)],
+                None,
+            ));
+        }
+        let mut passed: Vec<RickrollObject> = Vec::new();
+        for arg in args {
+            match scope.get_var(arg.clone()) {
+                Some(value) => passed.push(value),
+                None => {
+                    return Err(Error::from_message(
+                        ErrorType::NameError,
+                        "missing-variable",
+                        &[("name", &arg)],
+                        None,
+                    ))
+                }
+            }
+        }
+        if self.max_statement_work.is_some() {
+            self.check_work(func, &passed)?;
+        }
+        passed.remove(0);
+        // checked before the value is moved out, so the array is left as it was
+        // functions that don't grow the array work on arrays of any length
+        let growth = growth_of(func);
+        if growth > 0 {
+            if let Some(RickrollObject::Array(arr)) = scope.get_var(args[0].clone()) {
+                self.check_length(func, arr.len() + growth)?;
+            }
+        }
+        // the value is moved out so an array that isn't shared is changed without being copied
+        let mut target = scope.take_var(args[0].clone()).unwrap();
+        let res = IN_PLACE_FUNCTIONS[func](&mut target, passed);
+        scope.set_var(args[0].clone(), target);
+        return res;
+    }
+
+    // evaluates source code holding a single expression, without any variables
+    // columns in errors are counted from the start of the source
+    pub fn eval_source(&self, source: &str) -> Result<RickrollObject, Error> {
+        let tokens = ExprLexer::new(source, 1).make_tokens()?;
+        let expr = ExprParser::new(tokens, Scope::new()).parse()?;
+        return self.eval(&expr, &Scope::new());
+    }
+
+    pub(crate) fn eval(&self, expr: &Expr, scope: &Scope) -> Result<RickrollObject, Error> {
         match expr {
             Expr::Value(obj) => Ok(obj.clone()),
             Expr::Name(name) => {
                 if scope.has_var(name.clone()) {
                     return Ok(scope.get_var(name.clone()).unwrap());
                 } else {
-                    return Err(Error::new(
+                    return Err(Error::from_message(
                         ErrorType::NameError,
-                        &format!("Variable {} doesn't exist", name)[..],
+                        "missing-variable",
+                        &[("name", &name)],
                         None,
                     ));
                 }
@@ -61,105 +708,127 @@ impl Interpreter {
                         UnaryMinus => match operand {
                             Int(x) => Ok(Int(-x)),
                             Float(x) => Ok(Float(-x)),
-                            _ => Err(eval_err(op)),
+                            x => Err(type_err(op, args, &[x])),
                         },
                         Not => match operand {
                             Bool(x) => Ok(Bool(!x)),
-                            _ => Err(eval_err(op)),
+                            x => Err(type_err(op, args, &[x])),
                         },
                         _ => panic!("Unary operator is not unary!"),
                     };
                 } else if !op.is_unary() && args.len() == 2 {
                     // expressions operands start from the top
                     let first = self.eval(&args[1], scope)?;
+                    // the right operand isn't evaluated when the left one decides the result,
+                    // so it can't raise an error
+                    match (op, &first) {
+                        (And, Bool(false)) => return Ok(Bool(false)),
+                        (Or, Bool(true)) => return Ok(Bool(true)),
+                        _ => (),
+                    }
                     let second = self.eval(&args[0], scope)?;
+                    // operands are only kept around when trapping
+                    let operands = if self.float_trap {
+                        Some((first.clone(), second.clone()))
+                    } else {
+                        None
+                    };
                     let ans = match op {
                         ArrayAccess => match (first, second) {
-                            (Array(arr), Int(x)) => Ok(arr[x as usize].clone()),
-                            _ => Err(eval_err(op)),
+                            (Array(arr), Int(x)) => match usize::try_from(x).ok().and_then(|ind| arr.get(ind)) {
+                                Some(elem) => Ok(elem.clone()),
+                                None => Err(Error::from_message(
+                                    ErrorType::IndexOutOfBoundsError,
+                                    "index-out-of-bounds",
+                                    &[("index", &x), ("length", &arr.len())],
+                                    None,
+                                )),
+                            },
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Add => match (first, second) {
                             (Int(x), Int(y)) => Ok(Int(x.wrapping_add(y))),
                             (Float(x), Float(y)) => Ok(Float(x + y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Subtract => match (first, second) {
                             (Int(x), Int(y)) => Ok(Int(x.wrapping_sub(y))),
                             (Float(x), Float(y)) => Ok(Float(x - y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Multiply => match (first, second) {
                             (Int(x), Int(y)) => Ok(Int(x.wrapping_mul(y))),
                             (Float(x), Float(y)) => Ok(Float(x * y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Divide => match (first, second) {
                             (Int(x), Int(y)) => {
                                 if y == 0 {
-                                    Err(Error::new(
+                                    Err(Error::from_message(
                                         ErrorType::RuntimeError,
-                                        &format!("Division by zero")[..],
+                                        "division-by-zero",
+                                        &[],
                                         None,
                                     ))
                                 } else {
-                                    Ok(Int(x.wrapping_div(y)))
+                                    let res = x.wrapping_div(y);
+                                    if self.division_warnings && x.wrapping_rem(y) != 0 {
+                                        self.warnings.borrow_mut().push(render_message(
+                                            "division-remainder",
+                                            &[("line", &self.line), ("left", &x), ("right", &y), ("result", &res), ("remainder", &x.wrapping_rem(y))],
+                                        ));
+                                    }
+                                    Ok(Int(res))
                                 }
                             },
                             (Float(x), Float(y)) => Ok(Float(x / y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Modulo => match (first, second) {
                             (Int(x), Int(y)) => Ok(Int(x.wrapping_rem(y))),
                             (Float(x), Float(y)) => Ok(Float(x % y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         And => match (first, second) {
                             (Bool(x), Bool(y)) => Ok(Bool(x && y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
                         Or => match (first, second) {
                             (Bool(x), Bool(y)) => Ok(Bool(x || y)),
-                            _ => Err(eval_err(op)),
-                        },
-                        Greater => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x > y)),
-                            (Float(x), Float(y)) => Ok(Bool(x > y)),
-                            _ => Err(eval_err(op)),
-                        },
-                        Less => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x < y)),
-                            (Float(x), Float(y)) => Ok(Bool(x < y)),
-                            _ => Err(eval_err(op)),
+                            (x, y) => Err(type_err(op, args, &[x, y])),
                         },
-                        GreaterEquals => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x >= y)),
-                            (Float(x), Float(y)) => Ok(Bool(x >= y)),
-                            _ => Err(eval_err(op)),
-                        },
-                        LessEquals => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x <= y)),
-                            (Float(x), Float(y)) => Ok(Bool(x <= y)),
-                            _ => Err(eval_err(op)),
-                        },
-                        Equals => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x == y)),
-                            (Float(x), Float(y)) => Ok(Bool(x == y)),
-                            (Bool(x), Bool(y)) => Ok(Bool(x == y)),
-                            (Char(x), Char(y)) => Ok(Bool(x == y)),
-                            _ => Ok(Bool(false)), // default false
-                        },
-                        NotEquals => match (first, second) {
-                            (Int(x), Int(y)) => Ok(Bool(x != y)),
-                            (Float(x), Float(y)) => Ok(Bool(x != y)),
-                            (Bool(x), Bool(y)) => Ok(Bool(x != y)),
-                            (Char(x), Char(y)) => Ok(Bool(x != y)),
-                            _ => Ok(Bool(true)), // default true
+                        Greater | Less | GreaterEquals | LessEquals => match compare(&first, &second) {
+                            Some(ord) => Ok(Bool(match op {
+                                Greater => ord == Ordering::Greater,
+                                Less => ord == Ordering::Less,
+                                GreaterEquals => ord != Ordering::Less,
+                                _ => ord != Ordering::Greater,
+                            })),
+                            // every comparison with NaN is false
+                            None if first.is_number() && second.is_number() => Ok(Bool(false)),
+                            None => Err(type_err(op, args, &[first, second])),
                         },
+                        // values of different types are never equal
+                        Equals => Ok(Bool(equals(&first, &second))),
+                        NotEquals => Ok(Bool(!equals(&first, &second))),
                         _ => panic!("Binary operator is not binary!"),
                     };
+                    if let (Some((first, second)), Ok(Float(res))) = (&operands, &ans) {
+                        if let (Float(x), Float(y)) = (first, second) {
+                            // only newly produced non-finite values are trapped
+                            if !res.is_finite() && x.is_finite() && y.is_finite() {
+                                return Err(Error::from_message(
+                                    ErrorType::RuntimeError,
+                                    "non-finite-result",
+                                    &[("operation", &format!("{:?}", op)), ("left", &x), ("right", &y), ("result", &res)],
+                                    None,
+                                ));
+                            }
+                        }
+                    }
                     return ans;
                 } else {
-                    return Err(Error::new(ErrorType::NameError, "Illegal operation", None));
+                    return Err(Error::from_message(ErrorType::NameError, "illegal-operation", &[], None));
                 }
             }
         }
@@ -167,6 +836,17 @@ impl Interpreter {
 
     // execute a statement
     // returns Ok(obj) if the function should return
+    // prints a value for the print statement on the line
+    fn say(&mut self, ln: usize, res: RickrollObject, buffer: &mut dyn Write) -> Result<(), Error> {
+        let written = write!(buffer, "{}{}", res.format_with(&self.words), self.newline.as_str())
+            .map_err(Error::from_write);
+        self.wrap_check(written, ln)?;
+        if let Some(values) = &mut self.say_values {
+            values.push((ln, res));
+        }
+        return Ok(());
+    }
+
     pub fn execute(
         &mut self,
         statement: &ASTNode,
@@ -174,48 +854,136 @@ impl Interpreter {
         buffer: &mut dyn Write,
         reader: &mut dyn BufRead,
     ) -> Result<Option<RickrollObject>, Error> {
+        self.statements += 1;
+        self.line = statement.get_line();
+        self.record(self.line);
         match statement {
             ASTNode::Say(ln, expr) => {
                 let res = self.wrap_check(self.eval(expr, scope), *ln)?;
-                writeln!(buffer, "{}", res).expect("Error when writing to buffer");
+                self.say(*ln, res, buffer)?;
+            }
+            ASTNode::SayConstants(_, values) => {
+                for (ind, (ln, res)) in values.iter().enumerate() {
+                    // the first value was counted with the statement itself
+                    if ind > 0 {
+                        self.statements += 1;
+                        self.line = *ln;
+                        self.record(*ln);
+                    }
+                    self.say(*ln, res.clone(), buffer)?;
+                }
+            }
+            ASTNode::Explain(ln, expr) => {
+                let res = self.wrap_check(self.eval(expr, scope), *ln)?;
+                let text = format!("{} ({}){}", res.format_with(&self.words), res.type_name(), self.newline.as_str());
+                let written = match self.explain_stream {
+                    ExplainStream::Stdout => buffer.write_all(text.as_bytes()),
+                    ExplainStream::Stderr => io::stderr().write_all(text.as_bytes()),
+                };
+                self.wrap_check(written.map_err(Error::from_write), *ln)?;
             }
             ASTNode::Let(_, name) => {
                 scope.add_var(name.clone());
             }
             ASTNode::Assign(ln, name, expr) => {
                 let res = self.wrap_check(self.eval(expr, scope), *ln)?;
+                if let Some(entry) = self.history.back_mut() {
+                    entry.written = Some((name.clone(), summarize(&res, HISTORY_VALUE_LIMIT)));
+                }
                 scope.set_var(name.clone(), res);
             }
-            ASTNode::While(ln, cond, body) => loop {
-                let res = self.wrap_check(self.eval(cond, scope), *ln)?;
-                match res {
-                    RickrollObject::Bool(x) => {
-                        if !x {
-                            break;
+            ASTNode::Expect(ln, name, type_name) => {
+                let res = scope.get_var(name.clone()).unwrap();
+                if res.type_name() != type_name {
+                    return Err(Error::from_message(
+                        ErrorType::TypeError,
+                        "expectation-failed",
+                        &[("name", &name), ("type", &type_name), ("found", &describe_value(&res))],
+                        Some(*ln),
+                    ));
+                }
+            }
+            ASTNode::While(ln, cond, body) => {
+                // variables and outside reads when the condition was last checked
+                let mut last: Option<(Vec<RickrollObject>, usize)> = None;
+                loop {
+                    let res = self.wrap_check(self.eval(cond, scope), *ln)?;
+                    match res {
+                        RickrollObject::Bool(x) => {
+                            trace!("while loop on line {} {}", ln, if x { "runs again" } else { "ends" });
+                            if !x {
+                                break;
+                            }
+                        }
+                        _ => {
+                            return Err(Error::from_message(
+                                ErrorType::RuntimeError,
+                                "condition-not-boolean",
+                                &[],
+                                Some(*ln),
+                            ))
                         }
                     }
-                    _ => {
-                        return Err(Error::new(
-                            ErrorType::RuntimeError,
-                            "While condition is not boolean",
+                    if self.loop_detection {
+                        let values: Vec<RickrollObject> = scope.iter().map(|(_, value)| value.clone()).collect();
+                        if let Some((prev, reads)) = &last {
+                            if *reads == self.outside_reads && same_values(prev, &values) {
+                                return Err(Error::from_message(
+                                    ErrorType::ResourceError,
+                                    "infinite-loop",
+                                    &[],
+                                    Some(*ln),
+                                ));
+                            }
+                        }
+                        last = Some((values, self.outside_reads));
+                    }
+                    scope.push(Context::new());
+                    for node in body {
+                        let res = self.execute(node, scope, buffer, reader)?;
+                        match res {
+                            Some(obj) => return Ok(Some(obj)),
+                            None => (),
+                        }
+                    }
+                    scope.pop();
+                }
+            }
+            ASTNode::ForEach(ln, name, index, array, body) => {
+                // the loop goes over the array as it was when the loop started
+                let elements = match self.wrap_check(self.eval(array, scope), *ln)? {
+                    RickrollObject::Array(elements) => elements,
+                    other => {
+                        return Err(Error::from_message(
+                            ErrorType::TypeError,
+                            "for-each-not-array",
+                            &[("found", &describe_value(&other))],
                             Some(*ln),
                         ))
                     }
-                }
-                scope.push(Context::new());
-                for node in body {
-                    let res = self.execute(node, scope, buffer, reader)?;
-                    match res {
-                        Some(obj) => return Ok(Some(obj)),
-                        None => (),
+                };
+                for (ind, element) in elements.iter().enumerate() {
+                    scope.push(Context::new());
+                    scope.add_var(name.clone());
+                    scope.set_var(name.clone(), element.clone());
+                    if let Some(index) = index {
+                        scope.add_var(index.clone());
+                        scope.set_var(index.clone(), RickrollObject::Int(ind as i32));
+                    }
+                    for node in body {
+                        let res = self.execute(node, scope, buffer, reader)?;
+                        if let Some(obj) = res {
+                            return Ok(Some(obj));
+                        }
                     }
+                    scope.pop();
                 }
-                scope.pop();
-            },
+            }
             ASTNode::If(ln, cond, body) => {
                 let res = self.wrap_check(self.eval(cond, scope), *ln)?;
                 match res {
                     RickrollObject::Bool(x) => {
+                        trace!("if statement on line {} {}", ln, if x { "runs" } else { "is skipped" });
                         if x {
                             scope.push(Context::new());
                             for node in body {
@@ -229,14 +997,78 @@ impl Interpreter {
                         }
                     }
                     _ => {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::RuntimeError,
-                            "While condition is not boolean",
+                            "condition-not-boolean",
+                            &[],
                             Some(*ln),
                         ))
                     }
                 }
             }
+            ASTNode::Try(_, body, name, filter, handler) => {
+                let depth = scope.len();
+                scope.push(Context::new());
+                let mut caught = None;
+                for node in body {
+                    match self.execute(node, scope, buffer, reader) {
+                        Ok(Some(obj)) => return Ok(Some(obj)),
+                        Ok(None) => (),
+                        // a closed output stream can't be handled
+                        Err(err) if err.kind() == ErrorType::BrokenPipeError => return Err(err),
+                        // errors of other types are left for an outer handler
+                        Err(err) if filter.is_some() && *filter != Some(err.kind()) => return Err(err),
+                        Err(err) => {
+                            caught = Some(err);
+                            break;
+                        }
+                    }
+                }
+                // discard contexts left behind by the failed statement
+                scope.truncate(depth);
+                if let Some(err) = caught {
+                    scope.push(Context::new());
+                    scope.add_var(name.clone());
+                    scope.set_var(name.clone(), to_char_array(&err.message()));
+                    for node in handler {
+                        let res = self.execute(node, scope, buffer, reader)?;
+                        if let Some(obj) = res {
+                            return Ok(Some(obj));
+                        }
+                    }
+                    scope.pop();
+                }
+            }
+            ASTNode::Capture(_, name, body) => {
+                // output inside the block is written to the variable instead
+                let mut captured: Vec<u8> = Vec::new();
+                let mut returned = None;
+                let mut limited = LimitedWriter {
+                    inner: &mut captured,
+                    budget: self.budget.clone(),
+                };
+                scope.push(Context::new());
+                for node in body {
+                    let res = self.execute(node, scope, &mut limited, reader)?;
+                    if res.is_some() {
+                        returned = res;
+                        break;
+                    }
+                }
+                scope.pop();
+                let text = String::from_utf8_lossy(&captured);
+                scope.set_var(name.clone(), to_char_array(&text));
+                if returned.is_some() {
+                    return Ok(returned);
+                }
+            }
+            ASTNode::Run(ln, func, args) if self.is_in_place(func) => {
+                self.wrap_check(self.run_in_place(func, args, scope), *ln)?;
+            }
+            ASTNode::RunAssign(ln, var, func, args) if self.is_in_place(func) => {
+                let res = self.wrap_check(self.run_in_place(func, args, scope), *ln)?;
+                scope.set_var(var.clone(), res);
+            }
             ASTNode::Run(ln, func, args) => {
                 let mut passed: Vec<RickrollObject> = Vec::new();
                 for arg in args {
@@ -245,9 +1077,9 @@ impl Interpreter {
                 let tail = scope.behead();
                 scope.push(Context::new());
                 let res = self.run_function(func.clone(), passed, scope, buffer, reader);
-                self.wrap_check(res, *ln)?;
                 scope.behead();
                 scope.push_all(tail);
+                self.wrap_check(res, *ln)?;
             }
             ASTNode::RunAssign(ln, var, func, args) => {
                 let mut passed: Vec<RickrollObject> = Vec::new();
@@ -257,15 +1089,50 @@ impl Interpreter {
                 let tail = scope.behead();
                 scope.push(Context::new());
                 let res = self.run_function(func.clone(), passed, scope, buffer, reader);
-                let res = self.wrap_check(res, *ln)?;
                 scope.behead();
                 scope.push_all(tail);
+                let res = self.wrap_check(res, *ln)?;
                 scope.set_var(var.clone(), res);
             },
             ASTNode::Return(ln, expr) => {
                 let res = self.wrap_check(self.eval(expr, scope), *ln)?;
                 return Ok(Some(res));
             },
+            ASTNode::Sleep(ln, expr) => {
+                let millis = match self.wrap_check(self.eval(expr, scope), *ln)? {
+                    RickrollObject::Int(x) => x,
+                    other => {
+                        return Err(Error::from_message(
+                            ErrorType::TypeError,
+                            "pause-not-int",
+                            &[("found", &describe_value(&other))],
+                            Some(*ln),
+                        ))
+                    }
+                };
+                if millis < 0 {
+                    return Err(Error::from_message(
+                        ErrorType::ValueError,
+                        "pause-negative",
+                        &[("millis", &millis)],
+                        Some(*ln),
+                    ));
+                }
+                if self.sandbox {
+                    return Err(Error::from_message(
+                        ErrorType::PermissionError,
+                        "pause-sandboxed",
+                        &[],
+                        Some(*ln),
+                    ));
+                }
+                self.clock.sleep(Duration::from_millis(millis as u64));
+            },
+            ASTNode::Raise(ln, err, expr) => {
+                let res = self.wrap_check(self.eval(expr, scope), *ln)?;
+                let desc = from_char_array(&res).unwrap_or_else(|| res.to_string());
+                return Err(Error::new(*err, &desc[..], Some(*ln)));
+            },
             _ => {
                 panic!("Interpreter::execute called with Function");
             },
@@ -273,8 +1140,67 @@ impl Interpreter {
         return Ok(None);
     }
 
-    // executes a function
-    pub fn run_function(
+    // records a call to a privileged function in the audit log
+    fn audit(&mut self, func: &str, passed: &[RickrollObject], allowed: bool) {
+        if allowed && self.audit_mode == AuditMode::Denials {
+            return;
+        }
+        if self.audit_log.len() == AUDIT_LOG_SIZE {
+            self.audit_log.pop_front();
+        }
+        self.audit_log.push_back(AuditEntry {
+            function: String::from(func),
+            args: passed.iter().map(|arg| summarize(arg, HISTORY_VALUE_LIMIT)).collect(),
+            line: self.line,
+            allowed,
+        });
+    }
+
+    // runs Now or Elapsed
+    fn read_clock(&self, func: &str, passed: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+        if !passed.is_empty() {
+            return Err(Error::from_message(
+                ErrorType::RuntimeError,
+                "wrong-argument-count",
+                &[("func", &func)],
+                None,
+            ));
+        }
+        let now = self.clock.now();
+        if func == "Now" {
+            return match i32::try_from(now.as_secs()) {
+                Ok(secs) => Ok(RickrollObject::Int(secs)),
+                Err(_) => Err(Error::from_message(ErrorType::ValueError, "time-too-large", &[], None)),
+            };
+        }
+        // a clock going backwards reads as no time passing
+        return Ok(RickrollObject::Float(now.saturating_sub(self.start).as_secs_f32()));
+    }
+
+    // runs a built-in function, or one of the functions run by the interpreter
+    fn run_builtin(
+        &mut self,
+        func: &str,
+        passed: Vec<RickrollObject>,
+        buffer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+    ) -> Result<RickrollObject, Error> {
+        if CLOCK_FUNCTIONS.contains(&func) {
+            return self.read_clock(func, passed);
+        }
+        self.check_work(func, &passed)?;
+        if RANDOM_FUNCTIONS.contains(&func) {
+            return run_random(func, passed, &mut self.rng);
+        }
+        let res = BUILTIN_FUNCTIONS[func](passed, buffer, reader)?;
+        if let RickrollObject::Array(arr) = &res {
+            self.check_length(func, arr.len())?;
+        }
+        return Ok(res);
+    }
+
+    // executes a function
+    pub fn run_function(
         &mut self,
         func: String,
         passed: Vec<RickrollObject>,
@@ -282,20 +1208,95 @@ impl Interpreter {
         buffer: &mut dyn Write,
         reader: &mut dyn BufRead,
     ) -> Result<RickrollObject, Error> {
-        if !self.functions.contains_key(&func) && BUILTIN_FUNCTIONS.contains_key(&func) {
-            let mut arg_vals = Vec::new();
-            for arg in passed {
-                arg_vals.push(arg.clone());
+        let is_clock = CLOCK_FUNCTIONS.contains(&&func[..]);
+        let is_random = RANDOM_FUNCTIONS.contains(&&func[..]);
+        if !self.functions.contains_key(&func) && (is_clock || is_random || BUILTIN_FUNCTIONS.contains_key(&func)) {
+            let privileged = PRIVILEGED_FUNCTIONS.contains(&&func[..]);
+            // a loop drawing random numbers can keep going with the same variables
+            let outside = privileged || is_random || INPUT_FUNCTIONS.contains(&&func[..]);
+            if outside {
+                self.outside_reads += 1;
+            }
+            if privileged {
+                self.audit(&func, &passed, !self.sandbox);
+            }
+            if self.sandbox && privileged {
+                return Err(Error::from_message(
+                    ErrorType::PermissionError,
+                    "function-sandboxed",
+                    &[("func", &func)],
+                    None,
+                ));
             }
-            return BUILTIN_FUNCTIONS[&func](arg_vals, buffer, reader);
+            if !outside {
+                return self.run_builtin(&func, passed, buffer, reader);
+            }
+            if let Some(replay) = &mut self.replay {
+                return replay.next(&func);
+            }
+            let res = self.run_builtin(&func, passed, buffer, reader)?;
+            if let Some(recording) = &mut self.recording {
+                recording.push(&func, res.clone());
+            }
+            return Ok(res);
         }
+        // the chorus and intro are at depth 1
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        if self.depth > MAX_RECURSION_DEPTH {
+            self.depth -= 1;
+            return Err(Error::from_message(
+                ErrorType::StackOverflowError,
+                "recursion-limit",
+                &[("max", &MAX_RECURSION_DEPTH), ("func", &func)],
+                None,
+            ));
+        }
+        let res = self.run_body(func, passed, scope, buffer, reader);
+        self.depth -= 1;
+        return res;
+    }
+
+    // binds the arguments of a function and runs its statements
+    fn run_body(
+        &mut self,
+        func: String,
+        passed: Vec<RickrollObject>,
+        scope: &mut Scope,
+        buffer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+    ) -> Result<RickrollObject, Error> {
         let function = self.functions.get(&func).unwrap().clone();
         match function {
-            ASTNode::Function(_, _, args, body) => {
+            ASTNode::Function(_, _, args, defaults, body) => {
+                if passed.len() > args.len() {
+                    return Err(Error::from_message(
+                        ErrorType::IllegalArgumentError,
+                        "too-many-arguments",
+                        &[("func", &func)],
+                        None,
+                    ));
+                }
                 // function arguments
-                for (arg, val) in args.iter().zip(passed.iter()) {
-                    scope.add_var(arg.clone());
-                    scope.set_var(arg.clone(), val.clone());
+                for ind in 0..args.len() {
+                    let val = if ind < passed.len() {
+                        passed[ind].clone()
+                    } else {
+                        // missing arguments take their default values
+                        match &defaults[ind] {
+                            Some(expr) => self.eval(expr, scope)?,
+                            None => {
+                                return Err(Error::from_message(
+                                    ErrorType::IllegalArgumentError,
+                                    "missing-argument",
+                                    &[("name", &args[ind]), ("func", &func)],
+                                    None,
+                                ))
+                            }
+                        }
+                    };
+                    scope.add_var(args[ind].clone());
+                    scope.set_var(args[ind].clone(), val);
                 }
                 for node in body {
                     let res = self.execute(&node, scope, buffer, reader)?;
@@ -312,35 +1313,1556 @@ impl Interpreter {
         };
     }
 
-    // execute the program
-    pub fn run(
+    // runs the intro and chorus blocks in the global scope
+    fn run_blocks(
         &mut self,
+        global_scope: &mut Scope,
         buffer: &mut dyn Write,
         reader: &mut dyn BufRead,
     ) -> Result<RickrollObject, Error> {
-        let mut global_scope = Scope::new();
         if self.functions.contains_key(&String::from("[INTRO]")) {
-            self.run_function(String::from("[INTRO]"), Vec::new(), &mut global_scope, buffer, reader)?;
+            self.run_function(String::from("[INTRO]"), Vec::new(), global_scope, buffer, reader)?;
         }
         if self.functions.contains_key(&String::from("[CHORUS]")) {
             global_scope.push(Context::new());
             let val = self.run_function(
                 String::from("[CHORUS]"),
                 Vec::new(),
-                &mut global_scope,
+                global_scope,
                 buffer,
                 reader,
             );
             global_scope.pop();
             return val;
         } else {
-            return Err(Error::new(
+            return Err(Error::from_message(
                 ErrorType::RuntimeError,
-                "No main function found",
+                "no-main",
+                &[],
                 None,
             ));
         }
     }
+
+    // runs the statements of a chorus that only prints in place
+    fn run_say_only(
+        &mut self,
+        scope: &mut Scope,
+        buffer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+    ) -> Result<RickrollObject, Error> {
+        self.max_depth = 1;
+        // the chorus is taken out instead of being cloned like a function call would
+        let chorus = self.functions.remove("[CHORUS]").unwrap();
+        let mut res = Ok(RickrollObject::Undefined);
+        if let ASTNode::Function(_, _, _, _, body) = &chorus {
+            for node in body {
+                if let Err(err) = self.execute(node, scope, buffer, reader) {
+                    res = Err(err);
+                    break;
+                }
+            }
+        }
+        self.functions.insert(String::from("[CHORUS]"), chorus);
+        return res;
+    }
+
+    // execute the program
+    pub fn run(
+        &mut self,
+        buffer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+    ) -> Result<RickrollObject, Error> {
+        self.statements = 0;
+        self.history.clear();
+        self.audit_log.clear();
+        self.warnings.borrow_mut().clear();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.clear();
+        }
+        if let Some(values) = &mut self.say_values {
+            values.clear();
+        }
+        if let Some(recording) = &mut self.recording {
+            *recording = Recording::new();
+        }
+        if let Some(replay) = &mut self.replay {
+            replay.restart();
+        }
+        self.depth = 0;
+        self.max_depth = 0;
+        self.budget = Rc::new(OutputBudget::new(self.output_limit));
+        self.start = self.clock.now();
+        self.rng = Rng::new(self.seed.unwrap_or(self.start.as_nanos() as u64));
+        let mut wrapped;
+        let buffer: &mut dyn Write = match self.wrap_width {
+            Some(width) => {
+                wrapped = WrappingWriter::new(buffer, width);
+                &mut wrapped
+            }
+            None => buffer,
+        };
+        let mut held: Vec<u8> = Vec::new();
+        let mut limited = LimitedWriter {
+            inner: if self.atomic_output { &mut held } else { &mut *buffer },
+            budget: self.budget.clone(),
+        };
+        let mut global_scope = Scope::new();
+        let mut val = if self.say_only {
+            self.run_say_only(&mut global_scope, &mut limited, reader)
+        } else {
+            self.run_blocks(&mut global_scope, &mut limited, reader)
+        };
+        if self.atomic_output && val.is_ok() {
+            if let Err(err) = buffer.write_all(&held) {
+                val = Err(Error::from_write(err));
+            }
+        }
+        // the end of a line that is still held back
+        if self.wrap_width.is_some() {
+            if let Err(err) = buffer.flush() {
+                val = val.and(Err(Error::from_write(err)));
+            }
+        }
+        // keep the global variables around, even if the program failed
+        self.globals = global_scope.head().clone();
+        // stop quietly if the output stream was closed
+        return match val {
+            Err(err) if err.kind() == ErrorType::BrokenPipeError => Ok(RickrollObject::Undefined),
+            Err(err) if self.history_size > 0 => {
+                Err(err.with_history(self.history.iter().map(|entry| entry.to_string()).collect()))
+            }
+            _ => val,
+        };
+    }
+
+    // executes the program, returning statistics about the run even if it fails
+    pub fn run_with_stats(
+        &mut self,
+        buffer: &mut dyn Write,
+        reader: &mut dyn BufRead,
+    ) -> Result<(RickrollObject, RunStats), Box<(Error, RunStats)>> {
+        let start = Instant::now();
+        let res = self.run(buffer, reader);
+        let stats = RunStats {
+            statements: self.statements,
+            output_bytes: self.budget.used.get(),
+            output_truncated: self.budget.notified.get(),
+            max_depth: self.max_depth,
+            elapsed: start.elapsed(),
+        };
+        return match res {
+            Ok(val) => Ok((val, stats)),
+            Err(err) => Err(Box::new((err, stats))),
+        };
+    }
+
+    // gets the number of statements executed by the last run
+    pub fn statements_executed(&self) -> usize {
+        self.statements
+    }
+
+    // gets the deepest function call depth reached by the last run
+    // the chorus and intro are at depth 1
+    pub fn max_depth_reached(&self) -> usize {
+        self.max_depth
+    }
+
+    // gets the lines of the statements executed at least once by the last run
+    // returns None if coverage isn't being recorded
+    pub fn coverage(&self) -> Option<&BTreeSet<usize>> {
+        self.coverage.as_ref()
+    }
+
+    // gets the values printed by Say during the last run with their lines, in the order printed
+    // values written to a capture are included, and returns None if values aren't being collected
+    pub fn say_values(&self) -> Option<&Vec<(usize, RickrollObject)>> {
+        self.say_values.as_ref()
+    }
+
+    // gets the calls to privileged functions made by the last run, oldest first
+    pub fn audit_log(&self) -> &VecDeque<AuditEntry> {
+        &self.audit_log
+    }
+
+    // gets the global variables as they were at the end of the last run
+    pub fn globals(&self) -> &Context {
+        &self.globals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::optimizer::Optimizer;
+    use crate::testing::*;
+
+    use std::io::Cursor;
+
+    fn interpreter(src: &str) -> Interpreter {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut parsed = Parser::new(tokens).parse().unwrap();
+        Optimizer::new().run(&mut parsed);
+        return Interpreter::new(parsed);
+    }
+
+    #[test]
+    fn in_place_functions() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a ARRAY
+Never gonna let x down
+Never gonna give x 1
+Never gonna run Push and desert a, x
+Never gonna give x 2
+Never gonna run Push and desert a, x
+Never gonna give x 0
+Never gonna run Unshift and desert a, x
+Never gonna say a
+(Ooh give you x) Never gonna run Pop and desert a
+Never gonna say x
+(Ooh give you x) Never gonna run Shift and desert a
+Never gonna say x
+Never gonna say a
+";
+        run_and_expect(src, "", "[0, 1, 2]\n2\n0\n[1]\n");
+    }
+
+    #[test]
+    fn in_place_functions_leave_copies_alone() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a ARRAY
+Never gonna let b down
+Never gonna give b a
+Never gonna let x down
+Never gonna give x 1
+Never gonna run Push and desert a, x
+Never gonna say a
+Never gonna say b
+";
+        run_and_expect(src, "", "[1]\n[]\n");
+    }
+
+    #[test]
+    fn in_place_functions_on_empty_arrays() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a ARRAY
+Never gonna let x down
+(Ooh give you x) Never gonna run TryPop and desert a
+Never gonna say x
+(Ooh give you x) Never gonna run TryShift and desert a
+Never gonna say x
+";
+        run_and_expect(src, "", "UNDEFINED\nUNDEFINED\n");
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a ARRAY\nNever gonna run Pop and desert a\n";
+        assert!(run_source(src, "").is_err());
+    }
+
+    #[test]
+    fn in_place_function_on_undeclared_variable() {
+        let src = "[Chorus]\nNever gonna run Pop and desert a\n";
+        let err = run_source(src, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.root().message_id(), Some("missing-variable"));
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a ARRAY\nNever gonna run Push and desert a, x\n";
+        assert_eq!(run_source(src, "").unwrap_err().kind(), ErrorType::NameError);
+    }
+
+    // pushes 1 onto a three times, then prints a
+    const PUSH_THREE: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a ARRAY
+Never gonna let x down
+Never gonna give x 1
+Never gonna run Push and desert a, x
+Never gonna run Push and desert a, x
+Never gonna run Push and desert a, x
+Never gonna say a
+";
+
+    fn run_with_max_length(src: &str, max: usize) -> Result<String, Error> {
+        let mut interpreter = interpreter(src);
+        interpreter.set_max_array_length(Some(max));
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new()))?;
+        return Ok(String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn max_array_length() {
+        assert_eq!(run_with_max_length(PUSH_THREE, 3).unwrap(), "[1, 1, 1]\n");
+        let err = run_with_max_length(PUSH_THREE, 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(err.root().message_id(), Some("array-length-limit"));
+        assert_eq!(
+            err.to_string(),
+            "Resource Limit: Push would make an array of 3 elements, more than the limit of 2\nTraceback on line 8"
+        );
+        // arrays returned by built-in functions are checked too
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna let x down
+Never gonna give x 0
+Never gonna let n down
+Never gonna give n 5
+(Ooh give you a) Never gonna run ArrayRange and desert x, n
+";
+        assert!(run_with_max_length(src, 5).is_ok());
+        assert_eq!(run_with_max_length(src, 4).unwrap_err().kind(), ErrorType::ResourceError);
+    }
+
+    #[test]
+    fn max_array_length_when_shrinking() {
+        let src = "\
+[Chorus]
+Never gonna let s down
+Never gonna give s \"abcdef\"
+Never gonna run Pop and desert s
+Never gonna run Shift and desert s
+Never gonna say s
+";
+        assert_eq!(run_with_max_length(src, 3).unwrap(), "[b, c, d, e]\n");
+    }
+
+    // repeats a three element array the number of times it reads
+    const REPEAT: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna let n down
+Never gonna give a \"abc\"
+(Ooh give you n) Never gonna run ReadToken and desert you
+(Ooh give you a) Never gonna run ArrayRepeat and desert a, n
+(Ooh give you n) Never gonna run ArrayLength and desert a
+Never gonna say n
+";
+
+    fn run_with_max_work(src: &str, input: &str, max: Option<usize>) -> Result<String, Error> {
+        let mut interpreter = interpreter(src);
+        interpreter.set_max_statement_work(max);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(input.as_bytes()))?;
+        return Ok(String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn max_statement_work() {
+        assert_eq!(run_with_max_work(REPEAT, "100", Some(1000)).unwrap(), "300\n");
+        // exactly at the limit
+        assert_eq!(run_with_max_work(REPEAT, "333", Some(999)).unwrap(), "999\n");
+        let err = run_with_max_work(REPEAT, "334", Some(999)).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(
+            err.to_string(),
+            "Resource Limit: ArrayRepeat would make or go over 1002 elements, more than the limit of 999 for a single statement\nTraceback on line 6"
+        );
+        // a call far too big to make fails without allocating it
+        let err = run_with_max_work(REPEAT, "2000000000", Some(1000)).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(run_with_max_work(REPEAT, "400", None).unwrap(), "1200\n");
+    }
+
+    // divides the two numbers it reads, then 7 by 2 as constants
+    const DIVIDE_READ: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna let b down
+(Ooh give you a) Never gonna run ReadToken and desert you
+(Ooh give you b) Never gonna run ReadToken and desert you
+Never gonna say a / b
+Never gonna say 7 / 2
+";
+
+    fn run_with_division_warnings(src: &str, input: &str, warn: bool) -> (String, Vec<String>) {
+        let mut interpreter = interpreter(src);
+        interpreter.set_division_warnings(warn);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(input.as_bytes())).unwrap();
+        return (String::from_utf8(output).unwrap(), interpreter.warnings());
+    }
+
+    #[test]
+    fn division_warnings() {
+        let (output, warnings) = run_with_division_warnings(DIVIDE_READ, "7 2", true);
+        assert_eq!(output, "3\n3\n");
+        assert_eq!(
+            warnings,
+            [
+                "Line 6: 7 / 2 gives 3, dropping the remainder 1",
+                "Line 7: 7 / 2 gives 3, dropping the remainder 1",
+            ]
+        );
+        // the constant division is left for the run to warn about
+        let (output, warnings) = run_with_division_warnings(DIVIDE_READ, "8 2", true);
+        assert_eq!(output, "4\n3\n");
+        assert_eq!(warnings, ["Line 7: 7 / 2 gives 3, dropping the remainder 1"]);
+        let (output, warnings) = run_with_division_warnings(DIVIDE_READ, "7 2", false);
+        assert_eq!(output, "3\n3\n");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn division_warnings_of_exact_division() {
+        let src = "\
+[Chorus]
+Never gonna say 8 / 2
+Never gonna say 7.0 / 2.0
+";
+        let (output, warnings) = run_with_division_warnings(src, "", true);
+        assert_eq!(output, "4\n3.5\n");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn try_block() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 5
+Never gonna make you cry
+    Never gonna say a / 0
+    Never gonna say a
+Never gonna tell a lie and hurt err
+    Never gonna say err
+Gotta make you understand
+Never gonna make you cry
+    Never gonna say a
+Never gonna tell a lie and hurt err
+    Never gonna say err
+Gotta make you understand
+";
+        let message = "Runtime Error: Division by zero";
+        let chars: Vec<String> = message.chars().map(String::from).collect();
+        run_and_expect(src, "", &format!("[{}]\n5\n", chars.join(", ")));
+    }
+
+    // raises an error if the number it reads is negative
+    const RAISING: &str = "\
+[Verse check]
+(Ooh give you n)
+Inside we both know n < 0
+    You wouldn't get this from any other guy \"n is negative\"
+Your heart's been aching but you're too shy to say it
+Never gonna say n
+[Chorus]
+Never gonna let x down
+(Ooh give you x) Never gonna run ReadToken and desert you
+Never gonna run check and desert x
+Never gonna say 0
+";
+
+    #[test]
+    fn uncaught_raise() {
+        assert_eq!(run_source(RAISING, "5").unwrap(), "5\n0\n");
+        let err = run_source(RAISING, "-5").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::UserError);
+        assert_eq!(err.root().message(), "User Error: n is negative");
+        // the error is on the line of the raise, called from the chorus
+        assert_eq!(err.to_string(), "User Error on line 4: n is negative\nTraceback on line 10");
+        // values that aren't strings are written as text
+        let err = run_source("[Chorus]\nYou wouldn't get this from any other ValueError 6 * 7\n", "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ValueError);
+        assert_eq!(err.to_string(), "Value Error on line 2: 42");
+    }
+
+    #[test]
+    fn caught_raise() {
+        let src = "\
+[Chorus]
+Never gonna make you cry
+    You wouldn't get this from any other guy \"first\"
+    Never gonna say 1
+Never gonna tell a UserError and hurt err
+    Never gonna say err
+Gotta make you understand
+Never gonna make you cry
+    Never gonna make you cry
+        You wouldn't get this from any other ValueError \"second\"
+    Never gonna tell a UserError and hurt err
+        Never gonna say 2
+    Gotta make you understand
+Never gonna tell a ValueError and hurt err
+    Never gonna say err
+Gotta make you understand
+";
+        // a handler for another type of error lets it through to the outer one
+        let first: Vec<String> = "User Error: first".chars().map(String::from).collect();
+        let second: Vec<String> = "Value Error: second".chars().map(String::from).collect();
+        run_and_expect(src, "", &format!("[{}]\n[{}]\n", first.join(", "), second.join(", ")));
+    }
+
+    #[test]
+    fn raise_unknown_type() {
+        let err = run_source("[Chorus]\nYou wouldn't get this from any other Oops \"x\"\n", "").unwrap_err();
+        assert_eq!(err.root().message_id(), Some("unknown-error-type"));
+        assert_eq!(err.kind(), ErrorType::NameError);
+    }
+
+    const FILTERED: &str = "\
+[Chorus]
+Never gonna make you cry
+    Never gonna say 1 / 0
+Never gonna tell a RuntimeError and hurt err
+    Never gonna say 1
+Gotta make you understand
+Never gonna say 2
+";
+
+    #[test]
+    fn try_block_with_error_type() {
+        run_and_expect(FILTERED, "", "1\n2\n");
+        // an error of another type is raised past the handler, and the rest of the program doesn't run
+        let mut interpreter = interpreter(&FILTERED.replace("RuntimeError", "TypeError"));
+        let mut output: Vec<u8> = Vec::new();
+        let err = interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::RuntimeError);
+        assert!(output.is_empty());
+        // and can be caught by an outer handler
+        let src = format!(
+            "[Chorus]\nNever gonna make you cry\n{}Never gonna tell a lie and hurt err\nNever gonna say 3\nGotta make you understand\n",
+            FILTERED.replace("RuntimeError", "TypeError").replace("[Chorus]\n", "")
+        );
+        run_and_expect(&src, "", "3\n");
+    }
+
+    fn run_detecting_loops(interpreter: &mut Interpreter, input: &str) -> Result<String, Error> {
+        interpreter.set_loop_detection(true);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(input.as_bytes()))?;
+        return Ok(String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn loop_detection() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 0
+Inside we both know a < 5
+    Never gonna say a
+We know the game and we're gonna play it
+";
+        let err = run_detecting_loops(&mut interpreter(src), "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(err.root().message_id(), Some("infinite-loop"));
+        assert!(err.to_string().contains("line 4"), "{}", err);
+        // a loop changing a variable every time is left alone
+        let src = src.replace("Never gonna say a", "Never gonna give a a + 1");
+        assert_eq!(run_detecting_loops(&mut interpreter(&src), "").unwrap(), "");
+    }
+
+    #[test]
+    fn loop_detection_with_input() {
+        // the variables are the same until the 2 is read, but what is read can change
+        let src = "\
+[Chorus]
+Never gonna let stop down
+Never gonna give stop FALSE
+Inside we both know stop == FALSE
+    Never gonna let t down
+    (Ooh give you t) Never gonna run ReadToken and desert you
+    Never gonna say t
+    Never gonna give stop t == 2
+We know the game and we're gonna play it
+";
+        assert_eq!(run_detecting_loops(&mut interpreter(src), "1 1 1 2").unwrap(), "1\n1\n1\n2\n");
+    }
+
+    // clock moving on by a second every time it is read
+    #[derive(Debug)]
+    struct TickingClock(std::cell::Cell<u64>);
+
+    impl Clock for TickingClock {
+        fn now(&self) -> Duration {
+            self.0.set(self.0.get() + 1);
+            return Duration::from_secs(self.0.get());
+        }
+
+        fn sleep(&self, _duration: Duration) {}
+    }
+
+    // clock moving on by a quarter of a second each time it is read, and by as long as it sleeps
+    #[derive(Debug)]
+    struct MockClock(std::cell::Cell<Duration>);
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            self.0.set(self.0.get() + Duration::from_millis(250));
+            return self.0.get();
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+    }
+
+    const READ_CLOCK: &str = "\
+[Chorus]
+Never gonna let t down
+(Ooh give you t) Never gonna run Now and desert you
+Never gonna say t
+(Ooh give you t) Never gonna run Elapsed and desert you
+Never gonna say t
+(Ooh give you t) Never gonna run Elapsed and desert you
+Never gonna say t
+";
+
+    #[test]
+    fn mocked_clock() {
+        let mut interpreter = interpreter(READ_CLOCK);
+        interpreter.set_clock(Box::new(MockClock(std::cell::Cell::new(Duration::from_secs(1_700_000_000)))));
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        // the run starts at the first reading
+        assert_eq!(String::from_utf8(output).unwrap(), "1700000000\n0.5\n0.75\n");
+    }
+
+    #[test]
+    fn sandbox_forbids_clock() {
+        let mut interpreter = interpreter(READ_CLOCK);
+        interpreter.set_sandbox(true);
+        let err = interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::PermissionError);
+        assert!(err.to_string().starts_with("Permission Denied: Now is not available in sandbox mode\n"));
+    }
+
+    fn run_pause(millis: &str, sandbox: bool) -> Result<String, Error> {
+        let src = format!(
+            "[Chorus]\nNever gonna let t down\n(Ooh give you t) Never gonna run Elapsed and desert you\nNever gonna say t\nWe've been waiting for {}\n(Ooh give you t) Never gonna run Elapsed and desert you\nNever gonna say t\n",
+            millis
+        );
+        let mut interpreter = interpreter(&src);
+        interpreter.set_clock(Box::new(MockClock(std::cell::Cell::new(Duration::ZERO))));
+        interpreter.set_sandbox(sandbox);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new()))?;
+        return Ok(String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn pause_with_mocked_clock() {
+        let start = std::time::Instant::now();
+        // a pause of ten minutes, on top of the quarter of a second each reading takes
+        assert_eq!(run_pause("600000", false).unwrap(), "0.25\n600.5\n");
+        assert_eq!(run_pause("1000 + 500", false).unwrap(), "0.25\n2\n");
+        assert_eq!(run_pause("0", false).unwrap(), "0.25\n0.5\n");
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn pause_errors() {
+        let err = run_pause("~1", false).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ValueError);
+        assert!(err.to_string().contains("Pause must not be negative, got -1 milliseconds"), "{}", err);
+        let err = run_pause("1.5", false).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert!(err.to_string().contains("Pause needs an INT number of milliseconds but found FLOAT 1.5"), "{}", err);
+        let mut interpreter = interpreter("[Chorus]\nWe've been waiting for 10\n");
+        interpreter.set_sandbox(true);
+        let err = interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::PermissionError);
+        assert_eq!(err.to_string(), "Permission Denied on line 2: Pausing is not available in sandbox mode");
+    }
+
+    #[test]
+    fn loop_detection_with_clock() {
+        let src = "\
+[Verse wait]
+(Ooh give you until)
+Inside we both know TRUE
+    Never gonna let t down
+    (Ooh give you t) Never gonna run Now and desert you
+    Inside we both know t >= until, never gonna give (give you t)
+We know the game and we're gonna play it
+
+[Chorus]
+Never gonna let until down
+Never gonna give until 3
+Never gonna run wait and desert until
+Never gonna say until
+";
+        let mut interpreter = interpreter(src);
+        interpreter.set_clock(Box::new(TickingClock(std::cell::Cell::new(0))));
+        assert_eq!(run_detecting_loops(&mut interpreter, "").unwrap(), "3\n");
+    }
+
+    #[test]
+    fn for_each() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 10
+Never gonna let b down
+Never gonna give b 20
+Never gonna let arr down
+(Ooh give you arr) Never gonna run ArrayOf and desert a, b
+We've known each x, i in arr for so long
+    Never gonna say i
+    Never gonna say x
+We know the game and we're gonna play it
+";
+        run_and_expect(src, "", "0\n10\n1\n20\n");
+    }
+
+    #[test]
+    fn for_each_goes_over_a_snapshot() {
+        // pushing inside the loop changes the array, but not the elements the loop sees
+        let src = "\
+[Chorus]
+Never gonna let arr down
+Never gonna give arr \"ab\"
+Never gonna let c down
+Never gonna give c 'z'
+We've known each x in arr for so long
+    Never gonna run Push and desert arr, c
+    Never gonna say x
+We know the game and we're gonna play it
+Never gonna say arr
+";
+        run_and_expect(src, "", "a\nb\n[a, b, z, z]\n");
+    }
+
+    #[test]
+    fn nested_for_each() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a \"12\"
+Never gonna let b down
+Never gonna give b \"34\"
+Never gonna let matrix down
+(Ooh give you matrix) Never gonna run ArrayOf and desert a, b
+We've known each row, i in matrix for so long
+    We've known each cell, j in row for so long
+        Never gonna say i + j
+        Never gonna say cell
+    We know the game and we're gonna play it
+We know the game and we're gonna play it
+";
+        run_and_expect(src, "", "0\n1\n1\n2\n1\n3\n2\n4\n");
+    }
+
+    #[test]
+    fn for_each_over_non_array() {
+        let src = "[Chorus]\nWe've known each x in 5 for so long\nNever gonna say x\nWe know the game and we're gonna play it\n";
+        let err = run_source(src, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.root().message_id(), Some("for-each-not-array"));
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
+    const COUNTDOWN: &str = "\
+[Verse down]
+(Ooh give you n)
+Never gonna say n
+Inside we both know n > 0
+    Never gonna give n n - 1
+    Never gonna run down and desert n
+Your heart's been aching but you're too shy to say it
+
+[Chorus]
+Never gonna let n down
+Never gonna give n 3
+Never gonna run down and desert n
+";
+
+    #[test]
+    fn run_with_stats() {
+        let mut interpreter = interpreter(COUNTDOWN);
+        let mut output: Vec<u8> = Vec::new();
+        let (_, stats) = interpreter.run_with_stats(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "3\n2\n1\n0\n");
+        assert_eq!(stats.output_bytes, 8);
+        assert!(!stats.output_truncated);
+        // the chorus and four calls of down
+        assert_eq!(stats.max_depth, 5);
+        assert_eq!(stats.statements, interpreter.statements);
+        assert!(stats.statements > 0);
+    }
+
+    #[test]
+    fn run_with_stats_on_failure() {
+        let mut interpreter = interpreter(&COUNTDOWN.replace("n - 1", "n / 0"));
+        let mut output: Vec<u8> = Vec::new();
+        let failed = interpreter.run_with_stats(&mut output, &mut Cursor::new(Vec::new())).unwrap_err();
+        let (err, stats) = *failed;
+        assert_eq!(err.kind(), ErrorType::RuntimeError);
+        assert_eq!(stats.output_bytes, 2);
+        assert_eq!(stats.max_depth, 2);
+    }
+
+    // runs the countdown without printing, on a thread with the stack programs get from the command line
+    fn count_down(n: usize) -> Result<RunStats, Error> {
+        let src = COUNTDOWN.replace("Never gonna say n\n", "").replace("give n 3", &format!("give n {}", n));
+        let runner = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            let mut output: Vec<u8> = Vec::new();
+            return match interpreter(&src).run_with_stats(&mut output, &mut Cursor::new(Vec::new())) {
+                Ok((_, stats)) => Ok(stats),
+                Err(failed) => Err(failed.0),
+            };
+        });
+        return runner.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn recursion_limit() {
+        // the chorus and the first call are two levels, and every call for n > 0 adds one
+        let stats = count_down(MAX_RECURSION_DEPTH - 2).unwrap();
+        assert_eq!(stats.max_depth, MAX_RECURSION_DEPTH);
+        let err = count_down(MAX_RECURSION_DEPTH - 1).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::StackOverflowError);
+        assert_eq!(err.root().message_id(), Some("recursion-limit"));
+    }
+
+    #[test]
+    fn say_values() {
+        let mut interpreter = interpreter(&COUNTDOWN.replace("Never gonna say n", "Never gonna say n * 10"));
+        assert!(interpreter.say_values().is_none());
+        interpreter.collect_say_values(true);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        // the values are printed as well
+        assert_eq!(String::from_utf8(output).unwrap(), "30\n20\n10\n0\n");
+        let values: Vec<String> = interpreter
+            .say_values()
+            .unwrap()
+            .iter()
+            .map(|(ln, value)| format!("{} {:?}", ln, value))
+            .collect();
+        assert_eq!(values, ["3 Int(30)", "3 Int(20)", "3 Int(10)", "3 Int(0)"]);
+
+        // every run starts over
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(interpreter.say_values().unwrap().len(), 4);
+
+        interpreter.collect_say_values(false);
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        assert!(interpreter.say_values().is_none());
+    }
+
+    #[test]
+    fn say_values_of_say_only_program() {
+        let mut interpreter = interpreter("[Chorus]\nNever gonna say 1 + 1\nNever gonna say 'a'\n");
+        interpreter.collect_say_values(true);
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        let values = interpreter.say_values().unwrap();
+        assert_eq!(format!("{:?}", values), "[(2, Int(2)), (3, Char('a'))]");
+    }
+
+    #[test]
+    fn run_with_stats_truncated() {
+        let mut interpreter = interpreter(COUNTDOWN);
+        interpreter.set_output_limit(OutputLimit::TruncateAndContinue(4));
+        let mut output: Vec<u8> = Vec::new();
+        let (_, stats) = interpreter.run_with_stats(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("3\n2\n\n"));
+        assert!(stats.output_truncated);
+    }
+
+    const COUNT_TO_TEN: &str = "\
+[Chorus]
+Never gonna let i down
+Never gonna give i 0
+Inside we both know i < 10
+    Never gonna say i
+    Never gonna give i i + 1
+We know the game and we're gonna play it
+Never gonna say i
+";
+
+    fn run_with_limit(limit: OutputLimit) -> (Result<RickrollObject, Error>, String, Interpreter) {
+        let mut interpreter = interpreter(COUNT_TO_TEN);
+        interpreter.set_output_limit(limit);
+        interpreter.set_coverage(true);
+        let mut output: Vec<u8> = Vec::new();
+        let res = interpreter.run(&mut output, &mut Cursor::new(Vec::new()));
+        return (res, String::from_utf8(output).unwrap(), interpreter);
+    }
+
+    #[test]
+    fn unlimited_output() {
+        let (res, output, _) = run_with_limit(OutputLimit::Unlimited);
+        assert!(res.is_ok());
+        assert_eq!(output, "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+    }
+
+    #[test]
+    fn truncated_output() {
+        let (res, output, interpreter) = run_with_limit(OutputLimit::TruncateAndContinue(7));
+        assert!(res.is_ok());
+        // a single notice, however much more is printed
+        assert_eq!(output, "0\n1\n2\n3\n[output truncated after 7 bytes]\n");
+        // the program still ran to the end
+        assert!(interpreter.coverage().unwrap().contains(&8));
+    }
+
+    #[test]
+    fn output_limit_error() {
+        let (res, output, interpreter) = run_with_limit(OutputLimit::Error(7));
+        let err = res.unwrap_err();
+        assert_eq!(err.root().message_id(), Some("write-failed"));
+        assert!(err.to_string().starts_with("Runtime Error: Failed to write output: output limit of 7 bytes exceeded\n"));
+        assert_eq!(output, "0\n1\n2\n3\n[output truncated after 7 bytes]\n");
+        assert!(!interpreter.coverage().unwrap().contains(&8));
+    }
+
+    // prints two lines, then divides by the number it reads
+    const DIVIDE: &str = "\
+[Chorus]
+Never gonna let n down
+Never gonna say 1
+Never gonna say 2
+(Ooh give you n) Never gonna run ReadToken and desert you
+Never gonna say 6 / n
+";
+
+    fn run_atomic(atomic: bool, input: &str) -> (Result<RickrollObject, Error>, String) {
+        let mut interpreter = interpreter(DIVIDE);
+        interpreter.set_atomic_output(atomic);
+        let mut output: Vec<u8> = Vec::new();
+        let res = interpreter.run(&mut output, &mut Cursor::new(input.as_bytes()));
+        return (res, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn atomic_output() {
+        assert_eq!(run_atomic(false, "3").1, "1\n2\n2\n");
+        let (res, output) = run_atomic(true, "3");
+        assert!(res.is_ok());
+        assert_eq!(output, "1\n2\n2\n");
+    }
+
+    #[test]
+    fn atomic_output_of_failed_run() {
+        let (res, output) = run_atomic(false, "0");
+        assert_eq!(res.unwrap_err().to_string(), "Runtime Error: Division by zero\nTraceback on line 6");
+        assert_eq!(output, "1\n2\n");
+        let (res, output) = run_atomic(true, "0");
+        assert_eq!(res.unwrap_err().to_string(), "Runtime Error: Division by zero\nTraceback on line 6");
+        assert_eq!(output, "");
+    }
+
+    // output stream failing with the given kind of error after taking some bytes
+    struct FailingWriter {
+        written: Vec<u8>,
+        left: usize,
+        kind: io::ErrorKind,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.left {
+                return Err(io::Error::new(self.kind, "closed"));
+            }
+            self.left -= buf.len();
+            self.written.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn failing_writer(kind: io::ErrorKind) -> FailingWriter {
+        return FailingWriter {
+            written: Vec::new(),
+            left: 4,
+            kind,
+        };
+    }
+
+    fn run_failing(src: &str, kind: io::ErrorKind) -> (Result<RickrollObject, Error>, String) {
+        let mut writer = failing_writer(kind);
+        let res = interpreter(src).run(&mut writer, &mut Cursor::new(Vec::new()));
+        return (res, String::from_utf8(writer.written).unwrap());
+    }
+
+    #[test]
+    fn broken_pipe_stops_quietly() {
+        let (res, output) = run_failing(COUNTDOWN, io::ErrorKind::BrokenPipe);
+        assert!(res.is_ok());
+        assert_eq!(output, "3\n2\n");
+    }
+
+    #[test]
+    fn broken_pipe_not_caught() {
+        let src = "\
+[Chorus]
+Never gonna let n down
+Never gonna give n 0
+Never gonna make you cry
+    Never gonna say n
+    Never gonna say n + 1
+    Never gonna say n + 2
+Never gonna tell a lie and hurt err
+    Never gonna give n 99
+Gotta make you understand
+";
+        let mut interpreter = interpreter(src);
+        interpreter.set_coverage(true);
+        let mut writer = failing_writer(io::ErrorKind::BrokenPipe);
+        assert!(interpreter.run(&mut writer, &mut Cursor::new(Vec::new())).is_ok());
+        assert_eq!(String::from_utf8(writer.written).unwrap(), "0\n1\n");
+        // the catch block never ran
+        assert!(!interpreter.coverage().unwrap().contains(&9));
+    }
+
+    #[test]
+    fn write_failure() {
+        let (res, output) = run_failing(COUNTDOWN, io::ErrorKind::Other);
+        let err = res.unwrap_err();
+        assert_eq!(err.kind(), ErrorType::RuntimeError);
+        assert_eq!(err.root().message_id(), Some("write-failed"));
+        assert_eq!(err.root().params(), vec![(String::from("reason"), String::from("closed"))]);
+        assert!(err.to_string().starts_with("Runtime Error: Failed to write output: closed\nTraceback on line 3\n"));
+        assert_eq!(output, "3\n2\n");
+    }
+
+    #[test]
+    fn type_assertion_passes() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 3
+You know the rules and a is INT
+Never gonna give a \"hi\"
+You know the rules and a is ARRAY
+Never gonna say a
+";
+        run_and_expect(src, "", "[h, i]\n");
+    }
+
+    #[test]
+    fn type_assertion_fails() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 3
+You know the rules and a is FLOAT
+Never gonna say a
+";
+        let err = run_source(src, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.to_string(), "Type Error on line 4: Expected a to be FLOAT but found INT 3");
+    }
+
+    // the message of the error a program stops with
+    fn error_message(src: &str) -> String {
+        return run_source(src, "").unwrap_err().to_string();
+    }
+
+    // checks an index read from the input before using it in an array of three ones
+    const GUARDED_INDEX: &str = "\
+[Chorus]
+Never gonna let one down
+Never gonna give one 1
+Never gonna let a down
+(Ooh give you a) Never gonna run ArrayOf and desert one, one, one
+Never gonna let i down
+(Ooh give you i) Never gonna run ReadToken and desert you
+Never gonna say i < 3 && a : i > 0
+Never gonna say i >= 3 || a : i > 0
+Never gonna say !(i < 3) || a : i == 1
+Never gonna say one == 1 && (i < 3 && 1 / (3 - i) > 0)
+Never gonna say i < 5 && a : i > 0
+";
+
+    #[test]
+    fn short_circuit() {
+        // the right side of every operator is used
+        run_and_expect(GUARDED_INDEX, "1", "TRUE\nTRUE\nTRUE\nFALSE\nTRUE\n");
+        // without the last line, which indexes past the end for larger indices
+        let safe = GUARDED_INDEX.replace("Never gonna say i < 5 && a : i > 0\n", "");
+        // the right sides would index past the end or divide by zero, but are skipped
+        run_and_expect(&safe, "3", "FALSE\nTRUE\nTRUE\nFALSE\n");
+    }
+
+    #[test]
+    fn short_circuit_only_when_decided() {
+        let err = run_source(GUARDED_INDEX, "3").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IndexOutOfBoundsError);
+        assert_eq!(
+            err.to_string(),
+            "Index Out of Bounds: Index 3 is out of bounds for an array of length 3\nTraceback on line 12"
+        );
+        // a left side that isn't a BOOL doesn't decide anything
+        let err = run_source("[Chorus]\nNever gonna let a down\nNever gonna give a 1\nNever gonna say a && 1 / 0\n", "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::RuntimeError);
+    }
+
+    #[test]
+    fn operator_type_errors() {
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a TRUE\nNever gonna say a + 1\n";
+        assert_eq!(
+            error_message(src),
+            "Type Error: Cannot apply '+' to BOOL TRUE (variable a) and INT 1 in a + 1\nTraceback on line 4"
+        );
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a \"hi\"
+Never gonna let b down
+Never gonna give b 2
+Never gonna say a * b
+";
+        assert_eq!(
+            error_message(src),
+            "Type Error: Cannot apply '*' to ARRAY \"hi\" (variable a) and INT 2 (variable b) in a * b\nTraceback on line 6"
+        );
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a 2\nNever gonna say !a\n";
+        assert_eq!(error_message(src), "Type Error: Cannot apply '!' to INT 2 (variable a) in !a\nTraceback on line 4");
+        // without any variables
+        assert_eq!(
+            error_message("[Chorus]\nNever gonna say 1 + TRUE\n"),
+            "Type Error: Cannot apply '+' to INT 1 and BOOL TRUE in 1 + TRUE\nTraceback on line 2"
+        );
+    }
+
+    #[test]
+    fn builtin_argument_type_errors() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 2
+Never gonna let b down
+(Ooh give you b) Never gonna run ArrayReverse and desert a
+";
+        assert_eq!(
+            error_message(src),
+            "Type Error: ArrayReverse expects ARRAY as argument 1, got INT 2\nTraceback on line 5"
+        );
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Never gonna let b down
+Never gonna give b 1.5
+Never gonna let c down
+(Ooh give you c) Never gonna run ArrayRange and desert a, b
+";
+        assert_eq!(
+            error_message(src),
+            "Type Error: ArrayRange expects INT as argument 2, got FLOAT 1.5\nTraceback on line 7"
+        );
+    }
+
+    #[test]
+    fn coverage_skips_untaken_branch() {
+        let src = "\
+[Verse unused]
+(Ooh give you x)
+Never gonna say x
+
+[Chorus]
+Never gonna let a down
+(Ooh give you a) Never gonna run ReadToken and desert you
+Inside we both know a > 5
+    Never gonna say a
+Your heart's been aching but you're too shy to say it
+Never gonna say a + 1
+";
+        let mut interpreter = interpreter(src);
+        assert!(interpreter.coverage().is_none());
+        interpreter.set_coverage(true);
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(b"1")).unwrap();
+        let lines: Vec<usize> = interpreter.coverage().unwrap().iter().copied().collect();
+        // neither the body of the if statement nor the function that is never called
+        assert_eq!(lines, [6, 7, 8, 11]);
+    }
+
+    const BANNER: &str = "\
+[Chorus]
+Never gonna say \"Never gonna\"
+Never gonna say 1 + 2
+Never gonna say 'x' == 'x'
+Never gonna say 3.0 / 2.0
+";
+
+    // runs a program with or without the fast path for programs that only print
+    fn run_say_only(src: &str, fast: bool) -> (Result<RickrollObject, Error>, String) {
+        let mut interpreter = interpreter(src);
+        assert!(interpreter.say_only);
+        interpreter.say_only = fast;
+        let mut output: Vec<u8> = Vec::new();
+        let res = interpreter.run(&mut output, &mut Cursor::new(Vec::new()));
+        return (res, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn say_only_fast_path() {
+        let (fast, fast_output) = run_say_only(BANNER, true);
+        let (slow, slow_output) = run_say_only(BANNER, false);
+        assert!(fast.is_ok() && slow.is_ok());
+        assert_eq!(fast_output, "[N, e, v, e, r,  , g, o, n, n, a]\n3\nTRUE\n1.5\n");
+        assert_eq!(fast_output, slow_output);
+        // the chorus is put back, so the program can run again
+        let mut interpreter = interpreter(BANNER);
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), fast_output);
+    }
+
+    #[test]
+    fn say_only_fast_path_errors() {
+        let src = "[Chorus]\nNever gonna say 1\nNever gonna say 1 / 0\nNever gonna say 2\n";
+        let (fast, fast_output) = run_say_only(src, true);
+        let (slow, slow_output) = run_say_only(src, false);
+        assert_eq!(fast.unwrap_err().to_string(), slow.unwrap_err().to_string());
+        assert_eq!(fast_output, "1\n");
+        assert_eq!(fast_output, slow_output);
+    }
+
+    #[test]
+    fn say_only_detection() {
+        assert!(!interpreter("[Chorus]\nNever gonna let a down\nNever gonna say 1\n").say_only);
+        assert!(!interpreter("[Intro]\nNever gonna say 1\n\n[Chorus]\nNever gonna say 1\n").say_only);
+        assert!(!interpreter("[Chorus]\nInside we both know TRUE\nNever gonna say 1\nYour heart's been aching but you're too shy to say it\n").say_only);
+    }
+
+    // compares the time per run with and without the fast path, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn say_only_overhead() {
+        for fast in [true, false] {
+            let mut interpreter = interpreter(BANNER);
+            interpreter.say_only = fast;
+            let runs = 100000;
+            let start = std::time::Instant::now();
+            for _ in 0..runs {
+                interpreter.run(&mut io::sink(), &mut Cursor::new(Vec::new())).unwrap();
+            }
+            eprintln!("fast path {}: {:?} per run", fast, start.elapsed() / runs);
+        }
+    }
+
+    const WORDS: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1 < 2
+Never gonna say a
+Never gonna say a == TRUE
+Never gonna say !a
+Never gonna let b down
+Never gonna say b
+Never gonna let c down
+(Ooh give you c) Never gonna run ArrayOf and desert a, b
+Never gonna say c
+Inside we both know a
+    Never gonna say 7
+Your heart's been aching but you're too shy to say it
+";
+
+    #[test]
+    fn print_words() {
+        let mut interpreter = interpreter(WORDS);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "TRUE\nTRUE\nFALSE\nUNDEFINED\n[TRUE, UNDEFINED]\n7\n");
+
+        interpreter.set_print_words(PrintWords {
+            true_word: String::from("up"),
+            false_word: String::from("down"),
+            undefined_word: String::from("desert"),
+        });
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        // only the words change, the values compare and branch the same way
+        assert_eq!(String::from_utf8(output).unwrap(), "up\nup\ndown\ndesert\n[up, desert]\n7\n");
+    }
+
+    fn run_with_newline(src: &str, newline: Newline) -> String {
+        let mut interpreter = interpreter(src);
+        interpreter.set_newline(newline);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn newline_style() {
+        let printed = "TRUE\nTRUE\nFALSE\nUNDEFINED\n[TRUE, UNDEFINED]\n7\n";
+        assert_eq!(run_with_newline(WORDS, Newline::Lf), printed);
+        assert_eq!(run_with_newline(WORDS, Newline::CrLf), printed.replace('\n', "\r\n"));
+        // programs that only print take another path
+        let printed = "[N, e, v, e, r,  , g, o, n, n, a]\n3\nTRUE\n1.5\n";
+        assert_eq!(run_with_newline(BANNER, Newline::Lf), printed);
+        assert_eq!(run_with_newline(BANNER, Newline::CrLf), printed.replace('\n', "\r\n"));
+        // only the line ending changes, not newlines inside printed values
+        let src = "[Chorus]\nNever gonna say '\\n'\n";
+        assert_eq!(run_with_newline(src, Newline::Lf), "\n\n");
+        assert_eq!(run_with_newline(src, Newline::CrLf), "\n\r\n");
+    }
+
+    const READ_ENV: &str = "\
+[Chorus]
+Never gonna let name down
+Never gonna give name \"CARGO_PKG_NAME\"
+Never gonna let value down
+(Ooh give you value) Never gonna run GetEnv and desert name
+Never gonna say value
+";
+
+    #[test]
+    fn sandbox_forbids_get_env() {
+        run_and_expect(READ_ENV, "", "[r, i, c, k, r, o, l, l]\n");
+        let mut interpreter = interpreter(READ_ENV);
+        interpreter.set_sandbox(true);
+        let err = interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::PermissionError);
+        assert_eq!(err.to_string(), "Permission Denied: GetEnv is not available in sandbox mode\nTraceback on line 5");
+    }
+
+    // tries reading an environment variable and the clock, carrying on if it isn't allowed
+    const PROBE: &str = "\
+[Chorus]
+Never gonna let name down
+Never gonna give name \"CARGO_PKG_NAME\"
+Never gonna let value down
+Never gonna make you cry
+    (Ooh give you value) Never gonna run GetEnv and desert name
+Never gonna tell a lie and hurt err
+    Never gonna say 0
+Gotta make you understand
+Never gonna make you cry
+    (Ooh give you value) Never gonna run Now and desert you
+Never gonna tell a lie and hurt err
+    Never gonna say 0
+Gotta make you understand
+";
+
+    fn audit(src: &str, sandbox: bool, mode: AuditMode) -> Vec<AuditEntry> {
+        let mut interpreter = interpreter(src);
+        interpreter.set_sandbox(sandbox);
+        interpreter.set_audit_mode(mode);
+        interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        return interpreter.audit_log().iter().cloned().collect();
+    }
+
+    #[test]
+    fn audit_log_of_denials() {
+        let log = audit(PROBE, true, AuditMode::Denials);
+        let shown: Vec<String> = log.iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(shown, ["line 6: denied GetEnv([C, A, R, G, O, _, P, K, G, _, N, A, M, ...)", "line 11: denied Now()"]);
+        // allowed calls are only kept when asked for
+        assert!(audit(PROBE, false, AuditMode::Denials).is_empty());
+        let log = audit(PROBE, false, AuditMode::Everything);
+        assert_eq!(log.iter().map(|entry| (entry.line, entry.allowed)).collect::<Vec<_>>(), [(6, true), (11, true)]);
+    }
+
+    #[test]
+    fn audit_log_size() {
+        let src = format!(
+            "\
+[Chorus]
+Never gonna let name down
+Never gonna give name \"{}\"
+Never gonna let value down
+Never gonna let i down
+Never gonna give i 0
+Inside we both know i < 1000
+    Never gonna give i i + 1
+    Never gonna make you cry
+        (Ooh give you value) Never gonna run GetEnv and desert name
+    Never gonna tell a lie and hurt err
+        Never gonna give value 0
+    Gotta make you understand
+We know the game and we're gonna play it
+",
+            "A".repeat(1000)
+        );
+        let log = audit(&src, true, AuditMode::Denials);
+        assert_eq!(log.len(), AUDIT_LOG_SIZE);
+        // the long name is cut off in every entry, after HISTORY_VALUE_LIMIT characters
+        let summary = format!("[{}...", "A, ".repeat(13));
+        assert!(log.iter().all(|entry| entry.line == 10 && entry.args == [summary.clone()]));
+    }
+
+    // writes the pieces through a wrapping writer, one write each
+    fn wrap(width: usize, pieces: &[&[u8]]) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        let mut writer = WrappingWriter::new(&mut output, width);
+        for piece in pieces {
+            writer.write_all(piece).unwrap();
+        }
+        writer.flush().unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn wrapping_writer() {
+        // at the last space before the limit
+        assert_eq!(wrap(10, &[b"never gonna give you up\n"]), "never\ngonna give\nyou up\n");
+        // lines that fit are left alone, and the width counts the line so far
+        assert_eq!(wrap(5, &[b"abc", b"de\nfg\n"]), "abcde\nfg\n");
+        // in the middle of a word without a space
+        assert_eq!(wrap(4, &[b"abcdefghij\n"]), "abcd\nefgh\nij\n");
+        // the end of a line without a newline is written by flush
+        assert_eq!(wrap(4, &[b"ab cd ef"]), "ab\ncd\nef");
+        // \r\n line endings take one column
+        assert_eq!(wrap(3, &[b"abc\r\nde\r\n"]), "abc\r\nde\r\n");
+    }
+
+    #[test]
+    fn wrapping_writer_multi_byte() {
+        // widths are in characters, not bytes
+        assert_eq!(wrap(4, &["abc\u{e9}\n".as_bytes()]), "abc\u{e9}\n");
+        assert_eq!(wrap(3, &["\u{20ac}\u{20ac}\u{20ac}\u{20ac}\n".as_bytes()]), "\u{20ac}\u{20ac}\u{20ac}\n\u{20ac}\n");
+        // a multi-byte character straddling the boundary moves to the next line whole
+        assert_eq!(wrap(4, &["abcd\u{e9}f\n".as_bytes()]), "abcd\n\u{e9}f\n");
+        assert_eq!(wrap(4, &["ab c\u{e9}\n".as_bytes()]), "ab\nc\u{e9}\n");
+        // even when its bytes come in separate writes
+        let euro = "\u{20ac}".as_bytes();
+        assert_eq!(wrap(4, &[b"abcd", &euro[..1], &euro[1..2], &euro[2..], b"\n"]), "abcd\n\u{20ac}\n");
+    }
+
+    #[test]
+    fn wrap_output() {
+        let src = "\
+[Chorus]
+Never gonna let text down
+Never gonna let n down
+I just wanna tell text how I'm feeling
+    Never gonna say 1234567890
+Don't tell me you're too blind to see
+(Ooh give you n) Never gonna run ArrayLength and desert text
+Never gonna say n
+Never gonna say 1234567890
+";
+        let mut interpreter = interpreter(src);
+        interpreter.set_wrap_output(Some(4));
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        // the captured line keeps all of its 11 characters
+        assert_eq!(String::from_utf8(output).unwrap(), "11\n1234\n5678\n90\n");
+        interpreter.set_wrap_output(None);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "11\n1234567890\n");
+    }
+
+    // explains a number, a string and an array of numbers it makes
+    const EXPLAINED: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna let x down
+Never gonna give x 2
+(Ooh give you a) Never gonna run ArrayOf and desert x, x
+And if you ask me how 7 / x is feeling
+And if you ask me how 7.0 / 2.0 is feeling
+And if you ask me how \"hi\" is feeling
+And if you ask me how a is feeling
+And if you ask me how 'c' is feeling
+And if you ask me how x > 1 is feeling
+Never gonna let u down
+And if you ask me how u is feeling
+";
+
+    #[test]
+    fn explain_values() {
+        run_and_expect(
+            EXPLAINED,
+            "",
+            "3 (INT)\n3.5 (FLOAT)\n[h, i] (ARRAY)\n[2, 2] (ARRAY)\nc (CHAR)\nTRUE (BOOL)\nUNDEFINED (UNDEFINED)\n",
+        );
+    }
+
+    #[test]
+    fn explain_is_output() {
+        // written with the rest of the output, so it can be captured
+        // tests/explain.rs checks writing it to stderr instead
+        let src = "\
+[Chorus]
+Never gonna let text down
+I just wanna tell text how I'm feeling
+    And if you ask me how 42 is feeling
+Don't tell me you're too blind to see
+Never gonna say text
+";
+        let chars: Vec<String> = "42 (INT)\n".chars().map(String::from).collect();
+        run_and_expect(src, "", &format!("[{}]\n", chars.join(", ")));
+    }
+
+    #[test]
+    fn capture_matches_printed_output() {
+        let head = "[Chorus]\nNever gonna let c down\nNever gonna give c 'z'\nNever gonna let text down\n";
+        let body = "Never gonna say 1 + 2\nNever gonna say 'a'\nNever gonna run PutChar and desert c\n";
+        let printed = run_source(&format!("{}{}", head, body), "").unwrap();
+        let captured = run_source(
+            &format!(
+                "{}I just wanna tell text how I'm feeling\n{}Don't tell me you're too blind to see\nNever gonna say text\n",
+                head, body
+            ),
+            "",
+        )
+        .unwrap();
+        let chars: Vec<String> = printed.chars().map(String::from).collect();
+        assert_eq!(captured, format!("[{}]\n", chars.join(", ")));
+    }
+
+    #[test]
+    fn nested_capture() {
+        let src = "\
+[Chorus]
+Never gonna let outer down
+Never gonna let inner down
+I just wanna tell outer how I'm feeling
+    Never gonna say 1
+    I just wanna tell inner how I'm feeling
+        Never gonna say 2
+    Don't tell me you're too blind to see
+    Never gonna say 3
+Don't tell me you're too blind to see
+Never gonna say outer
+Never gonna say inner
+Never gonna say 4
+";
+        run_and_expect(src, "", "[1, \n, 3, \n]\n[2, \n]\n4\n");
+    }
+
+    #[test]
+    fn error_inside_capture() {
+        let src = "\
+[Chorus]
+Never gonna let text down
+Never gonna make you cry
+    I just wanna tell text how I'm feeling
+        Never gonna say 1
+        Never gonna say 1 / 0
+    Don't tell me you're too blind to see
+Never gonna tell a lie and hurt err
+    Never gonna say 2
+Gotta make you understand
+Never gonna say text
+Never gonna say 3
+";
+        // the captured output is dropped and printing goes back to the output stream
+        run_and_expect(src, "", "2\nUNDEFINED\n3\n");
+    }
+
+    #[test]
+    fn capture_counts_towards_output_limit() {
+        let src = "\
+[Chorus]
+Never gonna let text down
+I just wanna tell text how I'm feeling
+    Never gonna say 12345
+Don't tell me you're too blind to see
+Never gonna say 1
+";
+        let mut interpreter = interpreter(src);
+        interpreter.set_output_limit(OutputLimit::Error(4));
+        let err = interpreter.run(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("write-failed"));
+    }
 }
 
 /*
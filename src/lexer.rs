@@ -1,10 +1,14 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::docs::{in_literal, match_statement, statement_examples};
 use crate::error::*;
-use crate::expr::ExprLexer;
+use crate::expr::{quote_char, render_value, ExprLexer, ExprParser};
 use crate::util::*;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+
 #[derive(Debug, Clone)]
 pub enum Token {
     Punc(usize, String),
@@ -27,39 +31,416 @@ impl Token {
     }
 }
 
+// the names of a function's parameters, each with the lexed tokens of its default value if it has one
+type Params = Vec<(String, Option<Vec<Token>>)>;
+
 #[derive(Debug)]
 pub struct Lexer {
     ptr: usize,
     raw: Vec<String>,
     lexed: Vec<Token>,
+    // longest line allowed, in bytes
+    max_line_length: Option<usize>,
+    // values substituted for ${NAME} placeholders
+    defines: HashMap<String, RickrollObject>,
+    // removed from the start of every line that begins with it
+    strip_prefix: Option<Regex>,
+    // skip illegal statements instead of failing
+    lenient: bool,
+    // indentation warnings and errors of the statements skipped in lenient mode
+    diagnostics: Vec<Error>,
+}
+
+// separates the condition and the returned value of a guard
+const GUARD_SEPARATOR: &str = ", never gonna give (give you ";
+
+// parses the expressions of a lexed line on their own, with every name in them declared
+fn check_exprs(tokens: Vec<Token>) -> Result<(), Error> {
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let keyword = match token {
+            Token::Statement(_, keyword) => keyword,
+            _ => continue,
+        };
+        let mut expr = Vec::new();
+        while let Some(token) = tokens.next_if(|token| !matches!(token, Token::Statement(_, _))) {
+            expr.push(token);
+        }
+        // names written before the expression
+        let skip = match &keyword[..] {
+            "SAY" | "CHECK" | "RETURN" | "SLEEP" | "EXPLAIN" => 0,
+            "ASSIGN" | "RAISE" => 1,
+            "FOREACH" if matches!(expr.get(1), Some(Token::Punc(_, punc)) if punc == ",") => 3,
+            "FOREACH" => 1,
+            _ => continue,
+        };
+        let expr = expr.split_off(skip);
+        let mut scope = Scope::new();
+        for token in &expr {
+            if let Token::Name(_, name) = token {
+                scope.add_var(name.clone());
+            }
+        }
+        ExprParser::new(expr, scope).parse().map_err(|err| Error::traceback(err, Some(1)))?;
+    }
+    return Ok(());
+}
+
+// finds where the condition of a guard ends, outside of any literal
+// returns None if the line isn't a guard
+fn guard_split(curln: &str) -> Option<usize> {
+    lazy_static! {
+        static ref GUARD: Regex = Regex::new("(?s)^Inside we both know .+, never gonna give \\(give you .+\\)$").unwrap();
+    }
+    if !curln.starts_with("Inside we both know ") || !GUARD.is_match(curln) {
+        return None;
+    }
+    return curln
+        .match_indices(GUARD_SEPARATOR)
+        .map(|(ind, _)| ind)
+        .find(|ind| *ind >= 20 && !in_literal(curln, *ind));
+}
+
+// common prefixes of programs copied from elsewhere, by name
+pub const PREFIX_PRESETS: [(&str, &str); 3] = [
+    // 1. or 1)
+    ("numbered", r"^\s*\d+[.)]\s"),
+    // > quoted text
+    ("quote", r"^\s*>\s?"),
+    // [00:12] or [01:00:12]
+    ("timestamp", r"^\s*\[\d+:\d{2}(:\d{2})?\]\s"),
+];
+
+// replaces the ${NAME} placeholders in a line with the values they are defined as
+// inside a string literal the value's text is inserted, escaped so it stays in the string,
+// and anywhere else the value is inserted as a literal
+// returns None if the line has no placeholders
+fn substitute(line: &str, defines: &HashMap<String, RickrollObject>, ln: usize) -> Result<Option<String>, Error> {
+    lazy_static! {
+        static ref PLACEHOLDER: Regex = Regex::new("^\\$\\{(\\w+)\\}").unwrap();
+    }
+    if !line.contains("${") {
+        return Ok(None);
+    }
+    let mut res = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut ind = 0;
+    while ind < line.len() {
+        if let Some(captures) = PLACEHOLDER.captures(&line[ind..]) {
+            let name = &captures[1];
+            let value = match defines.get(name) {
+                Some(value) => value,
+                None => {
+                    return Err(Error::from_message(
+                        ErrorType::NameError,
+                        "undefined-placeholder",
+                        &[("name", &name)],
+                        Some(ln),
+                    ));
+                }
+            };
+            if in_string {
+                let text = from_char_array(value).unwrap_or_else(|| value.to_string());
+                for chr in text.chars() {
+                    match chr {
+                        '"' | '\\' => {
+                            res.push('\\');
+                            res.push(chr);
+                        }
+                        '\n' => res += "\\n",
+                        _ => res.push(chr),
+                    }
+                }
+            } else {
+                res += &render_value(value).map_err(|err| Error::traceback(err, Some(ln)))?;
+            }
+            ind += captures[0].len();
+            continue;
+        }
+        let chr = line[ind..].chars().next().unwrap();
+        if escaped {
+            escaped = false;
+        } else if in_string && chr == '\\' {
+            escaped = true;
+        } else if chr == '"' {
+            // each quote of a """ toggles too, which leaves the same state
+            in_string = !in_string;
+        }
+        res.push(chr);
+        ind += chr.len_utf8();
+    }
+    return Ok(Some(res));
+}
+
+// joins the line at ptr with the following ones while a """ string is left open
+// returns the joined line and the number of extra lines it spans
+// a line is only copied if it has to be joined
+pub(crate) fn logical_line(raw: &[String], ptr: usize, max_line_length: Option<usize>) -> Result<(Cow<'_, str>, usize), Error> {
+    let check_length = |ind: usize| {
+        if let Some(max) = max_line_length {
+            if raw[ind].len() > max {
+                return Err(Error::from_message(
+                    ErrorType::ResourceError,
+                    "line-length-limit",
+                    &[("length", &raw[ind].len()), ("max", &max)],
+                    Some(ind + 1),
+                ));
+            }
+        }
+        return Ok(());
+    };
+    check_length(ptr)?;
+    let mut res = Cow::Borrowed(&raw[ptr][..]);
+    let mut extra = 0;
+    while res.matches("\"\"\"").count() % 2 == 1 {
+        extra += 1;
+        if ptr + extra >= raw.len() {
+            return Err(Error::from_message(
+                ErrorType::SyntaxError,
+                "unterminated-multi-line-string",
+                &[],
+                Some(ptr + 1),
+            ));
+        }
+        check_length(ptr + extra)?;
+        let joined = res.to_mut();
+        joined.push('\n');
+        joined.push_str(&raw[ptr + extra]);
+    }
+    return Ok((res, extra));
+}
+
+// how a statement changes the nesting of blocks
+enum Nesting {
+    // starts a function, which is never inside another block
+    Function,
+    Opens,
+    Closes,
+    // closes a block and opens the next one, like the handler of an error handling block
+    Reopens,
+    Keeps,
+}
+
+fn nesting_of(curln: &str) -> Nesting {
+    return match match_statement(curln) {
+        Some(("VERSE", _)) | Some(("CHORUS", _)) | Some(("INTRO", _)) => Nesting::Function,
+        Some(("CHECK", _)) | Some(("FOREACH", _)) | Some(("TRY", _)) | Some(("CAPTURE", _)) => Nesting::Opens,
+        Some(("WHILE_END", _)) | Some(("IF_END", _)) | Some(("TRY_END", _)) | Some(("CAPTURE_END", _)) => {
+            Nesting::Closes
+        }
+        Some(("CATCH", _)) => Nesting::Reopens,
+        _ => Nesting::Keeps,
+    };
+}
+
+// the tabs and spaces a line starts with
+fn indentation(line: &str) -> &str {
+    return &line[..(line.len() - line.trim_start_matches([' ', '\t']).len())];
+}
+
+// a block whose lines are checked for inconsistent indentation
+struct IndentedBlock {
+    opener: usize,
+    // columns the opener is indented by
+    width: usize,
+    // the character the block is indented with, once a line is indented
+    style: Option<char>,
+    // only the first bad line of a block is reported
+    reported: bool,
+}
+
+impl IndentedBlock {
+    fn new(opener: usize, indent: &str) -> IndentedBlock {
+        IndentedBlock {
+            opener,
+            width: indent.chars().count(),
+            style: indent.chars().next(),
+            reported: false,
+        }
+    }
+
+    fn check(&mut self, indent: &str, line: usize) -> Option<Error> {
+        let mixed = (indent.contains(' ') && indent.contains('\t'))
+            || self.style.is_some_and(|style| indent.chars().any(|chr| chr != style));
+        if self.style.is_none() {
+            self.style = indent.chars().next();
+        }
+        let id = if mixed {
+            "mixed-indentation"
+        } else if indent.chars().count() < self.width {
+            "dedented-line"
+        } else {
+            return None;
+        };
+        if self.reported {
+            return None;
+        }
+        self.reported = true;
+        return Some(Error::from_message(ErrorType::SyntaxError, id, &[("opener", &self.opener)], Some(line)));
+    }
+}
+
+// finds blocks mixing tabs and spaces in their indentation, or with lines indented less than
+// the line opening them, since these are usually signs of a misplaced terminator
+// the language doesn't depend on indentation, so these are only warnings
+fn indentation_warnings(raw: &[String]) -> Vec<Error> {
+    let mut warnings = Vec::new();
+    let mut blocks: Vec<IndentedBlock> = Vec::new();
+    let mut ptr = 0;
+    while ptr < raw.len() {
+        // the lexer reports the error itself
+        let (logical, extra) = match logical_line(raw, ptr, None) {
+            Ok(res) => res,
+            Err(_) => break,
+        };
+        let curln = logical.trim();
+        if !curln.is_empty() {
+            let indent = indentation(&raw[ptr]);
+            let nesting = nesting_of(curln);
+            match nesting {
+                Nesting::Function => blocks.clear(),
+                Nesting::Closes | Nesting::Reopens => {
+                    blocks.pop();
+                }
+                _ => (),
+            }
+            // a terminator belongs to the block around the one it closes
+            if let Some(block) = blocks.last_mut() {
+                warnings.extend(block.check(indent, ptr + 1));
+            }
+            if let Nesting::Function | Nesting::Opens | Nesting::Reopens = nesting {
+                blocks.push(IndentedBlock::new(ptr + 1, indent));
+            }
+        }
+        ptr += extra + 1;
+    }
+    return warnings;
+}
+
+// indents every statement by one unit for each block it is in, keeping the rest of the line
+// function bodies aren't indented, and lines inside multi-line strings are left as they are
+pub fn format_source(source: &str, unit: &str) -> String {
+    let raw: Vec<String> = source.split('\n').map(String::from).collect();
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth: usize = 0;
+    let mut ptr = 0;
+    while ptr < raw.len() {
+        let (logical, extra) = match logical_line(&raw, ptr, None) {
+            Ok(res) => res,
+            // an unterminated string runs to the end of the program
+            Err(_) => (Cow::Borrowed(""), raw.len() - ptr - 1),
+        };
+        let curln = logical.trim();
+        if curln.is_empty() && extra == 0 {
+            lines.push(String::new());
+            ptr += 1;
+            continue;
+        }
+        let level = match nesting_of(curln) {
+            Nesting::Function => {
+                depth = 0;
+                0
+            }
+            Nesting::Opens => {
+                depth += 1;
+                depth - 1
+            }
+            Nesting::Closes => {
+                depth = depth.saturating_sub(1);
+                depth
+            }
+            Nesting::Reopens => depth.saturating_sub(1),
+            Nesting::Keeps => depth,
+        };
+        lines.push(unit.repeat(level) + raw[ptr].trim_start_matches([' ', '\t']));
+        lines.extend(raw[(ptr + 1)..=(ptr + extra)].iter().cloned());
+        ptr += extra + 1;
+    }
+    return lines.join("\n");
 }
 
 impl Lexer {
     pub fn new(raw_text: String) -> Lexer {
         Lexer {
             ptr: 0,
-            raw: {
-                let mut res = Vec::new();
-                let mut cur = String::new();
-                for chr in raw_text.chars() {
-                    if chr == '\n' {
-                        res.push(cur);
-                        cur = String::new();
-                    } else {
-                        cur.push(chr);
-                    }
-                }
-                res.push(cur);
-                res
-            },
+            raw: raw_text.split('\n').map(String::from).collect(),
             lexed: Vec::new(),
+            max_line_length: None,
+            defines: HashMap::new(),
+            strip_prefix: None,
+            lenient: false,
+            diagnostics: Vec::new(),
         }
     }
 
+    // skips lines that aren't statements, keeping their errors as diagnostics, so as much of
+    // a broken program as possible is lexed
+    // other errors still stop lexing
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    // removes a prefix matching the pattern from the start of each line before it is lexed
+    // lines that don't start with the prefix are left as they are, and keep their line numbers
+    // a pattern that would remove part of a statement is rejected
+    pub fn set_strip_prefix(&mut self, pattern: Option<&str>) -> Result<(), Error> {
+        let pattern = match pattern {
+            Some(pattern) => pattern,
+            None => {
+                self.strip_prefix = None;
+                return Ok(());
+            }
+        };
+        let prefix = match Regex::new(pattern) {
+            Ok(prefix) => prefix,
+            Err(err) => {
+                return Err(Error::from_message(
+                    ErrorType::ValueError,
+                    "invalid-prefix",
+                    &[("reason", &err)],
+                    None,
+                ))
+            }
+        };
+        for example in statement_examples() {
+            if prefix.find(example).is_some_and(|found| found.start() == 0 && !found.as_str().is_empty()) {
+                return Err(Error::from_message(
+                    ErrorType::ValueError,
+                    "prefix-removes-statement",
+                    &[("pattern", &pattern), ("statement", &example)],
+                    None,
+                ));
+            }
+        }
+        self.strip_prefix = Some(prefix);
+        return Ok(());
+    }
+
+    // replaces ${NAME} placeholders in the program with the values of the defines
+    pub fn set_defines(&mut self, defines: HashMap<String, RickrollObject>) {
+        self.defines = defines;
+    }
+
+    // rejects programs with lines longer than max_line_length bytes
+    pub fn set_max_line_length(&mut self, max_line_length: Option<usize>) {
+        self.max_line_length = max_line_length;
+    }
+
     fn has_more(&self) -> bool {
         self.ptr < self.raw.len()
     }
 
+    // lexes an expression that is a slice of the line, counting columns in errors from the start of it
+    fn lex_expr(&self, line: &str, expr: &str) -> Result<Vec<Token>, Error> {
+        let expr = expr.trim_start();
+        // both are slices of the same string, so the expression starts where its address does
+        let start = expr.as_ptr() as usize - line.as_ptr() as usize;
+        let mut lexer = ExprLexer::new(expr, self.ptr + 1);
+        lexer.set_column_offset(line[..start].chars().count());
+        return self.wrap_check(lexer.make_tokens());
+    }
+
     // wraps a traceback around a possible error
     fn wrap_check<T>(&self, res: Result<T, Error>) -> Result<T, Error> {
         if let Err(error) = res {
@@ -69,6 +450,7 @@ impl Lexer {
     }
 
     // helper function splitting a string of the form "A, BCD, EEE" into ["A", "BCD", "EEE"]
+    // a single trailing comma is allowed
     fn split_vars(&self, raw: String, empty: String) -> Result<Vec<String>, Error> {
         let mut args: Vec<String> = Vec::new();
         let mut cur: String = String::new();
@@ -79,9 +461,14 @@ impl Lexer {
             } else if chr == ',' {
                 // variable break
                 if cur.is_empty() {
-                    return Err(Error::new(
-                        ErrorType::NameError,
-                        "Blank variable name",
+                    return Err(Error::from_message(
+                        ErrorType::SyntaxError,
+                        if args.is_empty() {
+                            "leading-comma"
+                        } else {
+                            "doubled-comma"
+                        },
+                        &[],
                         Some(self.ptr + 1),
                     ));
                 }
@@ -89,9 +476,10 @@ impl Lexer {
                 cur.clear();
             } else if !chr.is_ascii_whitespace() {
                 // illegal character
-                return Err(Error::new(
+                return Err(Error::from_message(
                     ErrorType::IllegalArgumentError,
-                    &(format!("Illegal character \"{}\" in variable", chr))[..],
+                    "illegal-variable-char",
+                    &[("char", &chr)],
                     Some(self.ptr + 1),
                 ));
             }
@@ -106,18 +494,126 @@ impl Lexer {
         return Ok(args);
     }
 
-    pub fn parse(mut self) -> Result<Vec<Token>, Error> {
+    // helper function splitting a parameter list of the form "A, B = 1, C = 'x'"
+    // parameters with default values have the lexed tokens of the default
+    // the list is a slice of the line, so columns in errors count from the start of the line
+    fn split_params(&self, line: &str, raw: &str) -> Result<Params, Error> {
+        // split on commas outside of character and string literals
+        let raw = raw.trim();
+        let mut pieces: Vec<&str> = Vec::new();
+        let mut start = 0;
+        let mut quote: Option<char> = None;
+        let mut escaped = false;
+        for (ind, chr) in raw.char_indices() {
+            if quote.is_some() && chr == '\\' && !escaped {
+                escaped = true;
+                continue;
+            }
+            if (chr == '\'' || chr == '"') && !escaped {
+                if quote.is_none() {
+                    quote = Some(chr);
+                } else if quote == Some(chr) {
+                    quote = None;
+                }
+            }
+            escaped = false;
+            if chr == ',' && quote.is_none() {
+                pieces.push(&raw[start..ind]);
+                start = ind + 1;
+            }
+        }
+        pieces.push(&raw[start..]);
+        // no parameters
+        if pieces.len() == 1 && pieces[0].trim() == "up" {
+            return Ok(Vec::new());
+        }
+        let mut params: Params = Vec::new();
+        let count = pieces.len();
+        for (ind, piece) in pieces.into_iter().enumerate() {
+            let (name, default) = match piece.find('=') {
+                Some(ind) => (String::from(piece[..ind].trim()), Some(&piece[(ind + 1)..])),
+                None => (String::from(piece.trim()), None),
+            };
+            // trailing comma
+            if name.is_empty() && default.is_none() && ind > 0 && ind == count - 1 {
+                continue;
+            }
+            let mut names = self.split_vars(name.clone(), String::new())?;
+            if names.len() != 1 {
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    if ind == 0 {
+                        "leading-comma"
+                    } else {
+                        "doubled-comma"
+                    },
+                    &[],
+                    Some(self.ptr + 1),
+                ));
+            }
+            let name = names.pop().unwrap();
+            let default = match default {
+                Some(expr) => {
+                    let tokens = self.lex_expr(line, expr)?;
+                    // default values are evaluated without any variables in scope
+                    if tokens.iter().any(|tok| matches!(tok, Token::Name(_, _))) {
+                        return Err(Error::from_message(
+                            ErrorType::SyntaxError,
+                            "default-not-constant",
+                            &[("name", &name)],
+                            Some(self.ptr + 1),
+                        ));
+                    }
+                    Some(tokens)
+                }
+                None => {
+                    if params.iter().any(|(_, default)| default.is_some()) {
+                        return Err(Error::from_message(
+                            ErrorType::SyntaxError,
+                            "missing-default",
+                            &[("name", &name)],
+                            Some(self.ptr + 1),
+                        ));
+                    }
+                    None
+                }
+            };
+            params.push((name, default));
+        }
+        return Ok(params);
+    }
+
+    pub fn parse(self) -> Result<Vec<Token>, Error> {
+        return self.parse_with_diagnostics().map(|(tokens, _)| tokens);
+    }
+
+    // lexes the program, also returning warnings about its indentation and the errors of the
+    // lines skipped in lenient mode
+    pub fn parse_with_diagnostics(mut self) -> Result<(Vec<Token>, Vec<Error>), Error> {
         // regexes for matching statements
         lazy_static! {
+            // expressions can span several lines inside a multi-line string
+            // statements with expressions are checked by prefix before the regex scans the whole line
             // print
-            static ref SAY: Regex = Regex::new("^Never gonna say .+$").unwrap();
+            static ref SAY: Regex = Regex::new("(?s)^Never gonna say .+$").unwrap();
             // let + assign to var
             static ref LET: Regex = Regex::new("^Never gonna let \\w+ down$").unwrap();
-            static ref ASSIGN: Regex = Regex::new("^Never gonna give \\w+ .+$").unwrap();
+            static ref LET_TRAILING: Regex = Regex::new("^Never gonna let \\w+ down\\s").unwrap();
+            static ref ASSIGN: Regex = Regex::new("(?s)^Never gonna give \\w+ .+$").unwrap();
+            // type assertion
+            static ref EXPECT: Regex = Regex::new("^You know the rules and \\w+ is \\w+$").unwrap();
             // check, if, and while
-            static ref CHECK: Regex = Regex::new("^Inside we both know .+$").unwrap();
+            static ref CHECK: Regex = Regex::new("(?s)^Inside we both know .+$").unwrap();
+            static ref FOREACH: Regex = Regex::new("(?s)^We've known each (\\w+)(, (\\w+))? in (.+) for so long$").unwrap();
             static ref WHILE_END: Regex = Regex::new("^We know the game and we\'re gonna play it$").unwrap();
             static ref IF_END: Regex = Regex::new("^Your heart\'s been aching but you\'re too shy to say it$").unwrap();
+            // error handling
+            static ref TRY: Regex = Regex::new("^Never gonna make you cry$").unwrap();
+            static ref CATCH: Regex = Regex::new("^Never gonna tell a (\\w+) and hurt (\\w+)$").unwrap();
+            static ref TRY_END: Regex = Regex::new("^Gotta make you understand$").unwrap();
+            // output capture
+            static ref CAPTURE: Regex = Regex::new("^I just wanna tell \\w+ how I'm feeling$").unwrap();
+            static ref CAPTURE_END: Regex = Regex::new("^Don't tell me you're too blind to see$").unwrap();
             // blocks (functions)
             static ref CHORUS: Regex = Regex::new("^\\[Chorus\\]$").unwrap();
             static ref INTRO: Regex = Regex::new("^\\[Intro\\]$").unwrap();
@@ -125,21 +621,58 @@ impl Lexer {
             // function statements
             static ref RUN: Regex = Regex::new("^Never gonna run \\w+ and desert .+$").unwrap();
             static ref RUN_ASSIGN: Regex = Regex::new("^\\(Ooh give you \\w+\\) Never gonna run \\w+ and desert .+$").unwrap();
-            static ref RETURN: Regex = Regex::new("^\\(Ooh\\) Never gonna give, never gonna give \\(give you .+\\)$").unwrap();
+            static ref RETURN: Regex = Regex::new("(?s)^\\(Ooh\\) Never gonna give, never gonna give \\(give you .+\\)$").unwrap();
+            // pause
+            static ref SLEEP: Regex = Regex::new("(?s)^We've been waiting for .+$").unwrap();
+            // raising an error
+            static ref RAISE: Regex = Regex::new("(?s)^You wouldn't get this from any other (\\w+) (.+)$").unwrap();
+            // printing a value with its type
+            static ref EXPLAIN: Regex = Regex::new("(?s)^And if you ask me how (.+) is feeling$").unwrap();
             // function parameters
             static ref ARGS: Regex = Regex::new("\\(Ooh give you .+\\)").unwrap();
+            // statements that stop before their expression
+            static ref MISSING_EXPR: Regex = Regex::new("^(Never gonna give \\w+|Inside we both know|We've been waiting for|You wouldn't get this from any other \\w+)$").unwrap();
         }
+        if let Some(prefix) = &self.strip_prefix {
+            for line in self.raw.iter_mut() {
+                let stripped = match prefix.find(line) {
+                    Some(found) if found.start() == 0 => found.end(),
+                    _ => continue,
+                };
+                line.replace_range(..stripped, "");
+            }
+        }
+        self.diagnostics = indentation_warnings(&self.raw);
         // iterate over raw
         while self.has_more() {
             // try to match a statement
-            let curln = self.raw[self.ptr].trim();
+            let (logical, extra) = logical_line(&self.raw, self.ptr, self.max_line_length)?;
+            // without defines, "${" is left alone so existing programs keep working
+            let logical = if self.defines.is_empty() {
+                logical
+            } else {
+                match substitute(&logical, &self.defines, self.ptr + 1)? {
+                    Some(replaced) => Cow::Owned(replaced),
+                    None => logical,
+                }
+            };
+            let curln = logical.trim();
             if curln == "" {
                 self.ptr += 1;
                 continue;
-            } else if SAY.is_match(curln) {
+            } else if curln == "Never gonna say" {
+                // trailing spaces were trimmed, so nothing is left to print
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    "empty-say",
+                    &[],
+                    Some(self.ptr + 1),
+                ));
+            } else if curln.starts_with("Never gonna say ") && SAY.is_match(curln) {
                 // ^Never gonna say .+$
-                let expr = String::from(&curln[16..]);
-                let tokens = self.wrap_check(ExprLexer::new(expr, self.ptr + 1).make_tokens())?;
+                // spaces around the expression are dropped, spaces in literals are kept
+                let expr = curln[16..].trim();
+                let tokens = self.lex_expr(&logical, expr)?;
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("SAY")));
                 for token in tokens {
@@ -151,15 +684,26 @@ impl Lexer {
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("LET")));
                 self.lexed.push(Token::Name(self.ptr + 1, varname));
-            } else if ASSIGN.is_match(curln) {
+            } else if let Some(found) = LET_TRAILING.find(curln) {
+                // ^Never gonna let \\w+ down\\s
+                // a declaration followed by more words is reported where they start
+                let rest = curln[found.end()..].trim_start();
+                let start = rest.as_ptr() as usize - logical.as_ptr() as usize;
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    "trailing-characters",
+                    &[("column", &(logical[..start].chars().count() + 1))],
+                    Some(self.ptr + 1),
+                ));
+            } else if curln.starts_with("Never gonna give ") && ASSIGN.is_match(curln) {
                 // ^Never gonna give \\w+ .+$
-                let slice = String::from(&curln[17..]); // \\w .+
+                let slice = &curln[17..]; // \\w .+
                 match slice.find(' ') {
                     Some(index) => {
                         let varname = String::from(String::from(&slice[..index]).trim());
-                        let expr = String::from(&slice[(index + 1)..]);
+                        let expr = &slice[(index + 1)..];
                         let tokens =
-                            self.wrap_check(ExprLexer::new(expr, self.ptr + 1).make_tokens())?;
+                            self.lex_expr(&logical, expr)?;
                         self.lexed
                             .push(Token::Statement(self.ptr + 1, String::from("ASSIGN")));
                         self.lexed.push(Token::Name(self.ptr + 1, varname));
@@ -168,22 +712,63 @@ impl Lexer {
                         }
                     }
                     None => {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::SyntaxError,
-                            "Illegal statement",
+                            "illegal-statement",
+                            &[],
                             Some(self.ptr + 1),
                         ));
                     }
                 }
-            } else if CHECK.is_match(curln) {
+            } else if EXPECT.is_match(curln) {
+                // ^You know the rules and \\w+ is \\w+$
+                let slice = &curln[23..];
+                let ind = slice.find(' ').unwrap();
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("EXPECT")));
+                self.lexed
+                    .push(Token::Name(self.ptr + 1, String::from(&slice[..ind])));
+                self.lexed
+                    .push(Token::Name(self.ptr + 1, String::from(&slice[(ind + 4)..])));
+            } else if let Some(split) = guard_split(curln) {
+                // ^Inside we both know .+, never gonna give \\(give you .+\\)$
+                // the same as an if statement containing only a return
+                let cond = &curln[20..split];
+                let expr = &curln[(split + GUARD_SEPARATOR.len())..(curln.len() - 1)];
+                let cond = self.lex_expr(&logical, cond)?;
+                let expr = self.lex_expr(&logical, expr)?;
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("CHECK")));
+                self.lexed.extend(cond);
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("RETURN")));
+                self.lexed.extend(expr);
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("IF_END")));
+            } else if curln.starts_with("Inside we both know ") && CHECK.is_match(curln) {
                 // ^Inside we both know .+$
-                let expr = String::from(&curln[20..]);
-                let tokens = self.wrap_check(ExprLexer::new(expr, self.ptr + 1).make_tokens())?;
+                let expr = &curln[20..];
+                let tokens = self.lex_expr(&logical, expr)?;
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("CHECK")));
                 for token in tokens {
                     self.lexed.push(token);
                 }
+            } else if curln.starts_with("We've known each ") && FOREACH.is_match(curln) {
+                // ^We've known each (\\w+)(, (\\w+))? in (.+) for so long$
+                let captures = FOREACH.captures(curln).unwrap();
+                let tokens = self.lex_expr(&logical, &captures[4])?;
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("FOREACH")));
+                self.lexed
+                    .push(Token::Name(self.ptr + 1, String::from(&captures[1])));
+                // the comma sets the index apart from a name starting the expression
+                if let Some(index) = captures.get(3) {
+                    self.lexed.push(Token::Punc(self.ptr + 1, String::from(",")));
+                    self.lexed
+                        .push(Token::Name(self.ptr + 1, String::from(index.as_str())));
+                }
+                self.lexed.extend(tokens);
             } else if WHILE_END.is_match(curln) {
                 // ^We know the game and we\'re gonna play it$
                 self.lexed
@@ -192,6 +777,36 @@ impl Lexer {
                 // ^Your heart\'s been aching but you\'re too shy to say it$
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("IF_END")));
+            } else if TRY.is_match(curln) {
+                // ^Never gonna make you cry$
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("TRY")));
+            } else if CATCH.is_match(curln) {
+                // ^Never gonna tell a (\\w+) and hurt (\\w+)$
+                let captures = CATCH.captures(curln).unwrap();
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("CATCH")));
+                self.lexed
+                    .push(Token::Name(self.ptr + 1, String::from(&captures[2])));
+                // a lie catches every error, anything else names the type to catch
+                if &captures[1] != "lie" {
+                    self.lexed
+                        .push(Token::Name(self.ptr + 1, String::from(&captures[1])));
+                }
+            } else if TRY_END.is_match(curln) {
+                // ^Gotta make you understand$
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("TRY_END")));
+            } else if CAPTURE.is_match(curln) {
+                // ^I just wanna tell \\w+ how I'm feeling$
+                let varname = String::from(&curln[18..(curln.len() - 16)]);
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("CAPTURE")));
+                self.lexed.push(Token::Name(self.ptr + 1, varname));
+            } else if CAPTURE_END.is_match(curln) {
+                // ^Don't tell me you're too blind to see$
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("CAPTURE_END")));
             } else if CHORUS.is_match(curln) {
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("VERSE")));
@@ -206,26 +821,33 @@ impl Lexer {
                 // ^\\[Verse \\w+\\]$
                 let func_name = String::from(&curln[7..(curln.len() - 1)]);
                 self.ptr += 1;
-                let curln = self.raw[self.ptr].trim();
-                if !self.has_more() || !ARGS.is_match(curln) {
-                    return Err(Error::new(
+                if !self.has_more() || !ARGS.is_match(self.raw[self.ptr].trim()) {
+                    return Err(Error::from_message(
                         ErrorType::SyntaxError,
-                        &(format!("No argument specification for function {}", func_name))[..],
+                        "missing-argument-list",
+                        &[("func", &func_name)],
                         Some(self.ptr + 1),
                     ));
                 }
+                let line = &self.raw[self.ptr];
+                let curln = line.trim();
                 // "\\(Ooh give you .+\\)"
-                let func_args = self.split_vars(
-                    String::from(&curln[14..(curln.len() - 1)]),
-                    String::from("up"),
-                )?;
+                let func_args = self.split_params(line, &curln[14..(curln.len() - 1)])?;
                 self.lexed
                     .push(Token::Statement(self.ptr, String::from("VERSE")));
                 self.lexed.push(Token::Name(self.ptr, func_name));
-                for arg in func_args {
+                for (arg, default) in func_args {
                     self.lexed.push(Token::Name(self.ptr + 1, arg));
+                    // default values follow an "=" operator
+                    if let Some(tokens) = default {
+                        self.lexed
+                            .push(Token::Operator(self.ptr + 1, String::from("=")));
+                        for token in tokens {
+                            self.lexed.push(token);
+                        }
+                    }
                 }
-            } else if RUN.is_match(curln) {
+            } else if curln.starts_with("Never gonna run ") && RUN.is_match(curln) {
                 // ^Never gonna run \\w+ and desert .+$
                 let substring = String::from(&curln[16..]); // \\w+ and desert .+$
                 let ind = substring.find(' ').unwrap();
@@ -240,7 +862,7 @@ impl Lexer {
                 for arg in func_args {
                     self.lexed.push(Token::Name(self.ptr + 1, arg));
                 }
-            } else if RUN_ASSIGN.is_match(curln) {
+            } else if curln.starts_with("(Ooh give you ") && RUN_ASSIGN.is_match(curln) {
                 // ^\\(Ooh give you \\w+\\) Never gonna run \\w+ and desert .+$
                 let substring = String::from(&curln[14..]); // \\w+\\) Never gonna run \\w+ and desert .+$
                 let ind = substring.find(')').unwrap();
@@ -260,26 +882,566 @@ impl Lexer {
                 for arg in func_args {
                     self.lexed.push(Token::Name(self.ptr + 1, arg));
                 }
-            } else if RETURN.is_match(curln) {
+            } else if curln.starts_with("(Ooh) Never gonna give") && RETURN.is_match(curln) {
                 // ^\\(Ooh\\) Never gonna give, never gonna give \\(give you .+\\)$
-                let expr = String::from(&curln[51..(curln.len() - 1)]);
-                let tokens = self.wrap_check(ExprLexer::new(expr, self.ptr + 1).make_tokens())?;
+                let expr = &curln[51..(curln.len() - 1)];
+                let tokens = self.lex_expr(&logical, expr)?;
                 self.lexed
                     .push(Token::Statement(self.ptr + 1, String::from("RETURN")));
                 for token in tokens {
                     self.lexed.push(token);
                 }
+            } else if curln.starts_with("We've been waiting for ") && SLEEP.is_match(curln) {
+                // ^We've been waiting for .+$
+                let expr = &curln[23..];
+                let tokens = self.lex_expr(&logical, expr)?;
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("SLEEP")));
+                for token in tokens {
+                    self.lexed.push(token);
+                }
+            } else if let Some(captures) = RAISE.captures(curln) {
+                // ^You wouldn't get this from any other (\\w+) (.+)$
+                let tokens = self.lex_expr(&logical, captures.get(2).unwrap().as_str())?;
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("RAISE")));
+                // any other guy raises an error of its own type, anything else names the type
+                let type_name = match &captures[1] {
+                    "guy" => ErrorType::UserError.name(),
+                    other => String::from(other),
+                };
+                self.lexed.push(Token::Name(self.ptr + 1, type_name));
+                self.lexed.extend(tokens);
+            } else if let Some(captures) = EXPLAIN.captures(curln) {
+                // ^And if you ask me how (.+) is feeling$
+                let tokens = self.lex_expr(&logical, captures.get(1).unwrap().as_str())?;
+                self.lexed
+                    .push(Token::Statement(self.ptr + 1, String::from("EXPLAIN")));
+                self.lexed.extend(tokens);
             } else {
                 // unknown statement
-                return Err(Error::new(
-                    ErrorType::SyntaxError,
-                    "Illegal statement",
-                    Some(self.ptr + 1),
-                ));
+                // a statement cut off before its expression or a confusable character are likely
+                // reasons for a line not to match
+                let confused = curln.chars().find_map(|chr| confusable(chr).map(|found| (chr, found)));
+                let err = if MISSING_EXPR.is_match(curln) {
+                    Error::from_message(
+                        ErrorType::SyntaxError,
+                        "missing-expression",
+                        &[("statement", &curln)],
+                        Some(self.ptr + 1),
+                    )
+                } else if let Some((chr, (name, ascii))) = confused {
+                    Error::from_message(
+                        ErrorType::SyntaxError,
+                        "illegal-statement-confusable",
+                        &[
+                            ("char", &quote_char(chr)),
+                            ("code", &format!("U+{:04X}", chr as u32)),
+                            ("name", &name),
+                            ("suggestion", &quote_char(ascii)),
+                        ],
+                        Some(self.ptr + 1),
+                    )
+                } else {
+                    Error::from_message(ErrorType::SyntaxError, "illegal-statement", &[], Some(self.ptr + 1))
+                };
+                if !self.lenient {
+                    return Err(err);
+                }
+                self.diagnostics.push(err);
+            }
+            self.ptr += 1 + extra;
+        }
+        return Ok((self.lexed, self.diagnostics));
+    }
+
+    // checks on its own whether a line is a statement, and gets the statement's keyword
+    // blank lines aren't statements, so they give None
+    // errors are on line 1, and names aren't checked since that needs the rest of the program
+    pub fn validate_line(line: &str) -> Result<Option<&'static str>, Error> {
+        let curln = line.trim();
+        if curln.is_empty() {
+            return Ok(None);
+        }
+        match match_statement(curln) {
+            // a verse is followed by its parameters on the next line, so each is checked alone
+            Some(("VERSE", _)) => return Ok(Some("VERSE")),
+            Some(("ARGS", captures)) => {
+                Lexer::new(String::new()).split_params(line, &captures[1])?;
+                return Ok(Some("ARGS"));
             }
-            self.ptr += 1;
+            _ => (),
+        }
+        check_exprs(Lexer::new(String::from(line)).parse()?)?;
+        return match match_statement(curln) {
+            Some((keyword, _)) => Ok(Some(keyword)),
+            None => Err(Error::from_message(ErrorType::SyntaxError, "illegal-statement", &[], Some(1))),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_source, Lexer, Token};
+    use crate::error::{Error, ErrorType};
+    use crate::expr::Expr;
+    use crate::interpreter::Interpreter;
+    use crate::parser::{ASTNode, Parser};
+    use crate::testing::*;
+    use crate::util::{to_char_array, RickrollObject};
+
+    use std::io::Cursor;
+
+    // the warnings about a program, as they are shown
+    fn warnings(src: &str) -> Vec<String> {
+        let (_, diagnostics) = Lexer::new(String::from(src)).parse_with_diagnostics().unwrap();
+        return diagnostics.iter().map(|warning| warning.to_string()).collect();
+    }
+
+    const MIXED: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Inside we both know a < 3
+    Never gonna say a
+\tNever gonna give a a + 1
+    Inside we both know a == 2
+  Never gonna say 0
+    Your heart's been aching but you're too shy to say it
+We know the game and we're gonna play it
+";
+
+    #[test]
+    fn indentation_warnings() {
+        // the tab on line 6, and line 8 that is indented less than the statement opening its block
+        assert_eq!(
+            warnings(MIXED),
+            [
+                "Syntax Error on line 6: Indentation mixes tabs and spaces in the block opened at line 4",
+                "Syntax Error on line 8: Line is indented less than the block opened at line 7",
+            ]
+        );
+        // the program still runs
+        run_and_expect(MIXED, "", "1\n0\n2\n");
+    }
+
+    #[test]
+    fn format_source_indentation() {
+        let formatted = format_source(MIXED, "  ");
+        assert!(warnings(&formatted).is_empty());
+        assert_eq!(format_source(&formatted, "  "), formatted);
+        assert_eq!(
+            formatted.lines().nth(7),
+            Some("    Never gonna say 0")
+        );
+        run_and_expect(&formatted, "", "1\n0\n2\n");
+        // consistently indented programs are left alone
+        let consistent = "\
+[Verse f]
+(Ooh give you up)
+Never gonna make you cry
+\tNever gonna say \"\"\"a
+  b\"\"\"
+Never gonna tell a lie and hurt err
+\tNever gonna say err
+Gotta make you understand
+
+[Chorus]
+Never gonna run f and desert up
+";
+        assert!(warnings(consistent).is_empty());
+        assert_eq!(format_source(consistent, "\t"), consistent);
+    }
+
+    #[test]
+    fn default_parameters() {
+        let src = "\
+[Verse add]
+(Ooh give you first, second = 10, third = TRUE)
+Never gonna say first + second
+Never gonna say third
+
+[Chorus]
+Never gonna let a down
+Never gonna give a 5
+Never gonna let b down
+Never gonna give b 1
+Never gonna run add and desert a
+Never gonna run add and desert a, b
+";
+        run_and_expect(src, "", "15\nTRUE\n6\nTRUE\n");
+    }
+
+    #[test]
+    fn default_parameters_errors() {
+        let after_default = "[Verse f]\n(Ooh give you a = 1, b)\nNever gonna say a\n\n[Chorus]\n";
+        let err = run_source(after_default, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.root().message_id(), Some("missing-default"));
+
+        let not_constant = "[Verse f]\n(Ooh give you a, b = a)\nNever gonna say a\n\n[Chorus]\n";
+        let err = run_source(not_constant, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.root().message_id(), Some("default-not-constant"));
+
+        let too_many = "\
+[Verse f]
+(Ooh give you a, b = 2)
+Never gonna say a
+
+[Chorus]
+Never gonna let x down
+Never gonna run f and desert x, x, x
+";
+        let err = run_source(too_many, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IllegalArgumentError);
+    }
+
+    #[test]
+    fn missing_argument_list() {
+        // at the end of the file, and before another line
+        for src in ["[Chorus]\nNever gonna say 1\n[Verse f]", "[Verse f]\nNever gonna say 1\n"] {
+            let err = Lexer::new(String::from(src)).parse().unwrap_err();
+            assert_eq!(err.kind(), ErrorType::SyntaxError, "{}", src);
+            assert_eq!(err.root().message_id(), Some("missing-argument-list"), "{}", src);
+        }
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let src = "\
+[Verse add]
+(Ooh give you a, b,)
+(Ooh) Never gonna give, never gonna give (give you a + b)
+
+[Chorus]
+Never gonna let x down
+Never gonna give x 2
+Never gonna let y down
+(Ooh give you y) Never gonna run add and desert x, x,
+Never gonna say y
+";
+        run_and_expect(src, "", "4\n");
+    }
+
+    #[test]
+    fn doubled_and_leading_commas() {
+        let cases = [
+            ("Never gonna run f and desert x,, x", "doubled-comma"),
+            ("Never gonna run f and desert , x", "leading-comma"),
+            ("(Ooh give you a,, b)", "doubled-comma"),
+            ("(Ooh give you , a)", "leading-comma"),
+        ];
+        for (line, message) in cases {
+            let block = if line.starts_with("(Ooh give you") { "[Verse f]" } else { "[Chorus]" };
+            let src = format!("{}\n{}\n", block, line);
+            let err = Lexer::new(src).parse().unwrap_err();
+            assert_eq!(err.kind(), ErrorType::SyntaxError, "{}", line);
+            assert_eq!(err.root().message_id(), Some(message), "{}", line);
+        }
+    }
+
+    #[test]
+    fn spaces_around_say() {
+        run_and_expect("[Chorus]\nNever gonna say     2 + 2\n", "", "4\n");
+        run_and_expect("[Chorus]\nNever gonna say 2 + 2     \n", "", "4\n");
+        run_and_expect("[Chorus]\nNever gonna say \t2 + 2 \t\n", "", "4\n");
+        // spaces in literals are kept
+        run_and_expect("[Chorus]\nNever gonna say   \" a \"   \n", "", "[ , a,  ]\n");
+        run_and_expect("[Chorus]\nNever gonna say   ' '\n", "", " \n");
+    }
+
+    #[test]
+    fn empty_say() {
+        for line in ["Never gonna say", "Never gonna say ", "Never gonna say   \t", "    Never gonna say"] {
+            let src = format!("[Chorus]\nNever gonna say 1\n{}\n", line);
+            let err = Lexer::new(src).parse().unwrap_err();
+            assert_eq!(err.kind(), ErrorType::SyntaxError, "{:?}", line);
+            assert_eq!(err.to_string(), "Syntax Error on line 3: Say requires an expression", "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn adjacent_strings() {
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a \"Hi\" \"!\"\nNever gonna say a\nNever gonna say \"a\"  \"b\" \"\"\n";
+        run_and_expect(src, "", "[H, i, !]\n[a, b]\n");
+        // the literals are joined before the program is optimized or run
+        let parsed = Parser::new(Lexer::new(String::from(src)).parse().unwrap()).parse().unwrap();
+        match &parsed["[CHORUS]"] {
+            ASTNode::Function(_, _, _, _, body) => match &body[3] {
+                ASTNode::Say(_, Expr::Value(value)) => assert_eq!(value.to_string(), "[a, b]"),
+                node => panic!("expected a print statement of a value, got {:?}", node),
+            },
+            _ => panic!("program has no chorus"),
         }
-        return Ok(self.lexed);
+    }
+
+    #[test]
+    fn multi_line_string() {
+        let src = "\
+[Chorus]
+Never gonna say \"\"\"one
+two
+three\"\"\"
+Never gonna say 1 / 0
+";
+        let err = run_source(src, "").unwrap_err();
+        // the statement after the string keeps its own line
+        assert_eq!(err.to_string(), "Runtime Error: Division by zero\nTraceback on line 5");
+        run_and_expect(
+            &src.replace("Never gonna say 1 / 0\n", ""),
+            "",
+            "[o, n, e, \n, t, w, o, \n, t, h, r, e, e]\n",
+        );
+    }
+
+    #[test]
+    fn unterminated_multi_line_string() {
+        let src = "[Chorus]\nNever gonna say 1\nNever gonna say \"\"\"one\ntwo\nNever gonna say 2\n";
+        let err = Lexer::new(String::from(src)).parse().unwrap_err();
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.root().message_id(), Some("unterminated-multi-line-string"));
+        assert_eq!(err.to_string(), "Syntax Error on line 3: Unterminated multi-line string");
+    }
+
+    // doubles a number, or returns 0 for negative numbers and a string for 1
+    const GUARDED: &str = "\
+[Verse Double]
+(Ooh give you n)
+Inside we both know n < 0, never gonna give (give you 0)
+Inside we both know n == 1, never gonna give (give you \"a, never gonna give (give you b)\")
+(Ooh) Never gonna give, never gonna give (give you n * 2)
+[Chorus]
+Never gonna let n down
+Never gonna let res down
+(Ooh give you n) Never gonna run ReadToken and desert you
+(Ooh give you res) Never gonna run Double and desert n
+Never gonna say res
+";
+
+    #[test]
+    fn guard_returns_early() {
+        run_and_expect(GUARDED, "-5", "0\n");
+        // the separator inside a string literal doesn't split the guard
+        run_and_expect(GUARDED, "1", "[a, ,,  , n, e, v, e, r,  , g, o, n, n, a,  , g, i, v, e,  , (, g, i, v, e,  , y, o, u,  , b, )]\n");
+    }
+
+    #[test]
+    fn guard_falls_through() {
+        run_and_expect(GUARDED, "0", "0\n");
+        run_and_expect(GUARDED, "21", "42\n");
+    }
+
+    // a single print statement of 50MB, most of it spaces after the expression
+    fn long_line() -> String {
+        let mut src = String::from("[Chorus]\nNever gonna say 1 + 2");
+        src.push_str(&" ".repeat(50_000_000));
+        src.push('\n');
+        return src;
+    }
+
+    #[test]
+    fn very_long_line() {
+        let start = std::time::Instant::now();
+        run_and_expect(&long_line(), "", "3\n");
+        // a string literal of 1MB
+        let src = format!("[Chorus]\nNever gonna say \"{}\"\n", "a".repeat(1_000_000));
+        // printed as "[a, a, ... a]"
+        assert_eq!(run_source(&src, "").unwrap().len(), 3_000_001);
+        // takes well under a second in release builds, this allows for slow debug builds
+        assert!(start.elapsed().as_secs() < 20, "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn line_length_limit() {
+        let mut lexer = Lexer::new(long_line());
+        lexer.set_max_line_length(Some(1000));
+        let err = lexer.parse().unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(err.to_string(), "Resource Limit on line 2: Line is 50000021 bytes long, more than the limit of 1000");
+    }
+
+    // columns of errors in expressions count from the start of their line, whatever statement they are in
+    #[test]
+    fn expression_error_columns() {
+        let lines = [
+            "Never gonna say 1 + $",
+            "    Never gonna say 1 + $",
+            "Never gonna give a 1 + $",
+            "Inside we both know a < $",
+            "Inside we both know $, never gonna give (give you 1)",
+            "Inside we both know a, never gonna give (give you $)",
+            "(Ooh) Never gonna give, never gonna give (give you $)",
+            "We've known each x in $ for so long",
+            "We've been waiting for $",
+            "You wouldn't get this from any other guy \"a\" + $",
+            "And if you ask me how $ is feeling",
+            "(Ooh give you a, b = 1, c = 2 + $)",
+        ];
+        for line in lines {
+            let block = if line.starts_with("(Ooh give you") { "[Verse f]" } else { "[Chorus]" };
+            let src = format!("{}\n{}\n", block, line);
+            let err = Lexer::new(src).parse().unwrap_err();
+            assert_eq!(err.kind(), ErrorType::IllegalCharError, "{}", line);
+            let column = line.find('$').unwrap() + 1;
+            assert!(err.root().params().contains(&(String::from("column"), column.to_string())), "{}", line);
+        }
+    }
+
+    // has a line in the middle of its chorus and one inside a loop that aren't statements
+    const BROKEN: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Never gonna frobnicate a
+Inside we both know a < 3
+    Never gonna say a
+    Gonna say a lot
+    Never gonna give a a + 1
+We know the game and we're gonna play it
+Never gonna say 10
+";
+
+    fn lenient(src: &str) -> (Vec<Token>, Vec<Error>) {
+        let mut lexer = Lexer::new(String::from(src));
+        lexer.set_lenient(true);
+        return lexer.parse_with_diagnostics().unwrap();
+    }
+
+    #[test]
+    fn lenient_skips_illegal_statements() {
+        let (tokens, diagnostics) = lenient(BROKEN);
+        let diagnostics: Vec<String> = diagnostics.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            diagnostics,
+            ["Syntax Error on line 4: Illegal statement", "Syntax Error on line 7: Illegal statement"]
+        );
+        // the statements around the skipped lines still compile and run
+        let mut interpreter = Interpreter::new(Parser::new(tokens).parse().unwrap());
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1\n2\n10\n");
+    }
+
+    #[test]
+    fn strict_stops_at_illegal_statements() {
+        let err = Lexer::new(String::from(BROKEN)).parse().unwrap_err();
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.to_string(), "Syntax Error on line 4: Illegal statement");
+        // other errors still stop a lenient lexer
+        let mut lexer = Lexer::new(format!("{}Never gonna say 1 + $\n", BROKEN));
+        lexer.set_lenient(true);
+        assert_eq!(lexer.parse().unwrap_err().kind(), ErrorType::IllegalCharError);
+    }
+
+    #[test]
+    fn validate_each_statement() {
+        let lines = [
+            ("SAY", "Never gonna say 1 + 2"),
+            ("LET", "Never gonna let a down"),
+            ("ASSIGN", "Never gonna give a 3 * 4"),
+            ("EXPECT", "You know the rules and a is INT"),
+            ("GUARD", "Inside we both know n < 2, never gonna give (give you n)"),
+            ("CHECK", "Inside we both know a < 10"),
+            ("FOREACH", "We've known each name, ind in names for so long"),
+            ("WHILE_END", "We know the game and we're gonna play it"),
+            ("IF_END", "Your heart's been aching but you're too shy to say it"),
+            ("TRY", "Never gonna make you cry"),
+            ("CATCH", "Never gonna tell a TypeError and hurt err"),
+            ("TRY_END", "Gotta make you understand"),
+            ("CAPTURE", "I just wanna tell out how I'm feeling"),
+            ("CAPTURE_END", "Don't tell me you're too blind to see"),
+            ("CHORUS", "[Chorus]"),
+            ("INTRO", "[Intro]"),
+            ("VERSE", "[Verse add]"),
+            ("RUN_ASSIGN", "(Ooh give you sum) Never gonna run add and desert a, b"),
+            ("ARGS", "(Ooh give you a, b = 1)"),
+            ("RUN", "Never gonna run add and desert you"),
+            ("RETURN", "(Ooh) Never gonna give, never gonna give (give you a + b)"),
+            ("SLEEP", "We've been waiting for 500"),
+            ("RAISE", "You wouldn't get this from any other guy \"Invalid input\""),
+            ("EXPLAIN", "And if you ask me how 7 / 2 is feeling"),
+        ];
+        for (keyword, line) in lines {
+            assert_eq!(Lexer::validate_line(line).unwrap(), Some(keyword), "{}", line);
+            // indentation doesn't matter
+            assert_eq!(Lexer::validate_line(&format!("    {}", line)).unwrap(), Some(keyword), "{}", line);
+        }
+        assert_eq!(Lexer::validate_line("").unwrap(), None);
+        assert_eq!(Lexer::validate_line("   \t").unwrap(), None);
+    }
+
+    #[test]
+    fn validate_malformed_lines() {
+        let lines = [
+            ("Never gonna sya 1", ErrorType::SyntaxError, "illegal-statement"),
+            ("Never gonna say", ErrorType::SyntaxError, "empty-say"),
+            ("Never gonna give a", ErrorType::SyntaxError, "missing-expression"),
+            ("We've been waiting for", ErrorType::SyntaxError, "missing-expression"),
+            ("Never gonna say 1 2", ErrorType::SyntaxError, "trailing-characters"),
+            ("Inside we both know (1 < 2", ErrorType::SyntaxError, "unclosed-parenthesis"),
+            ("Never gonna say \"abc", ErrorType::IllegalCharError, "unterminated-string"),
+            ("Never gonna say 1 $ 2", ErrorType::IllegalCharError, "illegal-char"),
+            ("(Ooh give you a, , b)", ErrorType::SyntaxError, "doubled-comma"),
+            ("(Ooh give you a = 1, b)", ErrorType::SyntaxError, "missing-default"),
+        ];
+        for (line, kind, id) in lines {
+            let err = Lexer::validate_line(line).unwrap_err();
+            assert_eq!(err.kind(), kind, "{}", line);
+            assert_eq!(err.root().message_id(), Some(id), "{}", line);
+        }
+        assert_eq!(
+            Lexer::validate_line("Never gonna give a").unwrap_err().to_string(),
+            "Syntax Error on line 1: Never gonna give a requires an expression"
+        );
+        // errors in expressions point at their column
+        let err = Lexer::validate_line("Never gonna say 1 $ 2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Illegal Character: Illegal character \"$\" in expression at column 19\nTraceback on line 1"
+        );
+    }
+
+    fn run_defined(src: &str, defines: &[(&str, RickrollObject)]) -> Result<String, Error> {
+        let mut lexer = Lexer::new(String::from(src));
+        lexer.set_defines(defines.iter().map(|(name, value)| (String::from(*name), value.clone())).collect());
+        let mut interpreter = Interpreter::new(Parser::new(lexer.parse()?).parse()?);
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new()))?;
+        return Ok(String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn number_defines() {
+        let defines = [("COUNT", RickrollObject::Int(2)), ("LOW", RickrollObject::Int(-2))];
+        let src = "[Chorus]\nNever gonna say ${COUNT} + 1\nNever gonna say ${LOW} * 3\nNever gonna say \"n=${COUNT}, ${LOW}\"\n";
+        assert_eq!(run_defined(src, &defines).unwrap(), "3\n-6\n[n, =, 2, ,,  , -, 2]\n");
+    }
+
+    #[test]
+    fn string_defines() {
+        let defines = [("WHO", to_char_array("world"))];
+        let src = "[Chorus]\nNever gonna say ${WHO}\nNever gonna say \"Hi ${WHO}!\"\n";
+        assert_eq!(run_defined(src, &defines).unwrap(), "[w, o, r, l, d]\n[H, i,  , w, o, r, l, d, !]\n");
+    }
+
+    #[test]
+    fn undefined_placeholder() {
+        let src = "[Chorus]\nNever gonna say ${COUNT}\nNever gonna say ${WHO}\n";
+        let err = run_defined(src, &[("COUNT", RickrollObject::Int(2))]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.to_string(), "Name Error on line 3: Placeholder ${WHO} is not defined");
+        // without any defines, placeholders are left as they are written
+        assert_eq!(run_defined("[Chorus]\nNever gonna say \"${WHO}\"\n", &[]).unwrap(), "[$, {, W, H, O, }]\n");
+    }
+
+    #[test]
+    fn defines_stay_in_their_literal() {
+        let text = "\" + 1\nNever gonna say 666 \\";
+        let printed = format!("{}\n", to_char_array(text));
+        let defines = [("EVIL", to_char_array(text))];
+        assert_eq!(run_defined("[Chorus]\nNever gonna say ${EVIL}\n", &defines).unwrap(), printed);
+        assert_eq!(run_defined("[Chorus]\nNever gonna say \"${EVIL}\"\n", &defines).unwrap(), printed);
     }
 }
 
@@ -1,7 +1,15 @@
+pub mod capabilities;
+pub mod decompiler;
+pub mod docs;
 pub mod error;
 pub mod expr;
 pub mod interpreter;
 pub mod lexer;
+pub mod optimizer;
 pub mod parser;
+pub mod random;
+pub mod refactor;
+pub mod replay;
 pub mod util;
 pub mod stdlib;
+pub mod testing;
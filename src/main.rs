@@ -1,80 +1,462 @@
-use rickroll::lexer::Lexer;
+use rickroll::capabilities::capabilities;
+use rickroll::decompiler::decompile;
+use rickroll::lexer::{format_source, Lexer, PREFIX_PRESETS};
+use rickroll::error::{parse_catalog, render_message, set_locale_catalog, ErrorType};
+use rickroll::optimizer::{Optimizer, Pass, DEFAULT_PASSES};
 use rickroll::parser::Parser;
-use rickroll::interpreter::Interpreter;
+use rickroll::replay::Recording;
+use rickroll::util::{fix_confusables, from_constant, parse_number, to_char_array, PrintWords, RickrollObject};
+use rickroll::interpreter::{AuditMode, ExplainStream, Interpreter, Newline, OutputLimit, RunStats, STACK_SIZE};
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::*;
 use std::path::PathBuf;
 
 use structopt::StructOpt;
 use ansi_term::Colour::Red;
+use log::{debug, LevelFilter, Log, Metadata, Record};
 
 #[derive(StructOpt, Debug)]
 struct Opt {
     #[structopt(short, long, about="Print debugging information")]
     debug: bool,
+    #[structopt(long, about="Raise an error when arithmetic produces NaN or infinity")]
+    float_trap: bool,
+    #[structopt(long, about="Warn on stderr when an integer division drops a remainder")]
+    warn_division: bool,
+    #[structopt(long, about="Seed the random number generator so runs give the same results")]
+    seed: Option<u64>,
+    #[structopt(long, parse(from_os_str), about="Save the input, time, random numbers and environment variables the program read to this file")]
+    record: Option<PathBuf>,
+    #[structopt(long, parse(from_os_str), about="Give the program the values saved by --record in this file instead of reading them")]
+    replay: Option<PathBuf>,
+    #[structopt(long, about="Stop while loops that run again without changing any variables")]
+    detect_loops: bool,
+    #[structopt(long, about="Forbid built-in functions that access the environment, and pausing")]
+    sandbox: bool,
+    #[structopt(long, about="Print the calls to privileged functions denied by the sandbox to stderr")]
+    audit: bool,
+    #[structopt(long, about="Also print the privileged calls that were allowed, implies --audit")]
+    audit_all: bool,
+    #[structopt(long, number_of_values=1, about="Replace ${NAME} in the program with a value, given as NAME=VALUE")]
+    define: Vec<String>,
+    #[structopt(long, about="Remove a prefix matching this pattern from each line, or one of the presets numbered, quote and timestamp")]
+    strip_prefix: Option<String>,
+    #[structopt(long, about="Reject programs with lines longer than this many bytes")]
+    max_line_length: Option<usize>,
+    #[structopt(long, about="Reject programs with more statements than this")]
+    max_statements: Option<usize>,
+    #[structopt(long, about="Stop a built-in function call that would make or go over more than this many elements")]
+    max_statement_work: Option<usize>,
+    #[structopt(long, about="Stop a built-in function call that would make or grow an array to more than this many elements")]
+    max_array_length: Option<usize>,
+    #[structopt(long, about="Allow calling functions and using Intro variables before they are declared")]
+    two_pass: bool,
+    #[structopt(long, about="Skip lines that aren't statements, printing their errors as warnings")]
+    lenient: bool,
+    #[structopt(long, about="Print the program with look-alike characters replaced by ASCII instead of running it")]
+    fix_confusables: bool,
+    #[structopt(long, about="Print the program with its blocks indented consistently instead of running it")]
+    format: bool,
+    #[structopt(long, default_value="2", about="Spaces added by --format for each block a statement is in")]
+    indent_width: usize,
+    #[structopt(long, about="Print what the program could do instead of running it")]
+    capabilities: bool,
+    #[structopt(long, about="Print the value of the file read as a single expression instead of running it")]
+    eval: bool,
+    #[structopt(long, about="Run the program exactly as it is written, without optimizing it")]
+    no_optimize: bool,
+    #[structopt(long, number_of_values=1, about="Skip an optimizer pass: constant-folding, constant-propagation, dead-code, peephole or merge-says")]
+    disable_pass: Vec<String>,
+    #[structopt(long, about="Print the program as rickroll source instead of running it")]
+    decompile: bool,
+    #[structopt(long, default_value="0", about="Show this many recently executed statements with errors")]
+    history: usize,
+    #[structopt(long, about="Stop writing output after this many bytes")]
+    max_output: Option<usize>,
+    #[structopt(long, about="Raise an error instead of continuing when the output limit is reached")]
+    max_output_error: bool,
+    #[structopt(long, about="Wrap printed lines longer than this many characters, or auto for the width of the terminal")]
+    wrap: Option<String>,
+    #[structopt(long, about="End printed lines with \\r\\n instead of \\n")]
+    crlf: bool,
+    #[structopt(long, about="Print the values and types shown by And if you ask me how EXPR is feeling to stderr")]
+    explain_to_stderr: bool,
+    #[structopt(long, default_value="TRUE", about="Word printed for TRUE")]
+    true_word: String,
+    #[structopt(long, default_value="FALSE", about="Word printed for FALSE")]
+    false_word: String,
+    #[structopt(long, default_value="UNDEFINED", about="Word printed for UNDEFINED")]
+    undefined_word: String,
+    #[structopt(long, about="Only print the output if the program finishes without an error")]
+    atomic_output: bool,
+    #[structopt(long, about="Explain what causes errors and how to fix them")]
+    explain: bool,
+    #[structopt(long, parse(from_os_str), about="Show errors and warnings with the translated messages in this file")]
+    messages: Option<PathBuf>,
+    #[structopt(long, about="Print the lines of the statements that were executed to stderr")]
+    coverage: bool,
+    #[structopt(long, about="Print a summary of the run to stderr")]
+    summary: bool,
+    // - reads the program from stdin
     #[structopt(parse(from_os_str))]
     file: PathBuf,
 }
 
-fn execute(file: PathBuf, debug: bool) -> std::result::Result<(), Error> {
+// writer counting the lines written through it
+struct LineCounter<'a> {
+    inner: &'a mut dyn Write,
+    lines: usize,
+}
+
+impl Write for LineCounter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|byte| **byte == b'\n').count();
+        return Ok(written);
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+// reads a value given on the command line as a number, a constant, or otherwise a string
+fn parse_define(value: &str) -> RickrollObject {
+    if let Some(obj) = from_constant(&String::from(value)) {
+        return obj;
+    }
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    return match parse_number(digits) {
+        Ok(RickrollObject::Int(x)) if negative => RickrollObject::Int(-x),
+        Ok(RickrollObject::Float(x)) if negative => RickrollObject::Float(-x),
+        Ok(num) => num,
+        Err(_) => to_char_array(value),
+    };
+}
+
+// width used by --wrap auto when the terminal doesn't say
+const DEFAULT_WRAP_WIDTH: usize = 80;
+
+// gets the width to wrap the output at from --wrap
+// auto only wraps output shown in a terminal, using the COLUMNS it sets
+fn wrap_width(value: &str) -> std::result::Result<Option<usize>, rickroll::error::Error> {
+    if value == "auto" {
+        if !stdout().is_terminal() {
+            return Ok(None);
+        }
+        let columns = std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok());
+        return Ok(Some(columns.unwrap_or(DEFAULT_WRAP_WIDTH)));
+    }
+    return match value.parse::<usize>() {
+        Ok(width) if width > 0 => Ok(Some(width)),
+        _ => Err(rickroll::error::Error::from_message(
+            ErrorType::ValueError,
+            "invalid-wrap-width",
+            &[("value", &value)],
+            None,
+        )),
+    };
+}
+
+fn report(e: &rickroll::error::Error, explain: bool) {
+    eprintln!("{}", Red.paint(format!("{}", e)));
+    if explain {
+        eprintln!("{}", e.explanation());
+    }
+}
+
+fn print_summary(interpreter: &Interpreter, stats: &RunStats, lines: usize, failed: bool) {
+    let mut res = String::new();
+    if failed {
+        res += &render_message("summary-failed", &[]);
+    } else {
+        res += &render_message("summary", &[]);
+    }
+    res += "\n";
+    res += &render_message("summary-statements", &[("count", &stats.statements)]);
+    res += "\n";
+    res += &render_message("summary-lines", &[("count", &lines)]);
+    res += "\n";
+    res += &render_message("summary-bytes", &[("count", &stats.output_bytes)]);
+    res += "\n";
+    if stats.output_truncated {
+        res += &render_message("summary-truncated", &[]);
+        res += "\n";
+    }
+    res += &render_message("summary-depth", &[("depth", &stats.max_depth)]);
+    res += "\n";
+    let millis = format!("{:.3}", stats.elapsed.as_secs_f64() * 1000.0);
+    res += &render_message("summary-time", &[("millis", &millis)]);
+    res += "\n";
+    res += &render_message("summary-globals", &[]);
+    for (name, value) in interpreter.globals().iter() {
+        res += &format!("\n  {} = {}", name, value);
+    }
+    eprintln!("{}", Red.paint(res));
+}
+
+// prints debugging information to stderr, so it is never mixed into the program's output
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!("{}", Red.paint(format!("{}", record.args())));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn execute(opt: Opt) -> std::result::Result<(), Error> {
+    let debug = opt.debug;
+    if debug && log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+    if let Some(path) = &opt.messages {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        match parse_catalog(&text) {
+            Ok(catalog) => set_locale_catalog(catalog),
+            Err(e) => {
+                report(&e, opt.explain);
+                return Ok(());
+            }
+        }
+    }
     // read from file
-    let mut f = File::open(file)?;
     let mut raw = String::new();
-    f.read_to_string(&mut raw)?;
+    if opt.file.as_os_str() == "-" {
+        stdin().read_to_string(&mut raw)?;
+    } else {
+        File::open(opt.file)?.read_to_string(&mut raw)?;
+    }
+    if opt.eval {
+        let mut interpreter = Interpreter::new(HashMap::new());
+        interpreter.set_float_trap(opt.float_trap);
+        let words = PrintWords {
+            true_word: opt.true_word,
+            false_word: opt.false_word,
+            undefined_word: opt.undefined_word,
+        };
+        // the trailing newline of the file isn't part of the expression
+        match interpreter.eval_source(raw.trim()) {
+            Ok(obj) => println!("{}", obj.format_with(&words)),
+            Err(e) => report(&e, opt.explain),
+        }
+        return Ok(());
+    }
+    if opt.fix_confusables {
+        print!("{}", fix_confusables(&raw));
+        return Ok(());
+    }
+    if opt.format {
+        print!("{}", format_source(&raw, &" ".repeat(opt.indent_width)));
+        return Ok(());
+    }
     if debug {
         eprintln!("{}", Red.paint("Started lexing..."));
     }
-    let lexer = Lexer::new(raw);
-    let tokens = lexer.parse();
+    let mut lexer = Lexer::new(raw);
+    lexer.set_max_line_length(opt.max_line_length);
+    let prefix = opt.strip_prefix.as_deref().map(|prefix| {
+        match PREFIX_PRESETS.iter().find(|(name, _)| *name == prefix) {
+            Some((_, pattern)) => pattern,
+            None => prefix,
+        }
+    });
+    if let Err(e) = lexer.set_strip_prefix(prefix) {
+        report(&e, opt.explain);
+        return Ok(());
+    }
+    let mut defines = HashMap::new();
+    for define in &opt.define {
+        match define.split_once('=') {
+            Some((name, value)) => defines.insert(String::from(name), parse_define(value)),
+            None => defines.insert(define.clone(), RickrollObject::Bool(true)),
+        };
+    }
+    lexer.set_defines(defines);
+    lexer.set_lenient(opt.lenient);
+    let tokens = lexer.parse_with_diagnostics();
     match tokens {
         Err(e) => {
-            eprintln!("{}", Red.paint(format!("{}", e)));
+            report(&e, opt.explain);
             return Ok(());
         }
         _ => (),
     };
-    let tokens = tokens.unwrap();
+    let (tokens, diagnostics) = tokens.unwrap();
+    for diagnostic in diagnostics {
+        eprintln!("{}", Red.paint(render_message("warning", &[("message", &diagnostic)])));
+    }
     if debug {
-        println!("{:?}", tokens);
+        debug!("{:?}", tokens);
         eprintln!("{}", Red.paint("Finished lexing..."));
         eprintln!("{}", Red.paint("Started parsing..."));
     }
-    let parser = Parser::new(tokens);
+    let mut parser = Parser::new(tokens);
+    parser.set_max_statements(opt.max_statements);
+    parser.set_two_pass(opt.two_pass);
     let parsed = parser.parse();
     match parsed {
         Err(e) => {
-            eprintln!("{}", Red.paint(format!("{}", e)));
+            report(&e, opt.explain);
             return Ok(());
         }
         _ => (),
     };
-    let parsed = parsed.unwrap();
+    let mut parsed = parsed.unwrap();
+    // checked before optimizing, so code that can never run is still reported
+    if opt.capabilities {
+        println!("{}", capabilities(&parsed));
+        return Ok(());
+    }
     if debug {
-        println!("{:?}", parsed);
+        debug!("{:?}", parsed);
         eprintln!("{}", Red.paint("Finished parsing..."));
+        eprintln!("{}", Red.paint("Started optimizing..."));
+    }
+    let mut disabled = Vec::new();
+    for name in &opt.disable_pass {
+        match Pass::from_name(name) {
+            Some(pass) => disabled.push(pass),
+            None => {
+                report(&rickroll::error::Error::from_message(
+                    ErrorType::ValueError,
+                    "unknown-pass",
+                    &[("name", &name)],
+                    None,
+                ), opt.explain);
+                return Ok(());
+            }
+        }
+    }
+    if opt.no_optimize {
+        disabled = DEFAULT_PASSES.to_vec();
+    }
+    Optimizer::without_passes(&disabled).run(&mut parsed);
+    if debug {
+        debug!("{:?}", parsed);
+        eprintln!("{}", Red.paint("Finished optimizing..."));
+    }
+    if opt.decompile {
+        match decompile(&parsed) {
+            Ok(source) => print!("{}", source),
+            Err(e) => report(&e, opt.explain),
+        }
+        return Ok(());
+    }
+    if debug {
         eprintln!("{}", Red.paint("Started executing..."));
     }
-    let mut interpreter = Interpreter::new(parsed);
-    let result = interpreter.run(&mut stdout(), &mut BufReader::new(stdin()));
-    match result {
+    let wrap = match opt.wrap.as_deref().map(wrap_width).transpose() {
+        Ok(wrap) => wrap.flatten(),
         Err(e) => {
-            eprintln!("{}", Red.paint(format!("{}", e)));
+            report(&e, opt.explain);
             return Ok(());
         }
-        _ => (),
+    };
+    let mut interpreter = Interpreter::new(parsed);
+    interpreter.set_wrap_output(wrap);
+    interpreter.set_float_trap(opt.float_trap);
+    interpreter.set_sandbox(opt.sandbox);
+    interpreter.set_loop_detection(opt.detect_loops);
+    interpreter.set_max_statement_work(opt.max_statement_work);
+    interpreter.set_max_array_length(opt.max_array_length);
+    interpreter.set_division_warnings(opt.warn_division);
+    interpreter.set_seed(opt.seed);
+    interpreter.set_recording(opt.record.is_some());
+    if let Some(path) = &opt.replay {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        match Recording::from_text(&text) {
+            Ok(recording) => interpreter.set_replay(Some(recording)),
+            Err(e) => {
+                report(&e, opt.explain);
+                return Ok(());
+            }
+        }
+    }
+    interpreter.set_print_words(PrintWords {
+        true_word: opt.true_word,
+        false_word: opt.false_word,
+        undefined_word: opt.undefined_word,
+    });
+    interpreter.set_newline(if opt.crlf { Newline::CrLf } else { Newline::Lf });
+    interpreter.set_explain_stream(if opt.explain_to_stderr { ExplainStream::Stderr } else { ExplainStream::Stdout });
+    interpreter.set_history_size(opt.history);
+    interpreter.set_coverage(opt.coverage);
+    interpreter.set_atomic_output(opt.atomic_output);
+    interpreter.set_audit_mode(if opt.audit_all { AuditMode::Everything } else { AuditMode::Denials });
+    interpreter.set_output_limit(match opt.max_output {
+        None => OutputLimit::Unlimited,
+        Some(cap) if opt.max_output_error => OutputLimit::Error(cap),
+        Some(cap) => OutputLimit::TruncateAndContinue(cap),
+    });
+    let mut out = stdout();
+    let mut counter = LineCounter {
+        inner: &mut out,
+        lines: 0,
+    };
+    let result = interpreter.run_with_stats(&mut counter, &mut BufReader::new(stdin()));
+    // a closed output stream is not an error
+    let _ = counter.flush();
+    // saved even when the program fails, so the failure can be replayed
+    if let (Some(path), Some(recording)) = (&opt.record, interpreter.recording()) {
+        File::create(path)?.write_all(recording.to_text().as_bytes())?;
+    }
+    for warning in interpreter.warnings() {
+        eprintln!("{}", Red.paint(render_message("warning", &[("message", &warning)])));
+    }
+    if opt.audit || opt.audit_all {
+        let mut res = render_message("audit-log", &[]);
+        for entry in interpreter.audit_log() {
+            res += &format!("\n  {}", entry);
+        }
+        eprintln!("{}", Red.paint(res));
+    }
+    if let Some(coverage) = interpreter.coverage() {
+        let lines: Vec<String> = coverage.iter().map(|line| line.to_string()).collect();
+        eprintln!("{}", Red.paint(render_message("executed-lines", &[("lines", &lines.join(", "))])));
+    }
+    let (val, stats) = match result {
+        Err(failed) => {
+            let (e, stats) = *failed;
+            report(&e, opt.explain);
+            if opt.summary {
+                print_summary(&interpreter, &stats, counter.lines, true);
+            }
+            return Ok(());
+        }
+        Ok(res) => res,
+    };
+    if opt.summary {
+        print_summary(&interpreter, &stats, counter.lines, false);
     }
     if debug {
-        println!(
-            "\n{:#?}",
-            result.unwrap()
-        );
+        debug!("{:#?}", val);
     }
     return Ok(());
 }
 
 fn main() -> std::result::Result<(), Error> {
     let opt = Opt::from_args();
-    execute(opt.file, opt.debug)?;
+    let runner = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || execute(opt))?;
+    match runner.join() {
+        Ok(res) => res?,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
     return Ok(());
 }
@@ -0,0 +1,751 @@
+use crate::expr::*;
+use crate::interpreter::Interpreter;
+use crate::parser::*;
+use crate::util::*;
+
+use log::debug;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    // evaluates operations whose operands are all values
+    ConstantFolding,
+    // replaces variables holding a value known from an earlier assignment with the value
+    ConstantPropagation,
+    // removes code that can never run
+    DeadCodeElimination,
+    // removes statements that have no effect
+    Peephole,
+    // joins print statements of constant values next to each other into one statement
+    MergeSays,
+}
+
+pub const DEFAULT_PASSES: [Pass; 5] = [
+    Pass::ConstantFolding,
+    Pass::ConstantPropagation,
+    Pass::DeadCodeElimination,
+    Pass::Peephole,
+    Pass::MergeSays,
+];
+
+impl Pass {
+    // gets the name the pass is chosen by on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::ConstantFolding => "constant-folding",
+            Pass::ConstantPropagation => "constant-propagation",
+            Pass::DeadCodeElimination => "dead-code",
+            Pass::Peephole => "peephole",
+            Pass::MergeSays => "merge-says",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        DEFAULT_PASSES.iter().find(|pass| pass.name() == name).copied()
+    }
+}
+
+#[derive(Debug)]
+pub struct Optimizer {
+    passes: Vec<Pass>,
+    // only used to evaluate constant expressions
+    folder: Interpreter,
+}
+
+// gets the blocks of statements directly inside a node
+// statements the passes don't know about have no blocks, so they are left exactly as they are
+fn blocks(node: &mut ASTNode) -> Vec<&mut Vec<ASTNode>> {
+    use ASTNode::*;
+    match node {
+        If(_, _, body)
+        | While(_, _, body)
+        | ForEach(_, _, _, _, body)
+        | Capture(_, _, body)
+        | Function(_, _, _, _, body) => {
+            vec![body]
+        }
+        Try(_, body, _, _, handler) => vec![body, handler],
+        _ => Vec::new(),
+    }
+}
+
+// gets the variables the statements may assign to, or None if they may assign to any variable
+// a function can assign to the variables of the chorus, and an in-place function to its argument,
+// so every call may assign to any variable
+fn assigned(nodes: &[ASTNode]) -> Option<HashSet<String>> {
+    use ASTNode::*;
+    let mut names = HashSet::new();
+    for node in nodes.iter().flat_map(|node| node.iter()) {
+        match node {
+            Assign(_, name, _) | Capture(_, name, _) => {
+                names.insert(name.clone());
+            }
+            Run(..) | RunAssign(..) => return None,
+            _ => (),
+        }
+    }
+    return Some(names);
+}
+
+// forgets the values of variables that may have been assigned to
+fn forget(known: &mut HashMap<String, RickrollObject>, names: Option<HashSet<String>>) {
+    match names {
+        Some(names) => known.retain(|name, _| !names.contains(name)),
+        None => known.clear(),
+    }
+}
+
+// replaces every variable with a known value in an expression
+fn replace_names(expr: &mut Expr, known: &HashMap<String, RickrollObject>) -> bool {
+    match expr {
+        Expr::Name(name) => match known.get(name) {
+            Some(obj) => {
+                *expr = Expr::Value(obj.clone());
+                return true;
+            }
+            None => return false,
+        },
+        Expr::Operation(_, args) => {
+            let mut changed = false;
+            for arg in args.iter_mut() {
+                changed |= replace_names(arg, known);
+            }
+            return changed;
+        }
+        Expr::Value(_) => return false,
+    }
+}
+
+fn is_constant_bool(expr: &Expr) -> bool {
+    matches!(expr, Expr::Value(RickrollObject::Bool(_)))
+}
+
+// checks if a statement only prints constant values
+fn is_constant_say(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::Say(_, Expr::Value(_)) | ASTNode::SayConstants(_, _))
+}
+
+// gets the values a statement printing constant values prints, with their lines
+fn constant_values(node: ASTNode) -> Vec<(usize, RickrollObject)> {
+    match node {
+        ASTNode::Say(ln, Expr::Value(obj)) => vec![(ln, obj)],
+        ASTNode::SayConstants(_, values) => values,
+        _ => panic!("constant_values called with a statement printing a non-constant value"),
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer::new()
+    }
+}
+
+impl Optimizer {
+    // makes an optimizer running the default passes
+    pub fn new() -> Optimizer {
+        Optimizer::with_passes(DEFAULT_PASSES.to_vec())
+    }
+
+    // makes an optimizer running the given passes in order
+    pub fn with_passes(passes: Vec<Pass>) -> Optimizer {
+        let mut folder = Interpreter::new(HashMap::new());
+        // non-finite results are left for the interpreter to trap
+        folder.set_float_trap(true);
+        // and divisions dropping a remainder for it to warn about
+        folder.set_division_warnings(true);
+        Optimizer { passes, folder }
+    }
+
+    // makes an optimizer running the default passes except the given ones
+    // without any passes, the program is left exactly as it was parsed
+    pub fn without_passes(disabled: &[Pass]) -> Optimizer {
+        Optimizer::with_passes(DEFAULT_PASSES.iter().filter(|pass| !disabled.contains(pass)).copied().collect())
+    }
+
+    pub fn passes(&self) -> &Vec<Pass> {
+        &self.passes
+    }
+
+    // runs the passes over every function until none of them change anything
+    // returns the number of rounds that changed the program
+    pub fn run(&self, functions: &mut HashMap<String, ASTNode>) -> usize {
+        let mut rounds = 0;
+        loop {
+            let mut changed = false;
+            for pass in &self.passes {
+                for (name, node) in functions.iter_mut() {
+                    if self.run_pass(*pass, node) {
+                        debug!("pass {} changed {} in round {}", pass.name(), name, rounds + 1);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                return rounds;
+            }
+            rounds += 1;
+        }
+    }
+
+    // runs a single pass over a function, returning whether it changed
+    fn run_pass(&self, pass: Pass, node: &mut ASTNode) -> bool {
+        match pass {
+            Pass::ConstantFolding => self.fold_node(node),
+            Pass::ConstantPropagation => match node {
+                ASTNode::Function(_, _, _, _, body) => self.propagate(body, &mut HashMap::new()),
+                _ => false,
+            },
+            Pass::DeadCodeElimination => {
+                let mut changed = false;
+                for body in blocks(node) {
+                    changed |= self.eliminate_dead(body);
+                }
+                changed
+            }
+            Pass::Peephole => {
+                let mut changed = false;
+                for body in blocks(node) {
+                    changed |= self.peephole(body);
+                }
+                changed
+            }
+            Pass::MergeSays => {
+                let mut changed = false;
+                for body in blocks(node) {
+                    changed |= self.merge_says(body);
+                }
+                changed
+            }
+        }
+    }
+
+    fn fold_node(&self, node: &mut ASTNode) -> bool {
+        use ASTNode::*;
+        let mut changed = match node {
+            Say(_, expr)
+            | Assign(_, _, expr)
+            | If(_, expr, _)
+            | While(_, expr, _)
+            | ForEach(_, _, _, expr, _)
+            | Return(_, expr)
+            | Sleep(_, expr)
+            | Raise(_, _, expr)
+            | Explain(_, expr) => {
+                self.fold_expr(expr)
+            }
+            Function(_, _, _, defaults, _) => {
+                let mut changed = false;
+                for default in defaults.iter_mut().flatten() {
+                    changed |= self.fold_expr(default);
+                }
+                changed
+            }
+            // expressions of other statements are never folded
+            _ => false,
+        };
+        for body in blocks(node) {
+            for child in body.iter_mut() {
+                changed |= self.fold_node(child);
+            }
+        }
+        return changed;
+    }
+
+    // folds an expression from the bottom up
+    fn fold_expr(&self, expr: &mut Expr) -> bool {
+        let mut changed = false;
+        if let Expr::Operation(op, args) = expr {
+            for arg in args.iter_mut() {
+                changed |= self.fold_expr(arg);
+            }
+            // indexing is left to the interpreter
+            if *op == Operator::ArrayAccess {
+                return changed;
+            }
+            if !args.iter().all(|arg| matches!(arg, Expr::Value(_))) {
+                return changed;
+            }
+            // operations that fail are kept so the error happens at runtime
+            let res = self.folder.eval(expr, &Scope::new());
+            if !self.folder.take_warnings().is_empty() {
+                return changed;
+            }
+            if let Ok(obj) = res {
+                *expr = Expr::Value(obj);
+                changed = true;
+            }
+        }
+        return changed;
+    }
+
+    // replaces variables with known values in an expression, where that leaves a value
+    // an operation keeps its variables unless it can then be folded, so errors at runtime still
+    // name the variables they come from
+    fn substitute(&self, expr: &mut Expr, known: &HashMap<String, RickrollObject>) -> bool {
+        if let Expr::Operation(_, _) = expr {
+            let mut folded = expr.clone();
+            if replace_names(&mut folded, known) {
+                self.fold_expr(&mut folded);
+                if let Expr::Value(_) = folded {
+                    *expr = folded;
+                    return true;
+                }
+            }
+        }
+        match expr {
+            Expr::Name(_) => return replace_names(expr, known),
+            Expr::Operation(_, args) => {
+                let mut changed = false;
+                for arg in args.iter_mut() {
+                    if let Expr::Operation(_, _) = arg {
+                        changed |= self.substitute(arg, known);
+                    }
+                }
+                return changed;
+            }
+            Expr::Value(_) => return false,
+        }
+    }
+
+    // goes through the statements in order, knowing the values variables were last assigned
+    // a block only knows the values from before it starts, and afterwards every variable it may
+    // have assigned to is forgotten, since it may not have run or may have stopped early
+    fn propagate(&self, body: &mut [ASTNode], known: &mut HashMap<String, RickrollObject>) -> bool {
+        use ASTNode::*;
+        let mut changed = false;
+        for node in body.iter_mut() {
+            match node {
+                Say(_, expr) | Return(_, expr) | Sleep(_, expr) | Raise(_, _, expr) | Explain(_, expr) => {
+                    changed |= self.substitute(expr, known);
+                }
+                Let(_, name) => {
+                    known.remove(name);
+                }
+                Assign(_, name, expr) => {
+                    changed |= self.substitute(expr, known);
+                    match expr {
+                        Expr::Value(obj) => known.insert(name.clone(), obj.clone()),
+                        _ => known.remove(name),
+                    };
+                }
+                If(_, cond, inner) => {
+                    changed |= self.substitute(cond, known);
+                    changed |= self.propagate(inner, &mut known.clone());
+                    forget(known, assigned(inner));
+                }
+                // the condition is checked again after every run of the body
+                While(_, cond, inner) => {
+                    forget(known, assigned(inner));
+                    changed |= self.substitute(cond, known);
+                    changed |= self.propagate(inner, &mut known.clone());
+                }
+                ForEach(_, name, index, array, inner) => {
+                    changed |= self.substitute(array, known);
+                    forget(known, assigned(inner));
+                    let mut inside = known.clone();
+                    inside.remove(name);
+                    if let Some(index) = index {
+                        inside.remove(index);
+                    }
+                    changed |= self.propagate(inner, &mut inside);
+                }
+                // the handler runs after any part of the try block
+                Try(_, inner, name, _, handler) => {
+                    changed |= self.propagate(inner, &mut known.clone());
+                    forget(known, assigned(inner));
+                    let mut inside = known.clone();
+                    inside.remove(name);
+                    changed |= self.propagate(handler, &mut inside);
+                    forget(known, assigned(handler));
+                }
+                Capture(_, name, inner) => {
+                    changed |= self.propagate(inner, &mut known.clone());
+                    forget(known, assigned(inner));
+                    known.remove(name);
+                }
+                Run(..) | RunAssign(..) => known.clear(),
+                // statements not listed here don't use or change variables
+                _ => (),
+            }
+        }
+        return changed;
+    }
+
+    fn eliminate_dead(&self, body: &mut Vec<ASTNode>) -> bool {
+        let len = body.len();
+        // nothing after a return or a raise runs
+        if let Some(pos) = body.iter().position(|node| matches!(node, ASTNode::Return(_, _) | ASTNode::Raise(_, _, _))) {
+            body.truncate(pos + 1);
+        }
+        body.retain(|node| {
+            !matches!(
+                node,
+                ASTNode::If(_, Expr::Value(RickrollObject::Bool(false)), _)
+                    | ASTNode::While(_, Expr::Value(RickrollObject::Bool(false)), _)
+            )
+        });
+        let mut changed = body.len() != len;
+        for node in body.iter_mut() {
+            for inner in blocks(node) {
+                changed |= self.eliminate_dead(inner);
+            }
+        }
+        return changed;
+    }
+
+    fn peephole(&self, body: &mut Vec<ASTNode>) -> bool {
+        let len = body.len();
+        body.retain(|node| match node {
+            // a non-boolean condition is still an error at runtime
+            ASTNode::If(_, cond, inner) => !(inner.is_empty() && is_constant_bool(cond)),
+            // an empty body can't raise anything to handle
+            ASTNode::Try(_, inner, _, _, _) => !inner.is_empty(),
+            // statements not listed here are always kept
+            _ => true,
+        });
+        let mut changed = body.len() != len;
+        for node in body.iter_mut() {
+            for inner in blocks(node) {
+                changed |= self.peephole(inner);
+            }
+        }
+        return changed;
+    }
+
+    // a value that isn't constant is printed at its own time, so only constant ones are joined
+    fn merge_says(&self, body: &mut Vec<ASTNode>) -> bool {
+        let mut changed = false;
+        let mut merged: Vec<ASTNode> = Vec::with_capacity(body.len());
+        for node in std::mem::take(body) {
+            if is_constant_say(&node) && merged.last().is_some_and(is_constant_say) {
+                let mut values = constant_values(merged.pop().unwrap());
+                values.extend(constant_values(node));
+                merged.push(ASTNode::SayConstants(values[0].0, values));
+                changed = true;
+            } else {
+                merged.push(node);
+            }
+        }
+        *body = merged;
+        for node in body.iter_mut() {
+            for inner in blocks(node) {
+                changed |= self.merge_says(inner);
+            }
+        }
+        return changed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::testing::*;
+
+    use std::io::Cursor;
+
+    // parses a program and runs the passes over it, returning the statements of its chorus
+    fn optimize(src: &str, passes: Vec<Pass>) -> Vec<ASTNode> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut parsed = Parser::new(tokens).parse().unwrap();
+        Optimizer::with_passes(passes).run(&mut parsed);
+        return match parsed.remove("[CHORUS]") {
+            Some(ASTNode::Function(_, _, _, _, body)) => body,
+            _ => panic!("program has no chorus"),
+        };
+    }
+
+    fn kinds(body: &[ASTNode]) -> Vec<&'static str> {
+        return body.iter().map(|node| node.kind()).collect();
+    }
+
+    // renders the expression of every print statement, including the ones inside blocks
+    fn printed(body: &[ASTNode]) -> Vec<String> {
+        return body
+            .iter()
+            .flat_map(|node| node.iter())
+            .filter_map(|node| match node {
+                ASTNode::Say(_, expr) => Some(render(expr)),
+                _ => None,
+            })
+            .collect();
+    }
+
+    const PROPAGATION: [Pass; 2] = [Pass::ConstantFolding, Pass::ConstantPropagation];
+
+    #[test]
+    fn constant_propagation() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 5
+Never gonna let b down
+Never gonna give b a * 2
+Never gonna let c down
+Never gonna say a + b
+Never gonna say c + a
+Never gonna give a c
+Never gonna say a
+";
+        // an operation that can't be worked out keeps its variables
+        let body = optimize(src, PROPAGATION.to_vec());
+        assert_eq!(printed(&body), ["15", "c + a", "a"]);
+        run_and_expect(&src.replace("Never gonna say c + a\n", ""), "", "15\nUNDEFINED\n");
+    }
+
+    #[test]
+    fn constant_propagation_in_loops() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 0
+Never gonna let b down
+Never gonna give b 10
+Inside we both know a < 3
+    Never gonna say a
+    Never gonna say b
+    Never gonna give a a + 1
+We know the game and we're gonna play it
+Never gonna say a
+We've known each x in \"ab\" for so long
+    Never gonna say b
+    Never gonna give b x
+We know the game and we're gonna play it
+Never gonna say b
+";
+        // a variable assigned inside a loop isn't known in its condition, its body or after it
+        let body = optimize(src, PROPAGATION.to_vec());
+        match &body[4] {
+            ASTNode::While(_, cond, _) => assert_eq!(render(cond), "a < 3"),
+            node => panic!("expected a while loop, got {:?}", node),
+        }
+        assert_eq!(printed(&body), ["a", "10", "a", "b", "b"]);
+        run_and_expect(src, "", "0\n10\n1\n10\n2\n10\n3\n10\na\nb\n");
+    }
+
+    #[test]
+    fn constant_propagation_in_try_blocks() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Never gonna make you cry
+    Never gonna say a
+    Never gonna give a 2
+    Never gonna say 1 / 0
+Never gonna tell a lie and hurt err
+    Never gonna say a
+Gotta make you understand
+Never gonna say a
+";
+        // the handler may run after any statement of the try block
+        let body = optimize(src, PROPAGATION.to_vec());
+        assert_eq!(printed(&body), ["1", "1 / 0", "a", "a"]);
+        run_and_expect(src, "", "1\n2\n2\n");
+    }
+
+    #[test]
+    fn constant_propagation_across_calls() {
+        let src = "\
+[Verse change]
+(Ooh give you x)
+Never gonna give x 2
+
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Never gonna run change and desert a
+Never gonna say a
+";
+        let body = optimize(src, PROPAGATION.to_vec());
+        assert_eq!(printed(&body), ["a"]);
+    }
+
+    const BANNER: &str = "\
+[Chorus]
+Never gonna say \"Never gonna\"
+Never gonna say 1 + 2
+Never gonna say TRUE
+Never gonna let a down
+Never gonna give a 5
+Never gonna say a
+Never gonna say 'x'
+Never gonna say 7
+";
+
+    #[test]
+    fn merge_says() {
+        let body = optimize(BANNER, vec![Pass::ConstantFolding, Pass::MergeSays]);
+        assert_eq!(kinds(&body), ["SayConstants", "Let", "Assign", "Say", "SayConstants"]);
+        match &body[0] {
+            ASTNode::SayConstants(2, values) => {
+                let lines: Vec<usize> = values.iter().map(|(ln, _)| *ln).collect();
+                assert_eq!(lines, [2, 3, 4]);
+                assert_eq!(values[1].1.to_string(), "3");
+            }
+            node => panic!("expected merged print statements, got {:?}", node),
+        }
+        // with the value of a known, every print statement is constant
+        let body = optimize(BANNER, DEFAULT_PASSES.to_vec());
+        assert_eq!(kinds(&body), ["SayConstants", "Let", "Assign", "SayConstants"]);
+    }
+
+    #[test]
+    fn merge_says_keeps_non_constant_says() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 5
+Never gonna say a
+Never gonna say a + 1
+Inside we both know a > 1
+    Never gonna say 1
+    Never gonna say a
+    Never gonna say 2
+Your heart's been aching but you're too shy to say it
+";
+        let body = optimize(src, vec![Pass::ConstantFolding, Pass::MergeSays]);
+        assert_eq!(kinds(&body), ["Let", "Assign", "Say", "Say", "If"]);
+        match &body[4] {
+            ASTNode::If(_, _, inner) => assert_eq!(kinds(inner), ["Say", "Say", "Say"]),
+            node => panic!("expected an if statement, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn merge_says_output() {
+        run_and_expect(BANNER, "", "[N, e, v, e, r,  , g, o, n, n, a]\n3\nTRUE\n5\nx\n7\n");
+        // each value still counts as a statement
+        let tokens = Lexer::new(String::from(BANNER)).parse().unwrap();
+        let mut parsed = Parser::new(tokens).parse().unwrap();
+        Optimizer::new().run(&mut parsed);
+        let mut interpreter = Interpreter::new(parsed);
+        interpreter.collect_say_values(true);
+        let (_, stats) = interpreter.run_with_stats(&mut Vec::new(), &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(stats.statements, 8);
+        let lines: Vec<usize> = interpreter.say_values().unwrap().iter().map(|(ln, _)| *ln).collect();
+        assert_eq!(lines, [2, 3, 4, 7, 8, 9]);
+    }
+
+    const UNREACHABLE: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 2 * 3
+Inside we both know 1 + 1 > 3
+    Never gonna say a
+Your heart's been aching but you're too shy to say it
+Never gonna say a
+";
+
+    fn parse(src: &str) -> HashMap<String, ASTNode> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        return Parser::new(tokens).parse().unwrap();
+    }
+
+    #[test]
+    fn fixed_point() {
+        // the condition has to be folded before the block can be removed
+        for passes in [
+            vec![Pass::ConstantFolding, Pass::DeadCodeElimination],
+            vec![Pass::DeadCodeElimination, Pass::ConstantFolding],
+        ] {
+            let mut parsed = parse(UNREACHABLE);
+            let optimizer = Optimizer::with_passes(passes.clone());
+            assert!(optimizer.run(&mut parsed) > 0, "{:?}", passes);
+            let body = match &parsed["[CHORUS]"] {
+                ASTNode::Function(_, _, _, _, body) => body.clone(),
+                _ => panic!("program has no chorus"),
+            };
+            assert_eq!(kinds(&body), ["Let", "Assign", "Say"], "{:?}", passes);
+            assert_eq!(printed(&body), ["a"], "{:?}", passes);
+        }
+    }
+
+    #[test]
+    fn second_run_changes_nothing() {
+        let mut parsed = parse(UNREACHABLE);
+        let optimizer = Optimizer::new();
+        assert!(optimizer.run(&mut parsed) > 0);
+        let once = format!("{:?}", parsed["[CHORUS]"]);
+        assert_eq!(optimizer.run(&mut parsed), 0);
+        assert_eq!(format!("{:?}", parsed["[CHORUS]"]), once);
+    }
+
+    #[test]
+    fn pass_names() {
+        for pass in DEFAULT_PASSES {
+            assert_eq!(Pass::from_name(pass.name()), Some(pass));
+        }
+        assert_eq!(Pass::from_name("tail-call"), None);
+    }
+
+    #[test]
+    fn only_constant_folding() {
+        let others: Vec<Pass> = DEFAULT_PASSES.iter().filter(|pass| **pass != Pass::ConstantFolding).copied().collect();
+        let optimizer = Optimizer::without_passes(&others);
+        assert_eq!(optimizer.passes(), &vec![Pass::ConstantFolding]);
+        let mut parsed = parse(UNREACHABLE);
+        assert!(optimizer.run(&mut parsed) > 0);
+        let body = match &parsed["[CHORUS]"] {
+            ASTNode::Function(_, _, _, _, body) => body.clone(),
+            _ => panic!("program has no chorus"),
+        };
+        // the condition is folded, but the block that can never run is kept
+        assert_eq!(kinds(&body), ["Let", "Assign", "If", "Say"]);
+        match &body[2] {
+            ASTNode::If(_, cond, inner) => {
+                assert_eq!(render(cond), "FALSE");
+                assert_eq!(kinds(inner), ["Say"]);
+            }
+            node => panic!("expected an if statement, got {:?}", node),
+        }
+        assert_eq!(printed(&body), ["a", "a"]);
+    }
+
+    #[test]
+    fn without_every_pass() {
+        let mut parsed = parse(UNREACHABLE);
+        let before = format!("{:?}", parsed["[CHORUS]"]);
+        assert_eq!(Optimizer::without_passes(&DEFAULT_PASSES).run(&mut parsed), 0);
+        assert_eq!(format!("{:?}", parsed["[CHORUS]"]), before);
+        // disabling one pass keeps the rest in order
+        let optimizer = Optimizer::without_passes(&[Pass::DeadCodeElimination]);
+        assert_eq!(
+            optimizer.passes(),
+            &vec![Pass::ConstantFolding, Pass::ConstantPropagation, Pass::Peephole, Pass::MergeSays]
+        );
+    }
+
+    // no pass has anything to do with type assertions, so they stand in for statements
+    // the passes don't know about
+    const ASSERTED: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 2 * 3
+Never gonna say 1 + 2
+You know the rules and a is INT
+Never gonna say a
+Inside we both know 1 > 2
+    Never gonna say 0
+Your heart's been aching but you're too shy to say it
+Never gonna say 4 - 1
+";
+
+    #[test]
+    fn unknown_statements_left_alone() {
+        let body = optimize(ASSERTED, DEFAULT_PASSES.to_vec());
+        assert_eq!(kinds(&body), ["Let", "Assign", "Say", "Expect", "SayConstants"]);
+        let expect = ASTNode::Expect(5, String::from("a"), String::from("INT"));
+        assert_eq!(format!("{:?}", body[3]), format!("{:?}", expect));
+        // the statements around it are still optimized, but not merged across it
+        assert_eq!(printed(&body), ["3"]);
+        match &body[4] {
+            ASTNode::SayConstants(_, values) => {
+                let values: Vec<String> = values.iter().map(|(ln, obj)| format!("{} {}", ln, obj)).collect();
+                assert_eq!(values, ["6 6", "10 3"]);
+            }
+            node => panic!("expected merged print statements, got {:?}", node),
+        }
+        run_and_expect(ASSERTED, "", "3\n6\n3\n");
+    }
+}
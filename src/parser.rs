@@ -2,19 +2,35 @@ use crate::error::*;
 use crate::expr::*;
 use crate::lexer::Token;
 use crate::util::*;
-use crate::stdlib::BUILTIN_FUNCTIONS;
+use crate::stdlib::is_builtin;
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use log::debug;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 
 #[derive(Debug, Clone)]
 pub enum ASTNode {
     Say(usize, Expr),
     Let(usize, String),
     Assign(usize, String, Expr),
+    Expect(usize, String, String),
     If(usize, Expr, Vec<ASTNode>),
     While(usize, Expr, Vec<ASTNode>),
-    Function(usize, String, Vec<String>, Vec<ASTNode>),
+    // runs the body for each element of an array, with a variable for the element and maybe its index
+    ForEach(usize, String, Option<String>, Expr, Vec<ASTNode>),
+    // the error type is the only one handled, or every error if there is none
+    Try(usize, Vec<ASTNode>, String, Option<ErrorType>, Vec<ASTNode>),
+    Capture(usize, String, Vec<ASTNode>),
+    Function(usize, String, Vec<String>, Vec<Option<Expr>>, Vec<ASTNode>),
     Return(usize, Expr),
+    // pauses for a number of milliseconds
+    Sleep(usize, Expr),
+    // raises an error of the type, with the value of the expression as its message
+    Raise(usize, ErrorType, Expr),
+    // prints the value of the expression with its type
+    Explain(usize, Expr),
+    // prints values one after another, each counted as the print statement on its line
+    // the optimizer makes these from print statements of constant values next to each other
+    SayConstants(usize, Vec<(usize, RickrollObject)>),
     Run(usize, String, Vec<String>),
     RunAssign(usize, String, String, Vec<String>),
 }
@@ -26,22 +42,325 @@ impl ASTNode {
             Say(ln, _) => *ln,
             Let(ln, _) => *ln,
             Assign(ln, _, _) => *ln,
+            Expect(ln, _, _) => *ln,
             If(ln, _, _) => *ln,
             While(ln, _, _) => *ln,
-            Function(ln, _, _, _) => *ln,
+            Try(ln, _, _, _, _) => *ln,
+            ForEach(ln, _, _, _, _) => *ln,
+            Capture(ln, _, _) => *ln,
+            Function(ln, _, _, _, _) => *ln,
             Return(ln, _) => *ln,
+            Sleep(ln, _) => *ln,
+            Raise(ln, _, _) => *ln,
+            Explain(ln, _) => *ln,
+            SayConstants(ln, _) => *ln,
             Run(ln, _, _) => *ln,
             RunAssign(ln, _, _, _) => *ln,
         }
     }
+
+    // gets the name of the kind of statement, which is the name of its variant
+    pub fn kind(&self) -> &'static str {
+        use ASTNode::*;
+        match self {
+            Say(..) => "Say",
+            Let(..) => "Let",
+            Assign(..) => "Assign",
+            Expect(..) => "Expect",
+            If(..) => "If",
+            While(..) => "While",
+            Try(..) => "Try",
+            ForEach(..) => "ForEach",
+            Capture(..) => "Capture",
+            Function(..) => "Function",
+            Return(..) => "Return",
+            Sleep(..) => "Sleep",
+            Raise(..) => "Raise",
+            Explain(..) => "Explain",
+            SayConstants(..) => "SayConstants",
+            Run(..) => "Run",
+            RunAssign(..) => "RunAssign",
+        }
+    }
+
+    // gets the blocks of statements directly inside the node, in the order they are written
+    pub fn blocks(&self) -> Vec<&Vec<ASTNode>> {
+        use ASTNode::*;
+        match self {
+            If(_, _, body)
+            | While(_, _, body)
+            | ForEach(_, _, _, _, body)
+            | Capture(_, _, body)
+            | Function(_, _, _, _, body) => vec![body],
+            Try(_, body, _, _, handler) => vec![body, handler],
+            _ => Vec::new(),
+        }
+    }
+
+    // iterates over the node and every statement inside it, each before the ones inside it
+    pub fn iter(&self) -> Nodes<'_> {
+        Nodes { stack: vec![std::slice::from_ref(self).iter()] }
+    }
+}
+
+// iterator over statements and the statements inside them, without copying any of them
+#[derive(Debug, Clone)]
+pub struct Nodes<'a> {
+    // the rest of each block being gone through, innermost last
+    stack: Vec<std::slice::Iter<'a, ASTNode>>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a ASTNode;
+
+    fn next(&mut self) -> Option<&'a ASTNode> {
+        while let Some(block) = self.stack.last_mut() {
+            match block.next() {
+                Some(node) => {
+                    // blocks are pushed in reverse so the first one is gone through first
+                    for inner in node.blocks().into_iter().rev() {
+                        self.stack.push(inner.iter());
+                    }
+                    return Some(node);
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        return None;
+    }
+}
+
+// iterates over every function of a program and the statements in them, with their lines
+// functions are gone through in the order they were declared
+pub fn program_nodes(functions: &HashMap<String, ASTNode>) -> impl Iterator<Item = (usize, &ASTNode)> {
+    let mut sorted: Vec<&ASTNode> = functions.values().collect();
+    sorted.sort_by_key(|node| node.get_line());
+    return sorted.into_iter().flat_map(|node| node.iter()).map(|node| (node.get_line(), node));
+}
+
+// finds every statement of a kind in a program, such as "Say", with their lines
+pub fn nodes_of_kind<'a>(
+    functions: &'a HashMap<String, ASTNode>,
+    kind: &'a str,
+) -> impl Iterator<Item = (usize, &'a ASTNode)> {
+    return program_nodes(functions).filter(move |(_, node)| node.kind() == kind);
+}
+
+// checks that functions built without the parser can be run
+// the parser already makes sure of all of this, so only functions from elsewhere need checking
+// variables are checked like with two-pass parsing, so Intro variables can be used anywhere
+pub fn verify(functions: &HashMap<String, ASTNode>) -> Result<(), Error> {
+    if !functions.contains_key("[CHORUS]") {
+        return Err(Error::from_message(ErrorType::RuntimeError, "no-main", &[], None));
+    }
+    let mut globals = Vec::new();
+    if let Some(ASTNode::Function(_, _, _, _, body)) = functions.get("[INTRO]") {
+        for node in body {
+            if let ASTNode::Let(_, name) = node {
+                globals.push(name.clone());
+            }
+        }
+    }
+    let mut sorted: Vec<(&String, &ASTNode)> = functions.iter().collect();
+    sorted.sort_by_key(|(_, node)| node.get_line());
+    for (key, node) in sorted {
+        match node {
+            ASTNode::Function(ln, name, args, defaults, body) => {
+                if name != key {
+                    return Err(Error::from_message(
+                        ErrorType::SyntaxError,
+                        "function-key-mismatch",
+                        &[("key", &key), ("name", &name)],
+                        Some(*ln),
+                    ));
+                }
+                if args.len() != defaults.len() {
+                    return Err(Error::from_message(
+                        ErrorType::SyntaxError,
+                        "default-count-mismatch",
+                        &[("name", &name), ("params", &args.len()), ("defaults", &defaults.len())],
+                        Some(*ln),
+                    ));
+                }
+                let mut scope = Scope::new();
+                for name in &globals {
+                    scope.add_var(name.clone());
+                }
+                scope.push(Context::new());
+                for arg in args {
+                    scope.add_var(arg.clone());
+                }
+                verify_block(body, &mut scope, functions)?;
+            }
+            _ => {
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    "not-a-function",
+                    &[("name", &key)],
+                    Some(node.get_line()),
+                ))
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn verify_name(name: &str, scope: &Scope, line: usize) -> Result<(), Error> {
+    if !scope.has_var(String::from(name)) {
+        return Err(Error::from_message(
+            ErrorType::NameError,
+            "undeclared-variable",
+            &[("name", &name)],
+            Some(line),
+        ));
+    }
+    return Ok(());
+}
+
+fn verify_expr(expr: &Expr, scope: &Scope, line: usize) -> Result<(), Error> {
+    match expr {
+        Expr::Value(_) => return Ok(()),
+        Expr::Name(name) => return verify_name(name, scope, line),
+        Expr::Operation(_, args) => {
+            for arg in args {
+                verify_expr(arg, scope, line)?;
+            }
+            return Ok(());
+        }
+    }
+}
+
+// checks the statements of a block, declaring its variables in a context of its own
+fn verify_block(body: &[ASTNode], scope: &mut Scope, functions: &HashMap<String, ASTNode>) -> Result<(), Error> {
+    use ASTNode::*;
+    scope.push(Context::new());
+    for node in body {
+        let line = node.get_line();
+        match node {
+            Say(_, expr) | Return(_, expr) | Sleep(_, expr) | Explain(_, expr) => {
+                verify_expr(expr, scope, line)?;
+            }
+            Raise(_, err, expr) => {
+                // tracebacks only wrap the errors raised by the program
+                if *err == ErrorType::Traceback {
+                    return Err(Error::from_message(ErrorType::SyntaxError, "raised-traceback", &[], Some(line)));
+                }
+                verify_expr(expr, scope, line)?;
+            }
+            SayConstants(..) => (),
+            Let(_, name) => scope.add_var(name.clone()),
+            Assign(_, name, expr) => {
+                verify_name(name, scope, line)?;
+                verify_expr(expr, scope, line)?;
+            }
+            Expect(_, name, _) => verify_name(name, scope, line)?,
+            If(_, cond, inner) | While(_, cond, inner) => {
+                verify_expr(cond, scope, line)?;
+                verify_block(inner, scope, functions)?;
+            }
+            ForEach(_, name, index, array, inner) => {
+                verify_expr(array, scope, line)?;
+                scope.push(Context::new());
+                scope.add_var(name.clone());
+                if let Some(index) = index {
+                    scope.add_var(index.clone());
+                }
+                verify_block(inner, scope, functions)?;
+                scope.pop();
+            }
+            Try(_, inner, name, _, handler) => {
+                verify_block(inner, scope, functions)?;
+                scope.push(Context::new());
+                scope.add_var(name.clone());
+                verify_block(handler, scope, functions)?;
+                scope.pop();
+            }
+            Capture(_, name, inner) => {
+                verify_name(name, scope, line)?;
+                verify_block(inner, scope, functions)?;
+            }
+            Function(_, name, _, _, _) => {
+                return Err(Error::from_message(
+                    ErrorType::SyntaxError,
+                    "nested-function-named",
+                    &[("name", &name)],
+                    Some(line),
+                ))
+            }
+            Run(_, func, args) | RunAssign(_, _, func, args) => {
+                if !functions.contains_key(func) && !is_builtin(func) {
+                    return Err(Error::from_message(
+                        ErrorType::NameError,
+                        "unknown-function",
+                        &[("name", &func)],
+                        Some(line),
+                    ));
+                }
+                if let RunAssign(_, var, _, _) = node {
+                    verify_name(var, scope, line)?;
+                }
+                for arg in args {
+                    verify_name(arg, scope, line)?;
+                }
+            }
+        }
+    }
+    scope.pop();
+    return Ok(());
+}
+
+// checks if a statement keyword ends (part of) a block
+fn is_terminator(kw: &str) -> bool {
+    matches!(kw, "WHILE_END" | "IF_END" | "CATCH" | "TRY_END" | "CAPTURE_END")
+}
+
+fn describe_terminator(kw: &str) -> &'static str {
+    match kw {
+        "WHILE_END" => "End of while loop",
+        "IF_END" => "End of if statement",
+        "CATCH" => "Error handler",
+        "TRY_END" => "End of error handling block",
+        "CAPTURE_END" => "End of capture block",
+        _ => panic!("describe_terminator called with non-terminator {}", kw),
+    }
+}
+
+// makes an error for a block that is never closed
+// line is where the parser gave up, if not at the end of the program
+fn unclosed_err(opener: usize, line: Option<usize>) -> Error {
+    Error::from_message(
+        ErrorType::SyntaxError,
+        "unclosed-block",
+        &[("line", &opener)],
+        line,
+    )
+}
+
+// makes an error for a terminator that can't close the innermost block
+fn mismatch_err(kw: &str, opener: usize, line: usize) -> Error {
+    Error::from_message(
+        ErrorType::SyntaxError,
+        "mismatched-block",
+        &[("terminator", &describe_terminator(kw)), ("line", &opener)],
+        Some(line),
+    )
 }
 
 #[derive(Debug)]
 pub struct Parser {
     tokens: VecDeque<Token>,
     output: HashMap<String, ASTNode>,
-    func_cache: HashSet<String>,
+    func_cache: HashMap<String, (usize, usize)>, // <Name, (Required args, Total args)>
     scope: Scope,
+    // number of statements parsed so far
+    statements: usize,
+    max_statements: Option<usize>,
+    // declare every function and the Intro's variables before parsing anything else
+    two_pass: bool,
+    // names of every variable and parameter declared so far
+    declared: BTreeSet<String>,
 }
 
 impl Parser {
@@ -49,11 +368,32 @@ impl Parser {
         Parser {
             tokens: VecDeque::from(tokens),
             output: HashMap::new(),
-            func_cache: HashSet::new(),
+            func_cache: HashMap::new(),
             scope: Scope::new(),
+            statements: 0,
+            max_statements: None,
+            two_pass: false,
+            declared: BTreeSet::new(),
         }
     }
 
+    // rejects programs with more than max_statements statements
+    pub fn set_max_statements(&mut self, max_statements: Option<usize>) {
+        self.max_statements = max_statements;
+    }
+
+    // lets functions be called before they are declared, and the Intro's variables
+    // be used anywhere, by declaring them before the rest of the program is parsed
+    pub fn set_two_pass(&mut self, two_pass: bool) {
+        self.two_pass = two_pass;
+    }
+
+    // adds a variable to the innermost scope
+    fn declare(&mut self, name: String) {
+        self.scope.add_var(name.clone());
+        self.declared.insert(name);
+    }
+
     fn get_name(&mut self) -> String {
         let name = self.tokens.pop_front().unwrap();
         match name {
@@ -92,13 +432,8 @@ impl Parser {
                         self.tokens.pop_front();
                         return Ok(ASTNode::If(line, condition, body));
                     }
-                    "VERSE" => {
-                        return Err(Error::new(
-                            ErrorType::SyntaxError,
-                            "Unbalanced statements",
-                            Some(*ln),
-                        ));
-                    }
+                    "VERSE" => return Err(unclosed_err(line, Some(*ln))),
+                    kw if is_terminator(kw) => return Err(mismatch_err(kw, line, *ln)),
                     _ => {
                         body.push(self.parse_statement()?);
                     }
@@ -107,105 +442,276 @@ impl Parser {
                 panic!("Parser::parse_loop called with invalid statement");
             }
         }
-        return Err(Error::new(
-            ErrorType::SyntaxError,
-            "Unbalanced statements",
-            None,
-        ));
+        return Err(unclosed_err(line, None));
+    }
+
+    // parses statements until a statement with the keyword end is reached
+    // opener is the line of the statement starting the block
+    fn parse_block(&mut self, end: &str, opener: usize) -> Result<Vec<ASTNode>, Error> {
+        let mut body: Vec<ASTNode> = Vec::new();
+        while !self.tokens.is_empty() {
+            let top = self.tokens.front().unwrap();
+            if let Token::Statement(ln, kw) = top {
+                if &kw[..] == end {
+                    self.tokens.pop_front();
+                    return Ok(body);
+                } else if &kw[..] == "VERSE" {
+                    return Err(unclosed_err(opener, Some(*ln)));
+                } else if is_terminator(kw) {
+                    return Err(mismatch_err(kw, opener, *ln));
+                }
+                body.push(self.parse_statement()?);
+            } else {
+                panic!("Parser::parse_block called with invalid statement");
+            }
+        }
+        return Err(unclosed_err(opener, None));
+    }
+
+    // declares a variable, which can't shadow another variable
+    fn declare_new(&mut self, name: String, line: usize) -> Result<(), Error> {
+        if self.scope.has_var(name.clone()) {
+            return Err(Error::from_message(
+                ErrorType::NameError,
+                "duplicate-variable",
+                &[("name", &name)],
+                Some(line),
+            ));
+        }
+        self.declare(name);
+        return Ok(());
+    }
+
+    fn parse_for_each(&mut self, line: usize) -> Result<ASTNode, Error> {
+        let name = self.get_name();
+        let index = match self.tokens.front() {
+            Some(Token::Punc(_, punc)) if punc == "," => {
+                self.tokens.pop_front();
+                Some(self.get_name())
+            }
+            _ => None,
+        };
+        // the array is evaluated before the loop's variables exist
+        let array = self.parse_expr()?;
+        self.scope.push(Context::new());
+        self.declare_new(name.clone(), line)?;
+        if let Some(index) = &index {
+            self.declare_new(index.clone(), line)?;
+        }
+        let body = self.parse_block("WHILE_END", line)?;
+        self.scope.pop();
+        return Ok(ASTNode::ForEach(line, name, index, array, body));
+    }
+
+    fn parse_try(&mut self, line: usize) -> Result<ASTNode, Error> {
+        self.scope.push(Context::new());
+        let body = self.parse_block("CATCH", line)?;
+        self.scope.pop();
+        let name = self.get_name();
+        let filter = match self.tokens.front() {
+            Some(Token::Name(ln, type_name)) => match ErrorType::from_name(type_name) {
+                Some(err) => Some(err),
+                None => {
+                    return Err(Error::from_message(
+                        ErrorType::NameError,
+                        "unknown-error-type",
+                        &[("name", &type_name)],
+                        Some(*ln),
+                    ))
+                }
+            },
+            _ => None,
+        };
+        if filter.is_some() {
+            self.tokens.pop_front();
+        }
+        self.scope.push(Context::new());
+        self.declare(name.clone());
+        let handler = self.parse_block("TRY_END", line)?;
+        self.scope.pop();
+        return Ok(ASTNode::Try(line, body, name, filter, handler));
+    }
+
+    // parses the arguments of a function call and checks them against the function
+    fn parse_call_args(&mut self, func: &String, line: usize) -> Result<Vec<String>, Error> {
+        let mut args: Vec<String> = Vec::new();
+        while !self.tokens.is_empty() {
+            match self.tokens.front().unwrap() {
+                Token::Name(_, name) => {
+                    args.push(name.clone());
+                    self.tokens.pop_front();
+                }
+                _ => break,
+            }
+        }
+        // built-in functions check their own arguments
+        if let Some((required, total)) = self.func_cache.get(func) {
+            if args.len() < *required || args.len() > *total {
+                let expected = if required == total {
+                    required.to_string()
+                } else {
+                    format!("{} to {}", required, total)
+                };
+                return Err(Error::from_message(
+                    ErrorType::IllegalArgumentError,
+                    "function-argument-count",
+                    &[("func", &func), ("expected", &expected), ("given", &args.len())],
+                    Some(line),
+                ));
+            }
+        }
+        return Ok(args);
     }
 
     fn parse_statement(&mut self) -> Result<ASTNode, Error> {
         let token = self.tokens.pop_front().unwrap();
+        self.statements += 1;
+        if let Some(max) = self.max_statements {
+            if self.statements > max {
+                return Err(Error::from_message(
+                    ErrorType::ResourceError,
+                    "statement-count-limit",
+                    &[("max", &max)],
+                    Some(token.get_line()),
+                ));
+            }
+        }
         if let Token::Statement(line, kw) = token {
+            debug!("parsing {} on line {}", kw, line);
             match &kw[..] {
                 "SAY" => {
                     return Ok(ASTNode::Say(line, self.parse_expr()?));
                 }
                 "LET" => {
                     let name = self.get_name();
-                    if self.scope.has_var(name.clone()) {
-                        return Err(Error::new(
+                    self.declare_new(name.clone(), line)?;
+                    debug!("declared variable {} on line {}", name, line);
+                    return Ok(ASTNode::Let(line, name));
+                }
+                "ASSIGN" => {
+                    let name = self.get_name();
+                    if !self.scope.has_var(name.clone()) {
+                        return Err(Error::from_message(
                             ErrorType::NameError,
-                            &format!("Variable name {} already exists", name)[..],
+                            "undeclared-variable",
+                            &[("name", &name)],
                             Some(line),
                         ));
                     }
-                    self.scope.add_var(name.clone());
-                    return Ok(ASTNode::Let(line, name));
+                    debug!("assigning variable {} on line {}", name, line);
+                    return Ok(ASTNode::Assign(line, name, self.parse_expr()?));
                 }
-                "ASSIGN" => {
+                "EXPECT" => {
                     let name = self.get_name();
+                    let type_name = self.get_name();
                     if !self.scope.has_var(name.clone()) {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::NameError,
-                            &format!("Variable name {} doesn't exist", name)[..],
+                            "undeclared-variable",
+                            &[("name", &name)],
                             Some(line),
                         ));
                     }
-                    return Ok(ASTNode::Assign(line, name, self.parse_expr()?));
+                    if !TYPE_NAMES.contains(&&type_name[..]) {
+                        return Err(Error::from_message(
+                            ErrorType::NameError,
+                            "unknown-type",
+                            &[("name", &type_name)],
+                            Some(line),
+                        ));
+                    }
+                    return Ok(ASTNode::Expect(line, name, type_name));
                 }
                 "CHECK" => {
                     return self.parse_loop(line);
                 }
-                "WHILE_END" | "IF_END" => {
-                    return Err(Error::new(
+                "FOREACH" => {
+                    return self.parse_for_each(line);
+                }
+                "TRY" => {
+                    return self.parse_try(line);
+                }
+                "CAPTURE" => {
+                    let name = self.get_name();
+                    if !self.scope.has_var(name.clone()) {
+                        return Err(Error::from_message(
+                            ErrorType::NameError,
+                            "undeclared-variable",
+                            &[("name", &name)],
+                            Some(line),
+                        ));
+                    }
+                    self.scope.push(Context::new());
+                    let body = self.parse_block("CAPTURE_END", line)?;
+                    self.scope.pop();
+                    return Ok(ASTNode::Capture(line, name, body));
+                }
+                kw if is_terminator(kw) => {
+                    return Err(Error::from_message(
                         ErrorType::SyntaxError,
-                        "Unbalanced statements",
+                        "unmatched-terminator",
+                        &[("terminator", &describe_terminator(kw))],
                         Some(line),
                     ));
                 }
                 "RUN" => {
                     let name = self.get_name();
-                    if !self.func_cache.contains(&name) && !BUILTIN_FUNCTIONS.contains_key(&name) {
-                        return Err(Error::new(
+                    if !self.func_cache.contains_key(&name) && !is_builtin(&name) {
+                        return Err(Error::from_message(
                             ErrorType::NameError,
-                            &format!("Function name {} doesn't exist", name)[..],
+                            "unknown-function",
+                            &[("name", &name)],
                             Some(line),
                         ));
                     }
-                    let mut args: Vec<String> = Vec::new();
-                    while !self.tokens.is_empty() {
-                        match self.tokens.front().unwrap() {
-                            Token::Name(_, name) => {
-                                args.push(name.clone());
-                                self.tokens.pop_front();
-                            }
-                            _ => break,
-                        }
-                    }
+                    let args = self.parse_call_args(&name, line)?;
                     return Ok(ASTNode::Run(line, name, args));
                 }
                 "RUN_ASSIGN" => {
                     let var_name = self.get_name();
                     let name = self.get_name();
-                    if !self.func_cache.contains(&name) && !BUILTIN_FUNCTIONS.contains_key(&name) {
-                        return Err(Error::new(
+                    if !self.func_cache.contains_key(&name) && !is_builtin(&name) {
+                        return Err(Error::from_message(
                             ErrorType::NameError,
-                            &format!("Function name {} doesn't exist", name)[..],
+                            "unknown-function",
+                            &[("name", &name)],
                             Some(line),
                         ));
                     }
-                    let mut args: Vec<String> = Vec::new();
-                    while !self.tokens.is_empty() {
-                        match self.tokens.front().unwrap() {
-                            Token::Name(_, name) => {
-                                args.push(name.clone());
-                                self.tokens.pop_front();
-                            }
-                            _ => break,
-                        }
-                    }
+                    let args = self.parse_call_args(&name, line)?;
                     return Ok(ASTNode::RunAssign(line, var_name, name, args));
                 }
                 "RETURN" => {
                     return Ok(ASTNode::Return(line, self.parse_expr()?));
                 }
+                "SLEEP" => {
+                    return Ok(ASTNode::Sleep(line, self.parse_expr()?));
+                }
+                "RAISE" => {
+                    let type_name = self.get_name();
+                    let err = match ErrorType::from_name(&type_name) {
+                        Some(err) => err,
+                        None => {
+                            return Err(Error::from_message(
+                                ErrorType::NameError,
+                                "unknown-error-type",
+                                &[("name", &type_name)],
+                                Some(line),
+                            ))
+                        }
+                    };
+                    return Ok(ASTNode::Raise(line, err, self.parse_expr()?));
+                }
+                "EXPLAIN" => {
+                    return Ok(ASTNode::Explain(line, self.parse_expr()?));
+                }
                 _ => panic!("Parser::parse_statement called with invalid keyword {}", kw),
             }
         } else {
-            return Err(Error::new(
+            return Err(Error::from_message(
                 ErrorType::SyntaxError,
-                "Illegal statement",
+                "illegal-statement",
+                &[],
                 Some(token.get_line()),
             ));
         }
@@ -216,36 +722,57 @@ impl Parser {
         let mut body: Vec<ASTNode> = Vec::new();
         if let Token::Statement(ln, kw) = &token {
             if kw.clone() == String::from("VERSE") {
-                // add scope
-                self.scope.push(Context::new());
                 // extract name
                 let name_token = self.tokens.pop_front().unwrap();
                 let name = match name_token {
                     Token::Name(_, name) => name,
                     _ => panic!("Parser::parse_function called with malformed verse token"),
                 };
-                // insert into func_cache
-                if self.func_cache.contains(&name) {
-                    return Err(Error::new(
+                // the intro runs in the global scope, so its variables stay declared
+                let global = name == "[INTRO]";
+                if !global {
+                    self.scope.push(Context::new());
+                }
+                // functions were already declared by the first pass
+                if self.func_cache.contains_key(&name) && !self.two_pass {
+                    return Err(Error::from_message(
                         ErrorType::NameError,
-                        &format!("Function named {} already exists", name)[..],
+                        "duplicate-function",
+                        &[("name", &name)],
                         Some(*ln),
                     ));
                 }
-                self.func_cache.insert(name.clone());
                 // extract arguments
                 let mut args: Vec<String> = Vec::new();
+                let mut defaults: Vec<Option<Expr>> = Vec::new();
                 while !self.tokens.is_empty() {
                     let front = self.tokens.front().unwrap();
                     match front {
                         Token::Name(_, name) => {
                             args.push(name.clone());
-                            self.scope.add_var(name.clone());
+                            defaults.push(None);
+                            self.declare(name.clone());
                             self.tokens.pop_front();
                         }
+                        Token::Operator(_, op) if &op[..] == "=" => {
+                            self.tokens.pop_front();
+                            // default values can't reference variables
+                            let mut expr_tokens: Vec<Token> = Vec::new();
+                            while !self.tokens.is_empty() {
+                                match self.tokens.front().unwrap() {
+                                    Token::Name(_, _) | Token::Statement(_, _) => break,
+                                    _ => expr_tokens.push(self.tokens.pop_front().unwrap()),
+                                }
+                            }
+                            let default = ExprParser::new(expr_tokens, Scope::new()).parse();
+                            *defaults.last_mut().unwrap() = Some(default.map_err(|err| Error::traceback(err, Some(*ln)))?);
+                        }
                         _ => break,
                     }
                 }
+                // insert into func_cache
+                let required = defaults.iter().filter(|default| default.is_none()).count();
+                self.func_cache.insert(name.clone(), (required, args.len()));
                 // extract body
                 while !self.tokens.is_empty() {
                     let front = self.tokens.front().unwrap();
@@ -256,46 +783,473 @@ impl Parser {
                             break;
                         }
                     } else {
-                        return Err(Error::new(
+                        return Err(Error::from_message(
                             ErrorType::SyntaxError,
-                            "Illegal statement",
+                            "illegal-statement",
+                            &[],
                             Some(token.get_line()),
                         ));
                     }
                 }
-                self.scope.pop();
-                return Ok(ASTNode::Function(*ln, name, args, body));
+                if !global {
+                    self.scope.pop();
+                }
+                return Ok(ASTNode::Function(*ln, name, args, defaults, body));
             } else {
-                return Err(Error::new(
+                return Err(Error::from_message(
                     ErrorType::SyntaxError,
-                    "Invalid start of function",
+                    "invalid-function-start",
+                    &[],
                     Some(*ln),
                 ));
             }
         } else {
-            return Err(Error::new(
+            return Err(Error::from_message(
                 ErrorType::SyntaxError,
-                "Invalid start of function",
+                "invalid-function-start",
+                &[],
                 Some(token.get_line()),
             ));
         }
     }
 
-    pub fn parse(mut self) -> Result<HashMap<String, ASTNode>, Error> {
+    // first pass of two-pass parsing, which fills func_cache with every function
+    fn declare_functions(&mut self) -> Result<(), Error> {
+        for ind in 0..self.tokens.len() {
+            if let (Token::Statement(ln, kw), Some(Token::Name(_, name))) = (&self.tokens[ind], self.tokens.get(ind + 1)) {
+                if kw != "VERSE" {
+                    continue;
+                }
+                if self.func_cache.contains_key(name) {
+                    return Err(Error::from_message(
+                        ErrorType::NameError,
+                        "duplicate-function",
+                        &[("name", &name)],
+                        Some(*ln),
+                    ));
+                }
+                // parameters are names, and the ones with default values are followed by "="
+                let (mut required, mut total) = (0, 0);
+                let mut pos = ind + 2;
+                while pos < self.tokens.len() {
+                    match &self.tokens[pos] {
+                        Token::Name(_, _) => {
+                            total += 1;
+                            match self.tokens.get(pos + 1) {
+                                Some(Token::Operator(_, op)) if &op[..] == "=" => (),
+                                _ => required += 1,
+                            }
+                        }
+                        Token::Statement(_, _) => break,
+                        _ => (),
+                    }
+                    pos += 1;
+                }
+                self.func_cache.insert(name.clone(), (required, total));
+            }
+        }
+        return Ok(());
+    }
+
+    // second pass of two-pass parsing starts with the intro, so its variables are declared everywhere
+    fn parse_intro_first(&mut self) -> Result<(), Error> {
+        let start = self.tokens.iter().zip(self.tokens.iter().skip(1)).position(|pair| {
+            matches!(pair, (Token::Statement(_, kw), Token::Name(_, name)) if kw == "VERSE" && name == "[INTRO]")
+        });
+        if let Some(start) = start {
+            let end = self
+                .tokens
+                .iter()
+                .skip(start + 1)
+                .position(|token| matches!(token, Token::Statement(_, kw) if kw == "VERSE"))
+                .map_or(self.tokens.len(), |ind| start + 1 + ind);
+            let rest = self.tokens.split_off(end);
+            let intro = self.tokens.split_off(start);
+            self.tokens.extend(rest);
+            let rest = std::mem::replace(&mut self.tokens, intro);
+            let fnc = self.parse_function();
+            self.tokens = rest;
+            self.output.insert(String::from("[INTRO]"), fnc?);
+        }
+        return Ok(());
+    }
+
+    pub fn parse(self) -> Result<HashMap<String, ASTNode>, Error> {
+        return Ok(self.parse_with_variables()?.0);
+    }
+
+    // parses the program, also returning the names of every variable and parameter it declares
+    pub fn parse_with_variables(mut self) -> Result<(HashMap<String, ASTNode>, BTreeSet<String>), Error> {
+        if self.two_pass {
+            self.declare_functions()?;
+            self.parse_intro_first()?;
+        }
         while !self.tokens.is_empty() {
             // parse function
             let fnc = self.parse_function()?;
-            if let ASTNode::Function(_, name, _, _) = &fnc {
+            if let ASTNode::Function(_, name, _, _, _) = &fnc {
                 self.output.insert(name.clone(), fnc);
             } else {
-                return Err(Error::new(
+                return Err(Error::from_message(
                     ErrorType::SyntaxError,
-                    "Statement not in function",
+                    "statement-outside-function",
+                    &[],
                     Some(fnc.get_line()),
                 ));
             }
         }
-        return Ok(self.output);
+        return Ok((self.output, self.declared));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::util::RickrollObject::*;
+
+    use std::io::Cursor;
+
+    fn function(ln: usize, name: &str, body: Vec<ASTNode>) -> (String, ASTNode) {
+        return (String::from(name), ASTNode::Function(ln, String::from(name), Vec::new(), Vec::new(), body));
+    }
+
+    fn chorus(body: Vec<ASTNode>) -> HashMap<String, ASTNode> {
+        return HashMap::from([function(1, "[CHORUS]", body)]);
+    }
+
+    fn verify_err(functions: HashMap<String, ASTNode>) -> Error {
+        return match Interpreter::from_functions(functions) {
+            Ok(_) => panic!("functions were accepted"),
+            Err(err) => err,
+        };
+    }
+
+    #[test]
+    fn from_functions_runs() {
+        let say = ASTNode::Say(3, Expr::Operation(Operator::Add, vec![Expr::Name(String::from("a")), Expr::Value(Int(2))]));
+        let functions = chorus(vec![
+            ASTNode::Let(2, String::from("a")),
+            ASTNode::Assign(2, String::from("a"), Expr::Value(Int(40))),
+            say,
+        ]);
+        let mut interpreter = Interpreter::from_functions(functions).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn verify_rejects() {
+        let err = verify_err(HashMap::from([function(1, "foo", Vec::new())]));
+        assert_eq!(err.message_id(), Some("no-main"));
+
+        let mut functions = chorus(Vec::new());
+        functions.insert(String::from("bar"), function(2, "foo", Vec::new()).1);
+        assert_eq!(verify_err(functions).message_id(), Some("function-key-mismatch"));
+
+        let mut functions = chorus(Vec::new());
+        functions.insert(
+            String::from("foo"),
+            ASTNode::Function(2, String::from("foo"), vec![String::from("a")], Vec::new(), Vec::new()),
+        );
+        assert_eq!(verify_err(functions).message_id(), Some("default-count-mismatch"));
+
+        let mut functions = chorus(Vec::new());
+        functions.insert(String::from("foo"), ASTNode::Say(2, Expr::Value(Int(1))));
+        assert_eq!(verify_err(functions).message_id(), Some("not-a-function"));
+
+        let err = verify_err(chorus(vec![function(2, "foo", Vec::new()).1]));
+        assert_eq!(err.message_id(), Some("nested-function-named"));
+
+        let err = verify_err(chorus(vec![ASTNode::Say(2, Expr::Name(String::from("a")))]));
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.message_id(), Some("undeclared-variable"));
+
+        let err = verify_err(chorus(vec![ASTNode::Run(2, String::from("foo"), Vec::new())]));
+        assert_eq!(err.message_id(), Some("unknown-function"));
+    }
+
+    #[test]
+    fn verify_rejects_raised_traceback() {
+        let err = verify_err(chorus(vec![ASTNode::Raise(2, ErrorType::Traceback, Expr::Value(Int(1)))]));
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.message_id(), Some("raised-traceback"));
+    }
+
+    fn parse_err(src: &str) -> Error {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        return Parser::new(tokens).parse().unwrap_err();
+    }
+
+    #[test]
+    fn type_assertion_names() {
+        let err = parse_err("[Chorus]\nNever gonna let a down\nYou know the rules and a is NUMBER\n");
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.to_string(), "Name Error on line 3: Type name NUMBER doesn't exist");
+        let err = parse_err("[Chorus]\nYou know the rules and a is INT\n");
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.to_string(), "Name Error on line 2: Variable name a doesn't exist");
+    }
+
+    #[test]
+    fn unclosed_block() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Inside we both know a < 3
+    Never gonna give a a + 1
+    Inside we both know a == 2
+        Never gonna say a
+    Your heart's been aching but you're too shy to say it
+";
+        let err = parse_err(src);
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.to_string(), "Syntax Error: Unclosed block opened at line 4");
+    }
+
+    #[test]
+    fn stray_terminator() {
+        let err = parse_err("[Chorus]\nNever gonna say 1\nWe know the game and we're gonna play it\n");
+        assert_eq!(err.kind(), ErrorType::SyntaxError);
+        assert_eq!(err.to_string(), "Syntax Error on line 3: End of while loop without a block to close");
+        let src = "\
+[Chorus]
+Never gonna make you cry
+    Never gonna say 1
+We know the game and we're gonna play it
+";
+        // a terminator for a different kind of block
+        let err = parse_err(src);
+        assert_eq!(err.to_string(), "Syntax Error on line 4: End of while loop doesn't match the block opened at line 2");
+    }
+
+    fn parse_with_max(src: &str, max: usize) -> Result<HashMap<String, ASTNode>, Error> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.set_max_statements(Some(max));
+        return parser.parse();
+    }
+
+    #[test]
+    fn max_statements() {
+        let src = "[Chorus]\nNever gonna let a down\nNever gonna give a 1\nNever gonna say a\n";
+        assert!(parse_with_max(src, 3).is_ok());
+        let err = parse_with_max(src, 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ResourceError);
+        assert_eq!(err.to_string(), "Resource Limit on line 4: Program has more than 2 statements");
+    }
+
+    #[test]
+    fn declared_variables() {
+        let src = "\
+[Intro]
+Never gonna let total down
+[Verse Add]
+(Ooh give you n, by = 1)
+Never gonna let sum down
+Never gonna give sum n * by
+[Chorus]
+Never gonna let count down
+Never gonna let sum down
+We've known each c, ind in \"abc\" for so long
+    Never gonna say c
+We know the game and we're gonna play it
+Never gonna make you cry
+    Never gonna say 1
+Never gonna tell a lie and hurt err
+    Never gonna say err
+Gotta make you understand
+";
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let (functions, declared) = Parser::new(tokens).parse_with_variables().unwrap();
+        assert_eq!(functions.len(), 3);
+        // sum is declared by two functions, but is only listed once
+        let names: Vec<&str> = declared.iter().map(|name| &name[..]).collect();
+        assert_eq!(names, ["by", "c", "count", "err", "ind", "n", "sum", "total"]);
+    }
+
+    // says something at the top of each function and inside each kind of block
+    const SAYS: &str = "\
+[Intro]
+Never gonna let total down
+Never gonna say 0
+[Verse Add]
+(Ooh give you n)
+Never gonna say n
+(Ooh) Never gonna give, never gonna give (give you n + 1)
+[Chorus]
+Never gonna let x down
+Never gonna give x 1
+Inside we both know x == 1
+    Never gonna say 1
+    Inside we both know x < 3
+        Never gonna give x x + 1
+        Never gonna say x
+    We know the game and we're gonna play it
+Your heart's been aching but you're too shy to say it
+Never gonna make you cry
+    Never gonna say 1 / 0
+Never gonna tell a lie and hurt err
+    Never gonna say err
+Gotta make you understand
+Never gonna run Add and desert x
+";
+
+    fn parse(src: &str) -> HashMap<String, ASTNode> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        return Parser::new(tokens).parse().unwrap();
+    }
+
+    #[test]
+    fn iterate_program() {
+        let functions = parse(SAYS);
+        let kinds: Vec<&str> = program_nodes(&functions).map(|(_, node)| node.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "Function", "Let", "Say", "Function", "Say", "Return", "Function", "Let", "Assign", "If", "Say",
+                "While", "Assign", "Say", "Try", "Say", "Say", "Run",
+            ]
+        );
+        // every statement is there once, with the line it is on
+        let lines: Vec<usize> = program_nodes(&functions).map(|(ln, _)| ln).collect();
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+        let chorus = functions.get("[CHORUS]").unwrap();
+        assert_eq!(chorus.iter().count(), 12);
+    }
+
+    #[test]
+    fn filter_program() {
+        let functions = parse(SAYS);
+        let says: Vec<usize> = nodes_of_kind(&functions, "Say").map(|(ln, _)| ln).collect();
+        assert_eq!(says, [3, 6, 12, 15, 19, 21]);
+        assert_eq!(nodes_of_kind(&functions, "ForEach").count(), 0);
+        assert_eq!(nodes_of_kind(&functions, "Function").count(), 3);
+    }
+
+    fn parse_passes(src: &str, two_pass: bool) -> Result<HashMap<String, ASTNode>, Error> {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.set_two_pass(two_pass);
+        return parser.parse();
+    }
+
+    // the parsed functions in order of their names, as ASTNode can't be compared
+    fn dump(functions: HashMap<String, ASTNode>) -> Vec<String> {
+        let mut res: Vec<(String, ASTNode)> = functions.into_iter().collect();
+        res.sort_by(|a, b| a.0.cmp(&b.0));
+        return res.iter().map(|pair| format!("{:?}", pair)).collect();
+    }
+
+    #[test]
+    fn forward_function_call() {
+        let src = "\
+[Chorus]
+Never gonna run Greet and desert you
+[Verse Greet]
+(Ooh give you up)
+Never gonna say 1
+";
+        let err = parse_passes(src, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.to_string(), "Name Error on line 2: Function name Greet doesn't exist");
+        assert!(parse_passes(src, true).is_ok());
+        run_passes_and_expect(src, "1\n");
+    }
+
+    #[test]
+    fn forward_intro_global() {
+        let src = "\
+[Verse Show]
+(Ooh give you up)
+Never gonna say total
+[Intro]
+Never gonna let total down
+Never gonna give total 5
+[Chorus]
+Never gonna run Show and desert you
+";
+        let err = parse_passes(src, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.to_string(), "Name Error: No such variable total");
+        run_passes_and_expect(src, "5\n");
+    }
+
+    fn run_passes_and_expect(src: &str, expected: &str) {
+        let mut interpreter = Interpreter::new(parse_passes(src, true).unwrap());
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn two_pass_parses_the_same() {
+        let src = "\
+[Intro]
+Never gonna let total down
+Never gonna give total 0
+[Verse Add]
+(Ooh give you n, by = 1)
+Never gonna give total total + n * by
+[Verse Twice]
+(Ooh give you n)
+Never gonna run Add and desert n
+Never gonna run Add and desert n
+[Chorus]
+Never gonna let i down
+Never gonna give i 0
+Inside we both know i < 3
+    Never gonna run Twice and desert i
+    Never gonna give i i + 1
+We know the game and we're gonna play it
+Never gonna say total
+";
+        let single = dump(parse_passes(src, false).unwrap());
+        assert_eq!(dump(parse_passes(src, true).unwrap()), single);
+        run_passes_and_expect(src, "6\n");
+    }
+
+    // logger keeping the messages logged by each thread, so tests running at the same time don't mix
+    struct CapturingLogger;
+
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED.with(|logged| logged.borrow_mut().push(format!("{} {}", record.level(), record.args())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger;
+
+    #[test]
+    fn parsing_logs() {
+        // only the first test setting a logger succeeds
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+        let tokens = Lexer::new(String::from("[Chorus]\nNever gonna let a down\nNever gonna give a 1\n")).parse().unwrap();
+        Parser::new(tokens).parse().unwrap();
+        let logged = LOGGED.with(|logged| logged.borrow_mut().split_off(0));
+        assert_eq!(
+            logged,
+            [
+                "DEBUG parsing LET on line 2",
+                "DEBUG declared variable a on line 2",
+                "DEBUG parsing ASSIGN on line 3",
+                "DEBUG assigning variable a on line 3",
+            ]
+        );
     }
 }
 
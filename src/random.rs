@@ -0,0 +1,257 @@
+use crate::error::*;
+use crate::stdlib::arg_type_err;
+use crate::util::*;
+
+use std::rc::Rc;
+
+// built-in functions that use the interpreter's random number generator
+// they are run by the interpreter, since the generator can be seeded
+pub const RANDOM_FUNCTIONS: [&str; 3] = ["Random", "Shuffle", "Sample"];
+
+// seedable random number generator (SplitMix64)
+// the numbers drawn for a seed are part of the language, so seeded programs give the same
+// results with every version of rickroll:
+// - Random [low] [high] draws one number below high - low + 1
+// - Shuffle [array] draws a number below i + 1 for each i from the last index down to 1,
+//   and swaps the elements at i and the number drawn
+// - Sample [array] [k] draws a number below length - i for each i from 0 up to k - 1,
+//   and swaps the elements at i and i + the number drawn, keeping the first k elements
+// a number below n is the first draw under the largest multiple of n that fits in 64 bits,
+// taken modulo n
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut x = self.state;
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        return x ^ (x >> 31);
+    }
+
+    // draws a number from 0 up to but not including n, which must not be 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        // draws over the last partial multiple of n would make smaller numbers more likely
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+fn arg_count_err(func: &str) -> Error {
+    Error::from_message(
+        ErrorType::RuntimeError,
+        "wrong-argument-count",
+        &[("func", &func)],
+        None,
+    )
+}
+
+fn random(args: Vec<RickrollObject>, rng: &mut Rng) -> Result<RickrollObject, Error> {
+    if args.len() != 2 {
+        return Err(arg_count_err("Random"));
+    }
+    let (low, high) = match (&args[0], &args[1]) {
+        (RickrollObject::Int(low), RickrollObject::Int(high)) => (*low, *high),
+        _ => return Err(arg_type_err("Random", &args, &["INT", "INT"])),
+    };
+    if low > high {
+        return Err(Error::from_message(
+            ErrorType::ValueError,
+            "random-empty-range",
+            &[("low", &low), ("high", &high)],
+            None,
+        ));
+    }
+    let span = (high as i64 - low as i64 + 1) as u64;
+    return Ok(RickrollObject::Int((low as i64 + rng.below(span) as i64) as i32));
+}
+
+// the array passed in is shared with the variable it came from, so it is copied before changing
+fn shuffle(args: Vec<RickrollObject>, rng: &mut Rng) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(arg_count_err("Shuffle"));
+    }
+    let mut arr = match &args[0] {
+        RickrollObject::Array(arr) => arr.to_vec(),
+        _ => return Err(arg_type_err("Shuffle", &args, &["ARRAY"])),
+    };
+    for ind in (1..arr.len()).rev() {
+        let other = rng.below(ind as u64 + 1) as usize;
+        arr.swap(ind, other);
+    }
+    return Ok(RickrollObject::Array(Rc::new(arr)));
+}
+
+fn sample(args: Vec<RickrollObject>, rng: &mut Rng) -> Result<RickrollObject, Error> {
+    if args.len() != 2 {
+        return Err(arg_count_err("Sample"));
+    }
+    let (mut arr, count) = match (&args[0], &args[1]) {
+        (RickrollObject::Array(arr), RickrollObject::Int(count)) => (arr.to_vec(), *count),
+        _ => return Err(arg_type_err("Sample", &args, &["ARRAY", "INT"])),
+    };
+    if count < 0 || count as usize > arr.len() {
+        return Err(Error::from_message(
+            ErrorType::ValueError,
+            "sample-too-large",
+            &[("count", &count), ("length", &arr.len())],
+            None,
+        ));
+    }
+    let count = count as usize;
+    for ind in 0..count {
+        let other = ind + rng.below((arr.len() - ind) as u64) as usize;
+        arr.swap(ind, other);
+    }
+    arr.truncate(count);
+    return Ok(RickrollObject::Array(Rc::new(arr)));
+}
+
+// runs one of RANDOM_FUNCTIONS
+pub fn run_random(func: &str, args: Vec<RickrollObject>, rng: &mut Rng) -> Result<RickrollObject, Error> {
+    match func {
+        "Random" => random(args, rng),
+        "Shuffle" => shuffle(args, rng),
+        "Sample" => sample(args, rng),
+        _ => panic!("run_random called with {}", func),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    use std::io::Cursor;
+
+    fn ints(values: &[i32]) -> RickrollObject {
+        RickrollObject::Array(Rc::new(values.iter().map(|value| RickrollObject::Int(*value)).collect()))
+    }
+
+    fn as_ints(obj: RickrollObject) -> Vec<i32> {
+        match obj {
+            RickrollObject::Array(arr) => arr
+                .iter()
+                .map(|obj| match obj {
+                    RickrollObject::Int(value) => *value,
+                    _ => panic!("{:?} isn't an INT", obj),
+                })
+                .collect(),
+            _ => panic!("{:?} isn't an ARRAY", obj),
+        }
+    }
+
+    fn sample(values: &[i32], count: i32, seed: u64) -> Result<RickrollObject, Error> {
+        return run_random("Sample", vec![ints(values), RickrollObject::Int(count)], &mut Rng::new(seed));
+    }
+
+    #[test]
+    fn draws_for_a_seed() {
+        // the reference SplitMix64 numbers for seed 0
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220A8397B1DCDAF);
+        assert_eq!(rng.next_u64(), 0x6E789E6AA1B965F4);
+        let mut rng = Rng::new(42);
+        let draws: Vec<u64> = (0..5).map(|_| rng.below(10)).collect();
+        assert_eq!(draws, [3, 1, 8, 4, 0]);
+    }
+
+    // shuffles, samples and draws a number with the same generator
+    const SEEDED: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna let b down
+Never gonna let low down
+Never gonna let high down
+Never gonna let count down
+Never gonna give a \"abcdef\"
+Never gonna give low 1
+Never gonna give high 6
+Never gonna give count 3
+(Ooh give you b) Never gonna run Shuffle and desert a
+Never gonna say b
+(Ooh give you b) Never gonna run Sample and desert a, count
+Never gonna say b
+(Ooh give you b) Never gonna run Random and desert low, high
+Never gonna say b
+Never gonna say a
+";
+
+    fn run_seeded(src: &str, seed: u64) -> String {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        let mut interpreter = Interpreter::new(Parser::new(tokens).parse().unwrap());
+        interpreter.set_seed(Some(seed));
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(Vec::new())).unwrap();
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn seeded_run() {
+        // pinned, so a change to the order of draws shows up here
+        let expected = "[e, d, a, c, f, b]\n[a, b, c]\n2\n[a, b, c, d, e, f]\n";
+        assert_eq!(run_seeded(SEEDED, 42), expected);
+        assert_eq!(run_seeded(SEEDED, 42), expected);
+        assert_ne!(run_seeded(SEEDED, 43), expected);
+    }
+
+    #[test]
+    fn shuffle_is_a_permutation() {
+        let values: Vec<i32> = (0..20).collect();
+        for seed in 0..50 {
+            let shuffled = run_random("Shuffle", vec![ints(&values)], &mut Rng::new(seed)).unwrap();
+            let mut shuffled = as_ints(shuffled);
+            shuffled.sort();
+            assert_eq!(shuffled, values);
+        }
+        assert_eq!(as_ints(run_random("Shuffle", vec![ints(&[])], &mut Rng::new(0)).unwrap()), []);
+    }
+
+    #[test]
+    fn sample_is_distinct() {
+        let values: Vec<i32> = (0..10).collect();
+        for seed in 0..50 {
+            let mut picked = as_ints(sample(&values, 4, seed).unwrap());
+            assert_eq!(picked.len(), 4);
+            picked.sort();
+            picked.dedup();
+            assert_eq!(picked.len(), 4);
+            assert!(picked.iter().all(|value| values.contains(value)));
+        }
+        assert_eq!(as_ints(sample(&[1, 2, 3], 3, 7).unwrap()).len(), 3);
+        assert_eq!(as_ints(sample(&[1, 2, 3], 0, 7).unwrap()), []);
+    }
+
+    #[test]
+    fn random_errors() {
+        for count in [-1, 4] {
+            let err = sample(&[1, 2, 3], count, 0).unwrap_err();
+            assert_eq!(err.kind(), ErrorType::ValueError);
+            assert_eq!(
+                err.to_string(),
+                format!("Value Error: Sample can't take {} elements from an array of length 3", count)
+            );
+        }
+        let bounds = vec![RickrollObject::Int(6), RickrollObject::Int(1)];
+        let err = run_random("Random", bounds, &mut Rng::new(0)).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("random-empty-range"));
+        let err = run_random("Shuffle", vec![RickrollObject::Int(1)], &mut Rng::new(0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = run_random("Sample", vec![ints(&[1])], &mut Rng::new(0)).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("wrong-argument-count"));
+    }
+}
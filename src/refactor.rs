@@ -0,0 +1,240 @@
+use crate::docs::{is_word_char, match_statement};
+use crate::error::*;
+use crate::lexer::*;
+use crate::util::*;
+
+use std::ops::Range;
+
+// words that can't be used as variable names
+const RESERVED_WORDS: [&str; 2] = ["you", "up"];
+
+// groups of each statement's pattern holding variable names or expressions
+// other groups hold function and type names, which are never renamed
+fn variable_groups(keyword: &str) -> &'static [usize] {
+    match keyword {
+        "SAY" | "LET" | "EXPECT" | "CHECK" | "CAPTURE" | "ARGS" | "RETURN" | "SLEEP" | "EXPLAIN" => &[1],
+        "ASSIGN" | "GUARD" => &[1, 2],
+        "CATCH" | "RUN" | "RAISE" => &[2],
+        "FOREACH" => &[1, 2, 3],
+        "RUN_ASSIGN" => &[1, 3],
+        _ => &[],
+    }
+}
+
+// groups of each statement's pattern that declare variables
+fn declaring_groups(keyword: &str) -> &'static [usize] {
+    match keyword {
+        "LET" | "CAPTURE" => &[1],
+        "CATCH" => &[2],
+        "FOREACH" => &[1, 2],
+        _ => &[],
+    }
+}
+
+// finds the words in a piece of a statement, skipping string and character literals
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut res = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = None;
+    for (ind, chr) in text.char_indices() {
+        if let Some(open) = quote {
+            if escaped {
+                escaped = false;
+            } else if chr == '\\' {
+                escaped = true;
+            } else if chr == open {
+                quote = None;
+            }
+            continue;
+        }
+        if is_word_char(chr) {
+            if start.is_none() {
+                start = Some(ind);
+            }
+            continue;
+        }
+        if let Some(pos) = start.take() {
+            res.push((pos, &text[pos..ind]));
+        }
+        if chr == '"' || chr == '\'' {
+            quote = Some(chr);
+        }
+    }
+    if let Some(pos) = start {
+        res.push((pos, &text[pos..]));
+    }
+    return res;
+}
+
+// finds where a name appears as a variable in a program, and the names of every variable
+// positions are byte ranges of the source
+fn find_variables(source: &str, name: &str) -> Result<(Vec<Range<usize>>, Vec<String>), Error> {
+    let raw: Vec<String> = source.split('\n').map(String::from).collect();
+    let mut found = Vec::new();
+    let mut declared = Vec::new();
+    let mut offset = 0;
+    let mut ptr = 0;
+    while ptr < raw.len() {
+        let (logical, extra) = logical_line(&raw, ptr, None)?;
+        let start = offset + logical.len() - logical.trim_start().len();
+        if let Some((keyword, captures)) = match_statement(logical.trim()) {
+            for ind in variable_groups(keyword) {
+                let group = match captures.get(*ind) {
+                    Some(group) => group,
+                    None => continue,
+                };
+                // a function without parameters, or a call without arguments
+                if RESERVED_WORDS.contains(&group.as_str()) {
+                    continue;
+                }
+                for (pos, word) in words(group.as_str()) {
+                    // parameters start the list or follow a comma, unlike default values
+                    let before = group.as_str()[..pos].trim_end();
+                    let is_param = keyword == "ARGS" && (before.is_empty() || before.ends_with(','));
+                    if declaring_groups(keyword).contains(ind) || is_param {
+                        declared.push(String::from(word));
+                    }
+                    if word == name {
+                        let begin = start + group.start() + pos;
+                        found.push(begin..(begin + word.len()));
+                    }
+                }
+            }
+        }
+        for line in &raw[ptr..=(ptr + extra)] {
+            offset += line.len() + 1;
+        }
+        ptr += extra + 1;
+    }
+    return Ok((found, declared));
+}
+
+// renames a variable everywhere it is declared and used, keeping the rest of the source as it is
+// variables of the same name in different functions are all renamed
+pub fn rename_variable(source: &str, old: &str, new: &str) -> Result<String, Error> {
+    // the program has to be valid to know what is a variable
+    Lexer::new(String::from(source)).parse()?;
+    if new.is_empty() || !new.chars().all(is_word_char) {
+        return Err(Error::from_message(
+            ErrorType::IllegalCharError,
+            "invalid-variable-name",
+            &[("name", &new)],
+            None,
+        ));
+    }
+    if RESERVED_WORDS.contains(&new) || from_constant(&String::from(new)).is_some() || new.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(Error::from_message(
+            ErrorType::NameError,
+            "reserved-word",
+            &[("name", &new)],
+            None,
+        ));
+    }
+    let (found, declared) = find_variables(source, old)?;
+    if !declared.iter().any(|name| name == old) {
+        return Err(Error::from_message(
+            ErrorType::NameError,
+            "undeclared-variable",
+            &[("name", &old)],
+            None,
+        ));
+    }
+    if old == new {
+        return Ok(String::from(source));
+    }
+    if declared.iter().any(|name| name == new) {
+        return Err(Error::from_message(
+            ErrorType::NameError,
+            "duplicate-variable",
+            &[("name", &new)],
+            None,
+        ));
+    }
+    let mut res = String::with_capacity(source.len());
+    let mut last = 0;
+    for range in found {
+        res += &source[last..range.start];
+        res += new;
+        last = range.end;
+    }
+    res += &source[last..];
+    return Ok(res);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    const PROGRAM: &str = "\
+[Verse show]
+(Ooh give you a, b = 1)
+Never gonna say a  +  b
+
+[Chorus]
+Never gonna let a down
+Never gonna let ab down
+Never gonna give a 2
+Never gonna give ab a * 3
+Inside we both know a < ab
+    Never gonna run show and desert a
+Your heart's been aching but you're too shy to say it
+We've known each c, a2 in \"a\" for so long
+    Never gonna say a
+We know the game and we're gonna play it
+";
+
+    #[test]
+    fn rename_everywhere() {
+        let renamed = rename_variable(PROGRAM, "a", "count").unwrap();
+        let expected = PROGRAM
+            .replace("you a, b", "you count, b")
+            .replace("say a  +", "say count  +")
+            .replace("let a down", "let count down")
+            .replace("give a 2", "give count 2")
+            .replace("ab a * 3", "ab count * 3")
+            .replace("know a < ab", "know count < ab")
+            .replace("desert a\n", "desert count\n")
+            .replace("say a\n", "say count\n");
+        assert_eq!(renamed, expected);
+        assert_eq!(run_source(&renamed, "").unwrap(), run_source(PROGRAM, "").unwrap());
+    }
+
+    #[test]
+    fn rename_collisions() {
+        let err = rename_variable(PROGRAM, "a", "ab").unwrap_err();
+        assert_eq!(err.kind(), ErrorType::NameError);
+        assert_eq!(err.root().message_id(), Some("duplicate-variable"));
+        // parameters and loop variables are variables too
+        assert!(rename_variable(PROGRAM, "ab", "b").is_err());
+        assert!(rename_variable(PROGRAM, "ab", "a2").is_err());
+        assert_eq!(rename_variable(PROGRAM, "a", "TRUE").unwrap_err().root().message_id(), Some("reserved-word"));
+        assert_eq!(rename_variable(PROGRAM, "a", "you").unwrap_err().root().message_id(), Some("reserved-word"));
+        assert_eq!(rename_variable(PROGRAM, "a", "a b").unwrap_err().kind(), ErrorType::IllegalCharError);
+        assert_eq!(rename_variable(PROGRAM, "z", "y").unwrap_err().root().message_id(), Some("undeclared-variable"));
+    }
+
+    #[test]
+    fn rename_leaves_literals_alone() {
+        let src = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a \"a \\\" a\" + \"\"\"
+a
+\"\"\" + 'a'
+Never gonna say a
+";
+        let renamed = rename_variable(src, "a", "b").unwrap();
+        assert_eq!(renamed, src.replace("let a", "let b").replace("give a", "give b").replace("say a", "say b"));
+    }
+
+    #[test]
+    fn rename_is_idempotent() {
+        let renamed = rename_variable(PROGRAM, "a", "count").unwrap();
+        // renaming again finds nothing left to rename
+        assert!(rename_variable(&renamed, "a", "count").is_err());
+        assert_eq!(rename_variable(&renamed, "count", "count").unwrap(), renamed);
+        assert_eq!(rename_variable(&renamed, "count", "a").unwrap(), PROGRAM);
+    }
+}
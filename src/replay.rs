@@ -0,0 +1,265 @@
+use crate::error::*;
+use crate::expr::render_value;
+use crate::interpreter::Interpreter;
+use crate::util::*;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// what a program got from outside of itself while it ran: the input it read, the time, random
+// numbers and environment variables, as the built-in function called and the value it returned
+// written as text, each step is a line with the function and the value as an expression, so a
+// recording can be saved, read and changed by hand
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    steps: Vec<(String, RickrollObject)>,
+}
+
+// writes a value as an expression that evaluates back to it
+// arrays without a literal are written as a call of ArrayOf, which is only read back here
+fn write_value(obj: &RickrollObject) -> String {
+    if let RickrollObject::Array(arr) = obj {
+        if !arr.is_empty() && from_char_array(obj).is_none() {
+            let elements: Vec<String> = arr.iter().map(write_value).collect();
+            return format!("ArrayOf({})", elements.join(", "));
+        }
+    }
+    // every other value has a literal
+    return render_value(obj).unwrap();
+}
+
+// splits the elements of an ArrayOf at the commas outside of literals and inner arrays
+fn split_elements(text: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (ind, chr) in text.char_indices() {
+        if let Some(open) = quote {
+            if escaped {
+                escaped = false;
+            } else if chr == '\\' {
+                escaped = true;
+            } else if chr == open {
+                quote = None;
+            }
+            continue;
+        }
+        match chr {
+            '"' | '\'' => quote = Some(chr),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(text[start..ind].trim());
+                start = ind + 1;
+            }
+            _ => (),
+        }
+    }
+    res.push(text[start..].trim());
+    return res;
+}
+
+fn read_value(text: &str, reader: &Interpreter) -> Option<RickrollObject> {
+    if let Some(inner) = text.strip_prefix("ArrayOf(").and_then(|rest| rest.strip_suffix(')')) {
+        let mut elements = Vec::new();
+        for element in split_elements(inner) {
+            elements.push(read_value(element, reader)?);
+        }
+        return Some(RickrollObject::Array(Rc::new(elements)));
+    }
+    return reader.eval_source(text).ok();
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording { steps: Vec::new() }
+    }
+
+    pub fn push(&mut self, func: &str, obj: RickrollObject) {
+        self.steps.push((String::from(func), obj));
+    }
+
+    pub fn steps(&self) -> &Vec<(String, RickrollObject)> {
+        &self.steps
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut res = String::new();
+        for (func, obj) in &self.steps {
+            res += &format!("{} {}\n", func, write_value(obj));
+        }
+        return res;
+    }
+
+    // reads a recording written by to_text, skipping blank lines
+    pub fn from_text(text: &str) -> Result<Recording, Error> {
+        let reader = Interpreter::new(HashMap::new());
+        let mut recording = Recording::new();
+        for (ind, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let step = line.trim().split_once(' ').and_then(|(func, value)| Some((func, read_value(value, &reader)?)));
+            match step {
+                Some((func, obj)) => recording.push(func, obj),
+                None => {
+                    return Err(Error::from_message(
+                        ErrorType::ValueError,
+                        "bad-recording-line",
+                        &[("line", &(ind + 1))],
+                        None,
+                    ))
+                }
+            }
+        }
+        return Ok(recording);
+    }
+}
+
+// goes through a recording, giving the values the steps returned in order
+#[derive(Debug, Clone)]
+pub struct Replay {
+    recording: Recording,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Replay {
+        Replay { recording, next: 0 }
+    }
+
+    // starts over from the first step, for another run
+    pub fn restart(&mut self) {
+        self.next = 0;
+    }
+
+    // gets the value the next step returned, which must be a call of the same function
+    pub fn next(&mut self, func: &str) -> Result<RickrollObject, Error> {
+        let step = self.next + 1;
+        return match self.recording.steps.get(self.next) {
+            Some((recorded, obj)) if recorded == func => {
+                self.next += 1;
+                Ok(obj.clone())
+            }
+            Some((recorded, _)) => Err(Error::from_message(
+                ErrorType::RuntimeError,
+                "replay-diverged",
+                &[("step", &step), ("func", &func), ("recorded", &recorded)],
+                None,
+            )),
+            None => Err(Error::from_message(
+                ErrorType::RuntimeError,
+                "replay-exhausted",
+                &[("step", &step), ("func", &func), ("steps", &self.recording.steps.len())],
+                None,
+            )),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    use std::io::Cursor;
+
+    // reads two numbers, then a random number between them and a shuffled array of them
+    const GUESS: &str = "\
+[Chorus]
+Never gonna let low down
+Never gonna let high down
+Never gonna let pick down
+Never gonna let both down
+Never gonna let order down
+(Ooh give you low) Never gonna run ReadToken and desert you
+(Ooh give you high) Never gonna run ReadToken and desert you
+(Ooh give you pick) Never gonna run Random and desert low, high
+(Ooh give you both) Never gonna run ArrayOf and desert low, high
+(Ooh give you order) Never gonna run Shuffle and desert both
+Never gonna say pick
+Never gonna say order
+";
+
+    fn interpreter(src: &str) -> Interpreter {
+        let tokens = Lexer::new(String::from(src)).parse().unwrap();
+        return Interpreter::new(Parser::new(tokens).parse().unwrap());
+    }
+
+    fn run(interpreter: &mut Interpreter, input: &str) -> Result<String, Error> {
+        let mut output: Vec<u8> = Vec::new();
+        interpreter.run(&mut output, &mut Cursor::new(input.as_bytes()))?;
+        return Ok(String::from_utf8_lossy(&output).into_owned());
+    }
+
+    fn record(src: &str, input: &str) -> (String, Recording) {
+        let mut recorder = interpreter(src);
+        recorder.set_recording(true);
+        let output = run(&mut recorder, input).unwrap();
+        return (output, recorder.recording().unwrap().clone());
+    }
+
+    fn replay(src: &str, recording: Recording) -> Result<String, Error> {
+        let mut replayer = interpreter(src);
+        replayer.set_replay(Some(recording));
+        return run(&mut replayer, "");
+    }
+
+    #[test]
+    fn replays_recorded_run() {
+        let (output, recording) = record(GUESS, "1 1000000\n");
+        let funcs: Vec<&str> = recording.steps().iter().map(|(func, _)| func.as_str()).collect();
+        assert_eq!(funcs, ["ReadToken", "ReadToken", "Random", "Shuffle"]);
+        assert_eq!(replay(GUESS, recording).unwrap(), output);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let (output, recording) = record(GUESS, "1 1000000\n");
+        let text = recording.to_text();
+        assert!(text.starts_with("ReadToken 1\nReadToken 1000000\nRandom "));
+        assert!(text.contains("Shuffle ArrayOf("));
+        let read = Recording::from_text(&text).unwrap();
+        assert_eq!(read.to_text(), text);
+        assert_eq!(replay(GUESS, read).unwrap(), output);
+    }
+
+    #[test]
+    fn bad_recording_line() {
+        let err = Recording::from_text("ReadToken 1\n\nReadToken\n").unwrap_err();
+        assert_eq!(err.root().message_id(), Some("bad-recording-line"));
+        assert_eq!(err.root().params(), vec![(String::from("line"), String::from("3"))]);
+    }
+
+    #[test]
+    fn diverged_replay() {
+        let (_, recording) = record(GUESS, "1 1000000\n");
+        // shuffles before picking the random number
+        let changed = GUESS.replace(
+            "(Ooh give you pick) Never gonna run Random and desert low, high\n",
+            "",
+        ) + "(Ooh give you pick) Never gonna run Random and desert low, high\n";
+        let err = replay(&changed, recording).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("replay-diverged"));
+        assert_eq!(
+            err.root().params(),
+            vec![
+                (String::from("step"), String::from("3")),
+                (String::from("func"), String::from("Shuffle")),
+                (String::from("recorded"), String::from("Random")),
+            ]
+        );
+    }
+
+    #[test]
+    fn exhausted_replay() {
+        let (_, recording) = record(GUESS, "1 1000000\n");
+        let longer = format!("{}(Ooh give you pick) Never gonna run Random and desert low, high\n", GUESS);
+        let err = replay(&longer, recording).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("replay-exhausted"));
+        assert_eq!(err.root().params()[2], (String::from("steps"), String::from("4")));
+    }
+}
@@ -1,12 +1,17 @@
 use crate::util::*;
 use crate::error::*;
+use crate::random::RANDOM_FUNCTIONS;
 
 use lazy_static::lazy_static;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::{BufRead, Write};
+use std::rc::Rc;
 
 type LibFunction = fn(Vec<RickrollObject>, &mut dyn Write, &mut dyn BufRead) -> Result<RickrollObject, Error>;
+// in-place functions modify the variable passed as their first argument
+type InPlaceFunction = fn(&mut RickrollObject, Vec<RickrollObject>) -> Result<RickrollObject, Error>;
 
 lazy_static! {
     pub static ref BUILTIN_FUNCTIONS: HashMap<String, LibFunction> = {
@@ -16,38 +21,157 @@ lazy_static! {
         m.insert(String::from("ArrayPush"), array_push as LibFunction);
         m.insert(String::from("ArrayReplace"), array_replace as LibFunction);
         m.insert(String::from("ArrayLength"), array_length as LibFunction);
+        m.insert(String::from("Length"), length as LibFunction);
+        m.insert(String::from("ArrayRange"), array_range as LibFunction);
+        m.insert(String::from("ArraySlice"), array_slice as LibFunction);
+        m.insert(String::from("ArrayRangeInclusive"), array_range_inclusive as LibFunction);
+        m.insert(String::from("ArraySort"), array_sort as LibFunction);
+        m.insert(String::from("ArraySortBy"), array_sort_by as LibFunction);
+        m.insert(String::from("ArrayUnique"), array_unique as LibFunction);
+        m.insert(String::from("ArrayReverse"), array_reverse as LibFunction);
+        m.insert(String::from("ArrayRepeat"), array_repeat as LibFunction);
+        m.insert(String::from("Min"), min as LibFunction);
+        m.insert(String::from("Max"), max as LibFunction);
+        m.insert(String::from("Between"), between as LibFunction);
+        m.insert(String::from("Abs"), abs as LibFunction);
+        m.insert(String::from("Floor"), floor as LibFunction);
+        m.insert(String::from("Ceil"), ceil as LibFunction);
+        m.insert(String::from("Round"), round as LibFunction);
+        m.insert(String::from("Sqrt"), sqrt as LibFunction);
+        m.insert(String::from("Pow"), pow as LibFunction);
+        m.insert(String::from("FormatNumber"), format_number as LibFunction);
+        m.insert(String::from("TypeOf"), type_of as LibFunction);
+        m.insert(String::from("IsInt"), is_int as LibFunction);
+        m.insert(String::from("IsFloat"), is_float as LibFunction);
+        m.insert(String::from("IsBool"), is_bool as LibFunction);
+        m.insert(String::from("IsArray"), is_array as LibFunction);
+        m.insert(String::from("IsChar"), is_char as LibFunction);
+        m.insert(String::from("IsUndefined"), is_undefined as LibFunction);
         m.insert(String::from("PutChar"), put_char as LibFunction);
         m.insert(String::from("ReadLine"), read_line as LibFunction);
+        m.insert(String::from("ReadToken"), read_token as LibFunction);
+        m.insert(String::from("ReadKey"), read_key as LibFunction);
+        m.insert(String::from("ParseCsv"), parse_csv as LibFunction);
+        m.insert(String::from("GetEnv"), get_env as LibFunction);
+        m.insert(String::from("AssertEqual"), assert_equal as LibFunction);
         m
     };
+    pub static ref IN_PLACE_FUNCTIONS: HashMap<String, InPlaceFunction> = {
+        let mut m = HashMap::new();
+        m.insert(String::from("Push"), push as InPlaceFunction);
+        m.insert(String::from("Pop"), pop as InPlaceFunction);
+        m.insert(String::from("TryPop"), try_pop as InPlaceFunction);
+        m.insert(String::from("Shift"), shift as InPlaceFunction);
+        m.insert(String::from("TryShift"), try_shift as InPlaceFunction);
+        m.insert(String::from("Unshift"), unshift as InPlaceFunction);
+        m.insert(String::from("Increment"), increment as InPlaceFunction);
+        m
+    };
+}
+
+// longest value shown in an assertion failure
+pub const ASSERT_VALUE_LIMIT: usize = 40;
+
+// built-in functions that read the interpreter's clock
+// they are run by the interpreter, since the clock can be replaced
+pub const CLOCK_FUNCTIONS: [&str; 2] = ["Now", "Elapsed"];
+
+// built-in functions that can't be called in sandbox mode
+pub const PRIVILEGED_FUNCTIONS: [&str; 3] = ["GetEnv", "Now", "Elapsed"];
+
+// built-in functions that read the program's input
+pub const INPUT_FUNCTIONS: [&str; 3] = ["ReadLine", "ReadToken", "ReadKey"];
+
+// estimates the work of a call to a built-in function from its arguments, before it runs
+// work is the number of elements the call makes or goes over
+// in-place functions get the variable they change as their first argument
+pub fn work_of(func: &str, args: &[RickrollObject]) -> usize {
+    use RickrollObject::*;
+    let len = |obj: &RickrollObject| match obj {
+        Array(x) => x.len(),
+        _ => 0,
+    };
+    match (func, args) {
+        ("ArrayOf", _) => return args.len(),
+        ("ArrayRepeat", [array, Int(count)]) => return len(array).saturating_mul((*count).max(0) as usize),
+        ("ArrayRange" | "ArrayRangeInclusive", [Int(start), Int(end), rest @ ..]) => {
+            let step = match rest {
+                [Int(step)] if *step > 0 => *step as i64,
+                [] => 1,
+                // the call fails without making anything
+                _ => return 0,
+            };
+            return ((*end as i64 - *start as i64).max(0) / step + 1) as usize;
+        }
+        // one element is changed without going over the array
+        ("Push" | "Pop" | "TryPop" | "Increment", _) => return 1,
+        _ => return args.iter().map(len).sum(),
+    }
+}
+
+// gets the number of elements an in-place function adds to its array
+pub fn growth_of(func: &str) -> usize {
+    match func {
+        "Push" | "Unshift" => return 1,
+        _ => return 0,
+    }
+}
+
+// checks if a built-in function of any kind exists
+pub fn is_builtin(name: &String) -> bool {
+    BUILTIN_FUNCTIONS.contains_key(name)
+        || IN_PLACE_FUNCTIONS.contains_key(name)
+        || CLOCK_FUNCTIONS.contains(&&name[..])
+        || RANDOM_FUNCTIONS.contains(&&name[..])
+}
+
+// makes an error naming the first argument that doesn't have one of the expected types
+// expected types are separated by "|"
+pub(crate) fn arg_type_err(func: &str, args: &[RickrollObject], expected: &[&str]) -> Error {
+    for (ind, (arg, types)) in args.iter().zip(expected.iter()).enumerate() {
+        if !types.split('|').any(|name| name == arg.type_name()) {
+            return Error::from_message(
+                ErrorType::TypeError,
+                "argument-type-mismatch",
+                &[("func", &func), ("expected", &types.replace("|", " or ")), ("position", &(ind + 1)), ("found", &describe_value(arg))],
+                None,
+            );
+        }
+    }
+    return Error::from_message(
+        ErrorType::TypeError,
+        "wrong-argument-types",
+        &[("func", &func)],
+        None,
+    );
 }
 
 fn array_of(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
-    return Ok(RickrollObject::Array(args));
+    return Ok(RickrollObject::Array(Rc::new(args)));
 }
 
 fn array_pop(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
     if args.len() != 2 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for ArrayPop", None));
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayPop")], None));
     }
     let arr = args[0].clone();
     let idx = args[1].clone();
     if let RickrollObject::Array(mut x) = arr {
         if let RickrollObject::Int(y) = idx {
             if y >= 0 && (y as usize) < x.len() {
-                x.remove(y as usize);
+                Rc::make_mut(&mut x).remove(y as usize);
                 return Ok(RickrollObject::Array(x));
             }  else {
-                return Err(Error::new(ErrorType::RuntimeError, "Array Index out of Bounds", None));
+                return Err(Error::from_message(ErrorType::RuntimeError, "array-index-out-of-bounds", &[], None));
             }
         }
     }
-    return Err(Error::new(ErrorType::RuntimeError, "Wrong type of arguments for ArrayPop", None));
+    return Err(arg_type_err("ArrayPop", &args, &["ARRAY", "INT"]));
 }
 
 fn array_push(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
     if args.len() != 3 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for ArrayPush", None));
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayPush")], None));
     }
     let arr = args[0].clone();
     let idx = args[1].clone();
@@ -55,19 +179,19 @@ fn array_push(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead)
     if let RickrollObject::Array(mut x) = arr {
         if let RickrollObject::Int(y) = idx {
             if y >= 0 && (y as usize) <= x.len() {
-                x.insert(y as usize, val);
+                Rc::make_mut(&mut x).insert(y as usize, val);
                 return Ok(RickrollObject::Array(x));
             } else {
-                return Err(Error::new(ErrorType::RuntimeError, "Array Index out of Bounds", None));
+                return Err(Error::from_message(ErrorType::RuntimeError, "array-index-out-of-bounds", &[], None));
             }
         }
     }
-    return Err(Error::new(ErrorType::RuntimeError, "Wrong type of arguments for ArrayPush", None));
+    return Err(arg_type_err("ArrayPush", &args, &["ARRAY", "INT"]));
 }
 
 fn array_replace(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
     if args.len() != 3 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for ArrayReplace", None));
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayReplace")], None));
     }
     let arr = args[0].clone();
     let idx = args[1].clone();
@@ -75,44 +199,524 @@ fn array_replace(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRe
     if let RickrollObject::Array(mut x) = arr {
         if let RickrollObject::Int(y) = idx {
             if y >= 0 && (y as usize) < x.len() {
-                x[y as usize] = val;
+                Rc::make_mut(&mut x)[y as usize] = val;
                 return Ok(RickrollObject::Array(x));
             } else {
-                return Err(Error::new(ErrorType::RuntimeError, "Array Index out of Bounds", None));
+                return Err(Error::from_message(ErrorType::RuntimeError, "array-index-out-of-bounds", &[], None));
             }
         }
     }
-    return Err(Error::new(ErrorType::RuntimeError, "Wrong type of arguments for ArrayReplace", None));
+    return Err(arg_type_err("ArrayReplace", &args, &["ARRAY", "INT"]));
 }
 
-fn array_length(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+// counts the elements of any container, which includes strings
+fn container_length(name: &str, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
     if args.len() != 1 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for ArrayLength", None));
+        return Err(Error::from_message(
+            ErrorType::RuntimeError,
+            "wrong-argument-count",
+            &[("func", &name)],
+            None,
+        ));
     }
-    let arr = args[0].clone();
-    if let RickrollObject::Array(x) = arr {
+    if let RickrollObject::Array(x) = &args[0] {
         return Ok(RickrollObject::Int(x.len() as i32));
     }
-    return Err(Error::new(ErrorType::RuntimeError, "Wrong type of arguments for ArrayLength", None));
+    return Err(arg_type_err(name, &args, &["ARRAY"]));
+}
+
+fn array_length(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return container_length("ArrayLength", args);
+}
+
+fn length(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return container_length("Length", args);
+}
+
+// makes an array counting from start by step while the value passes the end check
+fn make_range(name: &str, args: Vec<RickrollObject>, inclusive: bool) -> Result<RickrollObject, Error> {
+    if args.len() != 2 && args.len() != 3 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &name)], None));
+    }
+    let mut bounds = Vec::new();
+    for arg in &args {
+        if let RickrollObject::Int(x) = arg {
+            bounds.push(*x as i64);
+        } else {
+            return Err(arg_type_err(name, &args, &["INT", "INT", "INT"]));
+        }
+    }
+    let step = if bounds.len() == 3 { bounds[2] } else { 1 };
+    if step <= 0 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "nonpositive-step", &[("func", &name)], None));
+    }
+    let mut arr = Vec::new();
+    let mut cur = bounds[0];
+    // out of order bounds make an empty array
+    while cur < bounds[1] || (inclusive && cur == bounds[1]) {
+        arr.push(RickrollObject::Int(cur as i32));
+        cur += step;
+    }
+    return Ok(RickrollObject::Array(Rc::new(arr)));
+}
+
+fn array_range(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return make_range("ArrayRange", args, false);
+}
+
+fn array_range_inclusive(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return make_range("ArrayRangeInclusive", args, true);
+}
+
+// resolves a slice bound, counting negative bounds from the end
+// bounds past either end are clamped
+fn slice_bound(bound: &RickrollObject, default: usize, len: usize) -> Option<usize> {
+    match bound {
+        RickrollObject::Undefined => Some(default),
+        RickrollObject::Int(x) => {
+            let x = *x as i64;
+            let x = if x < 0 { x + len as i64 } else { x };
+            Some(x.max(0).min(len as i64) as usize)
+        }
+        _ => None,
+    }
+}
+
+fn array_slice(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 3 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArraySlice")], None));
+    }
+    if let RickrollObject::Array(x) = &args[0] {
+        let start = slice_bound(&args[1], 0, x.len());
+        let end = slice_bound(&args[2], x.len(), x.len());
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Ok(RickrollObject::Array(Rc::new(Vec::new())));
+            }
+            return Ok(RickrollObject::Array(Rc::new(Vec::from(&x[start..end]))));
+        }
+    }
+    return Err(arg_type_err("ArraySlice", &args, &["ARRAY", "INT|UNDEFINED", "INT|UNDEFINED"]));
+}
+
+fn array_sort(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArraySort")], None));
+    }
+    if let RickrollObject::Array(x) = &args[0] {
+        return sort_by_key("ArraySort", x.to_vec(), |elem| elem);
+    }
+    return Err(arg_type_err("ArraySort", &args, &["ARRAY"]));
+}
+
+// sorts the elements by the keys the function gets from them
+fn sort_by_key<F>(func: &str, mut sorted: Vec<RickrollObject>, key: F) -> Result<RickrollObject, Error>
+where
+    F: Fn(&RickrollObject) -> &RickrollObject,
+{
+    let mut failed = None;
+    // the sort is stable, so equal elements keep their order
+    sorted.sort_by(|a, b| match compare(key(a), key(b)) {
+        Some(ord) => ord,
+        None => {
+            if failed.is_none() {
+                failed = Some((describe_value(key(a)), describe_value(key(b))));
+            }
+            std::cmp::Ordering::Equal
+        }
+    });
+    if let Some((first, second)) = failed {
+        return Err(Error::from_message(
+            ErrorType::TypeError,
+            "sort-incomparable",
+            &[("func", &func), ("left", &first), ("right", &second)],
+            None,
+        ));
+    }
+    return Ok(RickrollObject::Array(Rc::new(sorted)));
+}
+
+// sorts arrays such as records by one of their elements
+fn array_sort_by(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArraySortBy")], None));
+    }
+    if let (RickrollObject::Array(x), RickrollObject::Int(ind)) = (&args[0], &args[1]) {
+        // every element is checked first, so the sort can take the keys without failing
+        for (pos, elem) in x.iter().enumerate() {
+            match elem {
+                RickrollObject::Array(inner) if *ind >= 0 && (*ind as usize) < inner.len() => (),
+                RickrollObject::Array(inner) => {
+                    return Err(Error::from_message(
+                        ErrorType::IndexOutOfBoundsError,
+                        "index-out-of-bounds",
+                        &[("index", &ind), ("length", &inner.len())],
+                        None,
+                    ))
+                }
+                other => {
+                    return Err(Error::from_message(
+                        ErrorType::TypeError,
+                        "sort-key-not-array",
+                        &[("position", &pos), ("found", &describe_value(other))],
+                        None,
+                    ))
+                }
+            }
+        }
+        let ind = *ind as usize;
+        return sort_by_key("ArraySortBy", x.to_vec(), |elem| match elem {
+            RickrollObject::Array(inner) => &inner[ind],
+            _ => panic!("array_sort_by called with an element that isn't an array"),
+        });
+    }
+    return Err(arg_type_err("ArraySortBy", &args, &["ARRAY", "INT"]));
+}
+
+fn array_unique(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayUnique")], None));
+    }
+    if let RickrollObject::Array(x) = &args[0] {
+        let mut unique: Vec<RickrollObject> = Vec::new();
+        for elem in x.iter() {
+            // values are duplicates if == says they are equal, which an INT and a FLOAT can be
+            if !unique.iter().any(|seen| equals(seen, elem)) {
+                unique.push(elem.clone());
+            }
+        }
+        return Ok(RickrollObject::Array(Rc::new(unique)));
+    }
+    return Err(arg_type_err("ArrayUnique", &args, &["ARRAY"]));
+}
+
+fn array_reverse(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayReverse")], None));
+    }
+    if let RickrollObject::Array(x) = &args[0] {
+        // strings are arrays of whole characters, so they reverse character by character
+        return Ok(RickrollObject::Array(Rc::new(x.iter().rev().cloned().collect())));
+    }
+    return Err(arg_type_err("ArrayReverse", &args, &["ARRAY"]));
+}
+
+fn array_repeat(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ArrayRepeat")], None));
+    }
+    if let (RickrollObject::Array(x), RickrollObject::Int(count)) = (&args[0], &args[1]) {
+        if *count < 0 {
+            return Err(Error::from_message(
+                ErrorType::ValueError,
+                "repeat-negative",
+                &[("count", &count)],
+                None,
+            ));
+        }
+        // lengths have to fit in an INT
+        let len = match x.len().checked_mul(*count as usize) {
+            Some(len) if len <= i32::MAX as usize => len,
+            _ => {
+                return Err(Error::from_message(
+                    ErrorType::ValueError,
+                    "repeat-too-long",
+                    &[("length", &x.len()), ("count", &count)],
+                    None,
+                ))
+            }
+        };
+        let repeated: Vec<RickrollObject> = x.iter().cycle().take(len).cloned().collect();
+        return Ok(RickrollObject::Array(Rc::new(repeated)));
+    }
+    return Err(arg_type_err("ArrayRepeat", &args, &["ARRAY", "INT"]));
+}
+
+// finds the smallest (or largest, if wanted is Greater) of several values or of the elements of an array
+fn extreme(name: &str, args: Vec<RickrollObject>, wanted: std::cmp::Ordering) -> Result<RickrollObject, Error> {
+    let values = match args.len() {
+        1 => match &args[0] {
+            RickrollObject::Array(x) => x.to_vec(),
+            _ => return Err(arg_type_err(name, &args, &["ARRAY"])),
+        },
+        0 => {
+            return Err(Error::from_message(
+                ErrorType::RuntimeError,
+                "wrong-argument-count",
+                &[("func", &name)],
+                None,
+            ))
+        }
+        _ => args,
+    };
+    if values.is_empty() {
+        return Err(Error::from_message(
+            ErrorType::ValueError,
+            "empty-array",
+            &[("func", &name)],
+            None,
+        ));
+    }
+    let mut best = &values[0];
+    for value in &values[1..] {
+        match compare(value, best) {
+            Some(ord) if ord == wanted => best = value,
+            Some(_) => (),
+            None => {
+                return Err(Error::from_message(
+                    ErrorType::TypeError,
+                    "incomparable",
+                    &[("func", &name), ("left", &describe_value(best)), ("right", &describe_value(value))],
+                    None,
+                ))
+            }
+        }
+    }
+    return Ok(best.clone());
+}
+
+fn min(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return extreme("Min", args, std::cmp::Ordering::Less);
+}
+
+fn max(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return extreme("Max", args, std::cmp::Ordering::Greater);
+}
+
+// checks low <= value <= high, which can't be written as a chain of comparisons
+fn between(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 3 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Between")], None));
+    }
+    let (value, low, high) = (&args[0], &args[1], &args[2]);
+    let mut inside = true;
+    for (left, right) in [(low, value), (value, high)] {
+        match compare(left, right) {
+            Some(ord) => inside &= ord != std::cmp::Ordering::Greater,
+            // every comparison with NaN is false
+            None if left.is_number() && right.is_number() => inside = false,
+            None => {
+                return Err(Error::from_message(
+                    ErrorType::TypeError,
+                    "between-incomparable",
+                    &[("left", &describe_value(left)), ("right", &describe_value(right))],
+                    None,
+                ))
+            }
+        }
+    }
+    return Ok(RickrollObject::Bool(inside));
+}
+
+fn abs(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Abs")], None));
+    }
+    return match &args[0] {
+        // like the arithmetic operators, the smallest INT wraps around
+        RickrollObject::Int(x) => Ok(RickrollObject::Int(x.wrapping_abs())),
+        RickrollObject::Float(x) => Ok(RickrollObject::Float(x.abs())),
+        _ => Err(arg_type_err("Abs", &args, &["INT|FLOAT"])),
+    };
+}
+
+// converts a number to an INT after rounding floats with the given function
+fn to_int(name: &str, args: Vec<RickrollObject>, rounding: fn(f32) -> f32) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(
+            ErrorType::RuntimeError,
+            "wrong-argument-count",
+            &[("func", &name)],
+            None,
+        ));
+    }
+    return match &args[0] {
+        RickrollObject::Int(x) => Ok(RickrollObject::Int(*x)),
+        RickrollObject::Float(x) => {
+            let rounded = rounding(*x);
+            // casting would silently saturate
+            if rounded.is_nan() || rounded < i32::MIN as f32 || rounded >= i32::MAX as f32 {
+                return Err(Error::from_message(
+                    ErrorType::ValueError,
+                    "int-out-of-range",
+                    &[("func", &name), ("value", &format_float(*x))],
+                    None,
+                ));
+            }
+            Ok(RickrollObject::Int(rounded as i32))
+        }
+        _ => Err(arg_type_err(name, &args, &["INT|FLOAT"])),
+    };
+}
+
+fn floor(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return to_int("Floor", args, f32::floor);
+}
+
+fn ceil(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return to_int("Ceil", args, f32::ceil);
+}
+
+// halfway values are rounded away from zero
+fn round(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return to_int("Round", args, f32::round);
+}
+
+fn sqrt(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Sqrt")], None));
+    }
+    let x = match &args[0] {
+        RickrollObject::Int(x) => *x as f32,
+        RickrollObject::Float(x) => *x,
+        _ => return Err(arg_type_err("Sqrt", &args, &["INT|FLOAT"])),
+    };
+    if x < 0.0 {
+        return Err(Error::from_message(
+            ErrorType::RuntimeError,
+            "sqrt-negative",
+            &[("value", &format_float(x))],
+            None,
+        ));
+    }
+    return Ok(RickrollObject::Float(x.sqrt()));
+}
+
+fn pow(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    use RickrollObject::*;
+    if args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Pow")], None));
+    }
+    return match (&args[0], &args[1]) {
+        // like the arithmetic operators, INT results wrap around
+        (Int(x), Int(y)) if *y >= 0 => Ok(Int(x.wrapping_pow(*y as u32))),
+        (Int(_), Int(y)) => Err(Error::from_message(
+            ErrorType::ValueError,
+            "pow-negative",
+            &[("power", &y)],
+            None,
+        )),
+        (Float(x), Int(y)) => Ok(Float(x.powi(*y))),
+        (Float(x), Float(y)) => Ok(Float(x.powf(*y))),
+        _ => Err(arg_type_err("Pow", &args, &["INT|FLOAT", if let Int(_) = args[0] { "INT" } else { "INT|FLOAT" }])),
+    };
+}
+
+// writes a number as a string, with + in front of positive numbers if sign is TRUE,
+// and the digits before the point split into groups of the given size from the right
+// a group size of 0 doesn't group the digits
+fn format_number(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    use RickrollObject::*;
+    if args.is_empty() || args.len() > 4 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"FormatNumber")], None));
+    }
+    let wrong_types = || arg_type_err("FormatNumber", &args, &["INT|FLOAT", "BOOL", "INT", "CHAR"]);
+    let (text, positive) = match args[0] {
+        Int(x) => (x.to_string(), x > 0),
+        Float(x) => (format_float(x), x > 0.0),
+        _ => return Err(wrong_types()),
+    };
+    let sign = match args.get(1) {
+        None => false,
+        Some(Bool(sign)) => *sign,
+        Some(_) => return Err(wrong_types()),
+    };
+    let group = match args.get(2) {
+        None => 0,
+        Some(Int(size)) if *size >= 0 => *size as usize,
+        Some(Int(size)) => {
+            return Err(Error::from_message(
+                ErrorType::ValueError,
+                "negative-group-size",
+                &[("size", &size)],
+                None,
+            ))
+        }
+        Some(_) => return Err(wrong_types()),
+    };
+    let sep = match args.get(3) {
+        None => ',',
+        Some(Char(sep)) => *sep,
+        Some(_) => return Err(wrong_types()),
+    };
+    let (mut res, magnitude) = match text.strip_prefix('-') {
+        Some(magnitude) => (String::from("-"), magnitude),
+        None if sign && positive => (String::from("+"), &text[..]),
+        None => (String::new(), &text[..]),
+    };
+    // NaN and inf have no digits to group
+    let digits = magnitude.find(|chr: char| !chr.is_ascii_digit()).unwrap_or(magnitude.len());
+    for (ind, chr) in magnitude[..digits].chars().enumerate() {
+        if group > 0 && ind > 0 && (digits - ind) % group == 0 {
+            res.push(sep);
+        }
+        res.push(chr);
+    }
+    res += &magnitude[digits..];
+    return Ok(to_char_array(&res));
+}
+
+// the type names are the ones used by type errors
+fn type_of(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"TypeOf")], None));
+    }
+    return Ok(to_char_array(args[0].type_name()));
+}
+
+// checks if the only argument has the type with the given name
+fn has_type(name: &str, args: Vec<RickrollObject>, type_name: &str) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(
+            ErrorType::RuntimeError,
+            "wrong-argument-count",
+            &[("func", &name)],
+            None,
+        ));
+    }
+    return Ok(RickrollObject::Bool(args[0].type_name() == type_name));
+}
+
+fn is_int(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsInt", args, TYPE_NAMES[0]);
+}
+
+fn is_float(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsFloat", args, TYPE_NAMES[1]);
+}
+
+fn is_bool(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsBool", args, TYPE_NAMES[2]);
+}
+
+fn is_array(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsArray", args, TYPE_NAMES[3]);
+}
+
+fn is_char(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsChar", args, TYPE_NAMES[4]);
+}
+
+fn is_undefined(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    return has_type("IsUndefined", args, TYPE_NAMES[5]);
 }
 
 fn put_char(args: Vec<RickrollObject>, writer: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
     if args.len() != 1 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for PutChar", None));
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"PutChar")], None));
     }
     let chr = args[0].clone();
     if let RickrollObject::Char(x) = chr {
         let mut buffer: [u8; 4] = [0; 4];
-        x.encode_utf8(&mut buffer);
-        writer.write(&buffer).unwrap();
+        writer.write_all(x.encode_utf8(&mut buffer).as_bytes()).map_err(Error::from_write)?;
         return Ok(RickrollObject::Undefined);
     }
-    return Err(Error::new(ErrorType::RuntimeError, "Wrong type of arguments for PutChar", None));
+    return Err(arg_type_err("PutChar", &args, &["CHAR"]));
 }
 
 fn read_line(args: Vec<RickrollObject>, _: &mut dyn Write, reader: &mut dyn BufRead) -> Result<RickrollObject, Error> {
     if args.len() != 0 {
-        return Err(Error::new(ErrorType::RuntimeError, "Wrong number of arguments for ReadLine", None));
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ReadLine")], None));
     }
     let mut line = String::new();
     reader.read_line(&mut line).unwrap();
@@ -123,5 +727,1053 @@ fn read_line(args: Vec<RickrollObject>, _: &mut dyn Write, reader: &mut dyn BufR
         }
         arr.push(RickrollObject::Char(c));
     }
-    return Ok(RickrollObject::Array(arr));
+    return Ok(RickrollObject::Array(Rc::new(arr)));
+}
+
+// converts a token read from the input into a number if it is written as a number literal
+// input can also start with '-', since it has no operators
+fn parse_token(token: String) -> RickrollObject {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, &token[..]),
+    };
+    return match parse_number(digits) {
+        Ok(RickrollObject::Int(x)) if negative => RickrollObject::Int(-x),
+        Ok(RickrollObject::Float(x)) if negative => RickrollObject::Float(-x),
+        Ok(num) => num,
+        Err(_) => to_char_array(&token),
+    };
+}
+
+// splits delimited text into rows of fields, and whether each field was quoted
+// fields in double quotes can hold the separator, newlines, and "" for a quote
+// rows are numbered from 1 in errors
+fn split_csv(text: &str, sep: char) -> Result<Vec<Vec<(String, bool)>>, Error> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if in_quotes {
+            if chr != '"' {
+                field.push(chr);
+            } else if chars.peek() == Some(&'"') {
+                chars.next();
+                field.push('"');
+            } else {
+                in_quotes = false;
+            }
+            continue;
+        }
+        if chr == sep || chr == '\n' || (chr == '\r' && chars.peek() == Some(&'\n')) {
+            if chr == '\r' {
+                continue;
+            }
+            row.push((std::mem::take(&mut field), quoted));
+            quoted = false;
+            if chr == '\n' {
+                rows.push(std::mem::take(&mut row));
+            }
+        } else if quoted {
+            return Err(Error::from_message(
+                ErrorType::ValueError,
+                "csv-after-quoted-field",
+                &[("row", &(rows.len() + 1)), ("char", &chr)],
+                None,
+            ));
+        } else if chr == '"' {
+            if !field.is_empty() {
+                return Err(Error::from_message(ErrorType::ValueError, "csv-quote-in-field", &[("row", &(rows.len() + 1))], None));
+            }
+            quoted = true;
+            in_quotes = true;
+        } else {
+            field.push(chr);
+        }
+    }
+    if in_quotes {
+        return Err(Error::from_message(ErrorType::ValueError, "csv-unclosed-quote", &[("row", &(rows.len() + 1))], None));
+    }
+    // text ending with a newline has no empty row after it
+    if !row.is_empty() || !field.is_empty() || quoted {
+        row.push((field, quoted));
+        rows.push(row);
+    }
+    for (ind, row) in rows.iter().enumerate() {
+        if row.len() != rows[0].len() {
+            return Err(Error::from_message(
+                ErrorType::ValueError,
+                "csv-row-length",
+                &[("row", &(ind + 1)), ("fields", &row.len()), ("expected", &rows[0].len())],
+                None,
+            ));
+        }
+    }
+    return Ok(rows);
+}
+
+// parses delimited text, such as CSV, into an array of rows that are arrays of fields
+// unquoted fields written as numbers become numbers, like with ReadToken
+fn parse_csv(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 && args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ParseCsv")], None));
+    }
+    let sep = match args.get(1) {
+        None => ',',
+        Some(RickrollObject::Char(sep)) if *sep != '"' && *sep != '\n' => *sep,
+        Some(RickrollObject::Char(sep)) => {
+            return Err(Error::from_message(
+                ErrorType::ValueError,
+                "csv-bad-separator",
+                &[("separator", &format!("{:?}", sep))],
+                None,
+            ))
+        }
+        Some(_) => return Err(arg_type_err("ParseCsv", &args, &["ARRAY", "CHAR"])),
+    };
+    let text = match from_char_array(&args[0]) {
+        Some(text) => text,
+        None => return Err(arg_type_err("ParseCsv", &args, &["ARRAY", "CHAR"])),
+    };
+    let rows = split_csv(&text, sep)?;
+    let mut res = Vec::new();
+    for row in rows {
+        let mut fields = Vec::new();
+        for (field, quoted) in row {
+            fields.push(if quoted { to_char_array(&field) } else { parse_token(field) });
+        }
+        res.push(RickrollObject::Array(Rc::new(fields)));
+    }
+    return Ok(RickrollObject::Array(Rc::new(res)));
+}
+
+fn read_token(args: Vec<RickrollObject>, _: &mut dyn Write, reader: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ReadToken")], None));
+    }
+    let mut token = Vec::new();
+    loop {
+        let buf = reader.fill_buf().map_err(|e| {
+            Error::from_message(ErrorType::RuntimeError, "read-failed", &[("reason", &e)], None)
+        })?;
+        if buf.is_empty() {
+            break;
+        }
+        let mut used = 0;
+        let mut done = false;
+        for byte in buf {
+            if byte.is_ascii_whitespace() {
+                if !token.is_empty() {
+                    done = true;
+                    break;
+                }
+            } else {
+                token.push(*byte);
+            }
+            used += 1;
+        }
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+    // the input ended before another token
+    if token.is_empty() {
+        return Ok(RickrollObject::Undefined);
+    }
+    return Ok(parse_token(String::from_utf8_lossy(&token).into_owned()));
+}
+
+fn read_key(args: Vec<RickrollObject>, _: &mut dyn Write, reader: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"ReadKey")], None));
+    }
+    let read_err = |e: std::io::Error| Error::from_message(ErrorType::RuntimeError, "read-failed", &[("reason", &e)], None);
+    let mut bytes = [0; 4];
+    if reader.read(&mut bytes[..1]).map_err(read_err)? == 0 {
+        return Ok(RickrollObject::Undefined);
+    }
+    // the number of bytes in a UTF-8 character is given by its first byte
+    let len = match bytes[0].leading_ones() {
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        _ => 1,
+    };
+    reader.read_exact(&mut bytes[1..len]).map_err(read_err)?;
+    return match std::str::from_utf8(&bytes[..len]) {
+        Ok(string) => Ok(RickrollObject::Char(string.chars().next().unwrap())),
+        Err(_) => Ok(RickrollObject::Char(char::REPLACEMENT_CHARACTER)),
+    };
+}
+
+fn get_env(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"GetEnv")], None));
+    }
+    if let Some(name) = from_char_array(&args[0]) {
+        // unset variables are UNDEFINED
+        return match std::env::var(name) {
+            Ok(value) => Ok(to_char_array(&value)),
+            Err(_) => Ok(RickrollObject::Undefined),
+        };
+    }
+    return Err(arg_type_err("GetEnv", &args, &["ARRAY"]));
+}
+
+// renders a value for an assertion failure, showing strings in quotes
+fn show_value(obj: &RickrollObject) -> String {
+    match from_char_array(obj) {
+        Some(string) if !string.is_empty() => {
+            let mut res: String = string.chars().take(ASSERT_VALUE_LIMIT).collect();
+            if string.chars().count() > ASSERT_VALUE_LIMIT {
+                res += "...";
+            }
+            format!("\"{}\"", res)
+        }
+        _ => summarize(obj, ASSERT_VALUE_LIMIT),
+    }
+}
+
+fn assert_equal(args: Vec<RickrollObject>, _: &mut dyn Write, _: &mut dyn BufRead) -> Result<RickrollObject, Error> {
+    if args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"AssertEqual")], None));
+    }
+    if equals(&args[0], &args[1]) {
+        return Ok(RickrollObject::Undefined);
+    }
+    let left = show_value(&args[0]);
+    let right = show_value(&args[1]);
+    return Err(match describe_difference(&args[0], &args[1], ASSERT_VALUE_LIMIT) {
+        Some(diff) => Error::from_message(
+            ErrorType::AssertionError,
+            "not-equal-difference",
+            &[("left", &left), ("right", &right), ("difference", &diff)],
+            None,
+        ),
+        None if args[0].type_name() != args[1].type_name() => Error::from_message(
+            ErrorType::AssertionError,
+            "not-equal-types",
+            &[("left", &left), ("right", &right), ("left_type", &args[0].type_name()), ("right_type", &args[1].type_name())],
+            None,
+        ),
+        None => Error::from_message(ErrorType::AssertionError, "not-equal", &[("left", &left), ("right", &right)], None),
+    });
+}
+
+fn push(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Push")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        Rc::make_mut(x).push(args[0].clone());
+        return Ok(RickrollObject::Undefined);
+    }
+    return Err(arg_type_err("Push", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+fn pop(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Pop")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        return match Rc::make_mut(x).pop() {
+            Some(val) => Ok(val),
+            None => Err(Error::from_message(ErrorType::RuntimeError, "pop-empty", &[], None)),
+        };
+    }
+    return Err(arg_type_err("Pop", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+fn try_pop(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"TryPop")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        return Ok(Rc::make_mut(x).pop().unwrap_or(RickrollObject::Undefined));
+    }
+    return Err(arg_type_err("TryPop", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+// removing from the front of an array takes O(n) time
+fn shift(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Shift")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        if x.is_empty() {
+            return Err(Error::from_message(ErrorType::RuntimeError, "shift-empty", &[], None));
+        }
+        return Ok(Rc::make_mut(x).remove(0));
+    }
+    return Err(arg_type_err("Shift", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+fn try_shift(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if !args.is_empty() {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"TryShift")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        if x.is_empty() {
+            return Ok(RickrollObject::Undefined);
+        }
+        return Ok(Rc::make_mut(x).remove(0));
+    }
+    return Err(arg_type_err("TryShift", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+// inserting at the front of an array takes O(n) time
+fn unshift(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if args.len() != 1 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Unshift")], None));
+    }
+    if let RickrollObject::Array(x) = target {
+        Rc::make_mut(x).insert(0, args[0].clone());
+        return Ok(RickrollObject::Undefined);
+    }
+    return Err(arg_type_err("Unshift", std::slice::from_ref(target), &["ARRAY"]));
+}
+
+// adds to the element at an index, 1 unless an amount is given, and returns the new value
+// an UNDEFINED element counts as 0, so counts can start from an array of UNDEFINED
+fn increment(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+    if args.len() != 1 && args.len() != 2 {
+        return Err(Error::from_message(ErrorType::RuntimeError, "wrong-argument-count", &[("func", &"Increment")], None));
+    }
+    let amount = args.get(1).cloned().unwrap_or(RickrollObject::Int(1));
+    if let (RickrollObject::Array(x), RickrollObject::Int(ind)) = (&mut *target, &args[0]) {
+        let len = x.len();
+        let elem = match usize::try_from(*ind).ok().filter(|ind| *ind < len) {
+            Some(ind) => &mut Rc::make_mut(x)[ind],
+            None => {
+                return Err(Error::from_message(
+                    ErrorType::IndexOutOfBoundsError,
+                    "index-out-of-bounds",
+                    &[("index", &ind), ("length", &len)],
+                    None,
+                ))
+            }
+        };
+        let res = match (&*elem, &amount) {
+            (RickrollObject::Int(x), RickrollObject::Int(y)) => RickrollObject::Int(x.wrapping_add(*y)),
+            (RickrollObject::Float(x), RickrollObject::Float(y)) => RickrollObject::Float(x + y),
+            (RickrollObject::Undefined, RickrollObject::Int(_) | RickrollObject::Float(_)) => amount.clone(),
+            (current, _) => {
+                return Err(Error::from_message(
+                    ErrorType::TypeError,
+                    "increment-type-mismatch",
+                    &[("amount", &describe_value(&amount)), ("current", &describe_value(current))],
+                    None,
+                ))
+            }
+        };
+        *elem = res.clone();
+        return Ok(res);
+    }
+    let mut passed = vec![target.clone()];
+    passed.extend(args);
+    return Err(arg_type_err("Increment", &passed, &["ARRAY", "INT"]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{run_and_expect, run_source};
+    use RickrollObject::*;
+
+    use std::io::Cursor;
+
+    fn array(elems: Vec<RickrollObject>) -> RickrollObject {
+        return Array(Rc::new(elems));
+    }
+
+    fn record(name: char, key: RickrollObject) -> RickrollObject {
+        return array(vec![Char(name), key]);
+    }
+
+    fn call(func: &str, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+        let mut output: Vec<u8> = Vec::new();
+        return BUILTIN_FUNCTIONS[func](args, &mut output, &mut Cursor::new(Vec::new()));
+    }
+
+    #[test]
+    fn sort_by_element() {
+        let records = array(vec![record('c', Int(2)), record('a', Int(1)), record('d', Int(2)), record('b', Int(0))]);
+        let sorted = call("ArraySortBy", vec![records, Int(1)]).unwrap();
+        // equal keys keep their order
+        assert_eq!(sorted.to_string(), "[[b, 0], [a, 1], [c, 2], [d, 2]]");
+        let records = array(vec![record('c', Int(2)), record('a', Int(1))]);
+        assert_eq!(call("ArraySortBy", vec![records, Int(0)]).unwrap().to_string(), "[[a, 1], [c, 2]]");
+        assert_eq!(call("ArraySortBy", vec![array(Vec::new()), Int(3)]).unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn sort_by_element_errors() {
+        let err = call("ArraySortBy", vec![array(vec![record('a', Int(1)), Int(2)]), Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.message_id(), Some("sort-key-not-array"));
+
+        let err = call("ArraySortBy", vec![array(vec![record('a', Int(1))]), Int(2)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IndexOutOfBoundsError);
+        let err = call("ArraySortBy", vec![array(vec![record('a', Int(1))]), Int(-1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IndexOutOfBoundsError);
+
+        let records = array(vec![record('a', Int(1)), record('b', Bool(true))]);
+        let err = call("ArraySortBy", vec![records, Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.message_id(), Some("sort-incomparable"));
+
+        let err = call("ArraySortBy", vec![array(Vec::new())]).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+        let err = call("ArraySortBy", vec![Int(1), Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+    }
+
+    #[test]
+    fn reverse() {
+        let reversed = call("ArrayReverse", vec![array(vec![Int(1), Float(2.5), array(vec![Int(3)])])]).unwrap();
+        assert_eq!(reversed.to_string(), "[[3], 2.5, 1]");
+        assert_eq!(call("ArrayReverse", vec![array(Vec::new())]).unwrap().to_string(), "[]");
+        // multi-byte characters are kept whole
+        let reversed = call("ArrayReverse", vec![to_char_array("añ→😀")]).unwrap();
+        assert_eq!(from_char_array(&reversed).unwrap(), "😀→ña");
+    }
+
+    #[test]
+    fn reverse_errors() {
+        let err = call("ArrayReverse", vec![Int(12)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("ArrayReverse", Vec::new()).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    // adds up the elements of the range made by a call of func, going over them with for-each
+    fn sum_range(func: &str, step: bool) -> String {
+        let call = if step { "a, b, c" } else { "a, b" };
+        return format!(
+            "\
+[Chorus]
+Never gonna let a down
+Never gonna give a 1
+Never gonna let b down
+Never gonna give b 10
+Never gonna let c down
+Never gonna give c 3
+Never gonna let sum down
+Never gonna give sum 0
+Never gonna let range down
+(Ooh give you range) Never gonna run {} and desert {}
+We've known each x, i in range for so long
+    Never gonna give sum sum + x
+We know the game and we're gonna play it
+Never gonna say sum
+",
+            func, call
+        );
+    }
+
+    #[test]
+    fn iterate_exclusive_range() {
+        run_and_expect(&sum_range("ArrayRange", false), "", "45\n");
+    }
+
+    #[test]
+    fn iterate_inclusive_range() {
+        run_and_expect(&sum_range("ArrayRangeInclusive", false), "", "55\n");
+    }
+
+    #[test]
+    fn iterate_stepped_range() {
+        // 1 + 4 + 7, and 10 is only reached by the inclusive range
+        run_and_expect(&sum_range("ArrayRange", true), "", "12\n");
+        run_and_expect(&sum_range("ArrayRangeInclusive", true), "", "22\n");
+    }
+
+    #[test]
+    fn range_bounds() {
+        assert_eq!(call("ArrayRange", vec![Int(5), Int(2)]).unwrap().to_string(), "[]");
+        assert_eq!(call("ArrayRangeInclusive", vec![Int(5), Int(2)]).unwrap().to_string(), "[]");
+        assert_eq!(call("ArrayRange", vec![Int(2), Int(2)]).unwrap().to_string(), "[]");
+        assert_eq!(call("ArrayRangeInclusive", vec![Int(2), Int(2)]).unwrap().to_string(), "[2]");
+        assert_eq!(call("ArrayRange", vec![Int(-2), Int(1)]).unwrap().to_string(), "[-2, -1, 0]");
+    }
+
+    #[test]
+    fn range_errors() {
+        let err = call("ArrayRange", vec![Int(1), Int(5), Int(0)]).unwrap_err();
+        assert_eq!(err.message_id(), Some("nonpositive-step"));
+        let err = call("ArrayRangeInclusive", vec![Int(1), Int(5), Int(-1)]).unwrap_err();
+        assert_eq!(err.message_id(), Some("nonpositive-step"));
+        let err = call("ArrayRange", vec![Int(1), Float(5.0)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("ArrayRange", vec![Int(1)]).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    fn ints(values: &[i32]) -> RickrollObject {
+        return array(values.iter().map(|x| Int(*x)).collect());
+    }
+
+    #[test]
+    fn slice() {
+        let arr = ints(&[0, 1, 2, 3, 4]);
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(1), Int(3)]).unwrap().to_string(), "[1, 2]");
+        // bounds past either end are clamped
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(3), Int(10)]).unwrap().to_string(), "[3, 4]");
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(3), Int(1)]).unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn open_ended_slice() {
+        let arr = ints(&[0, 1, 2, 3, 4]);
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(2), Undefined]).unwrap().to_string(), "[2, 3, 4]");
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Undefined, Int(3)]).unwrap().to_string(), "[0, 1, 2]");
+        assert_eq!(call("ArraySlice", vec![arr, Undefined, Undefined]).unwrap().to_string(), "[0, 1, 2, 3, 4]");
+    }
+
+    #[test]
+    fn negative_bounds_slice() {
+        let arr = ints(&[0, 1, 2, 3, 4]);
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(-3), Int(-1)]).unwrap().to_string(), "[2, 3]");
+        assert_eq!(call("ArraySlice", vec![arr.clone(), Int(-2), Undefined]).unwrap().to_string(), "[3, 4]");
+        assert_eq!(call("ArraySlice", vec![arr, Int(-10), Int(2)]).unwrap().to_string(), "[0, 1]");
+    }
+
+    #[test]
+    fn slice_errors() {
+        let err = call("ArraySlice", vec![ints(&[1]), Float(0.0), Undefined]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("ArraySlice", vec![ints(&[1]), Int(0)]).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    #[test]
+    fn get_env() {
+        // cargo sets the package name for the tests it runs
+        let value = call("GetEnv", vec![to_char_array("CARGO_PKG_NAME")]).unwrap();
+        assert_eq!(from_char_array(&value).unwrap(), "rickroll");
+        let value = call("GetEnv", vec![to_char_array("RICKROLL_SURELY_NOT_SET")]).unwrap();
+        assert_eq!(value.to_string(), "UNDEFINED");
+        let err = call("GetEnv", vec![Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(call("Length", vec![ints(&[4, 5, 6])]).unwrap().to_string(), "3");
+        assert_eq!(call("Length", vec![array(Vec::new())]).unwrap().to_string(), "0");
+        // characters, not bytes
+        assert_eq!(call("Length", vec![to_char_array("añ😀")]).unwrap().to_string(), "3");
+        assert_eq!(call("ArrayLength", vec![to_char_array("Rick")]).unwrap().to_string(), "4");
+    }
+
+    #[test]
+    fn length_errors() {
+        for value in [Int(5), Float(1.5), Bool(true), Char('a'), Undefined] {
+            let err = call("Length", vec![value.clone()]).unwrap_err();
+            assert_eq!(err.kind(), ErrorType::TypeError, "{}", value);
+        }
+        let err = call("Length", vec![Int(5)]).unwrap_err();
+        assert_eq!(err.to_string(), "Type Error: Length expects ARRAY as argument 1, got INT 5");
+        let err = call("Length", Vec::new()).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    fn strings(values: &[&str]) -> RickrollObject {
+        return array(values.iter().map(|x| to_char_array(x)).collect());
+    }
+
+    #[test]
+    fn sort_numbers() {
+        assert_eq!(call("ArraySort", vec![ints(&[3, -1, 2, 0])]).unwrap().to_string(), "[-1, 0, 2, 3]");
+        let floats = array(vec![Float(2.5), Float(-0.5), Float(1.0)]);
+        assert_eq!(call("ArraySort", vec![floats]).unwrap().to_string(), "[-0.5, 1, 2.5]");
+        assert_eq!(call("ArraySort", vec![array(Vec::new())]).unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn sort_strings() {
+        let sorted = call("ArraySort", vec![strings(&["pear", "apple", "app", "Zebra"])]).unwrap();
+        let sorted: Vec<String> = match sorted {
+            Array(x) => x.iter().map(|s| from_char_array(s).unwrap()).collect(),
+            _ => panic!("ArraySort didn't return an array"),
+        };
+        // by character, so upper case letters come first and prefixes come before longer strings
+        assert_eq!(sorted, ["Zebra", "app", "apple", "pear"]);
+    }
+
+    #[test]
+    fn unique() {
+        assert_eq!(call("ArrayUnique", vec![ints(&[3, 1, 3, 2, 1])]).unwrap().to_string(), "[3, 1, 2]");
+        // values of different types are never duplicates, except numbers with the same value
+        let mixed = array(vec![Int(1), Char('1'), Int(1), Bool(true), Char('1'), Float(1.0), Float(1.5)]);
+        let unique = call("ArrayUnique", vec![mixed]).unwrap();
+        assert_eq!(format!("{:?}", unique), format!("{:?}", array(vec![Int(1), Char('1'), Bool(true), Float(1.5)])));
+        let unique = call("ArrayUnique", vec![strings(&["a", "b", "a"])]).unwrap();
+        assert_eq!(unique.to_string(), "[[a], [b]]");
+    }
+
+    fn between(value: RickrollObject, low: RickrollObject, high: RickrollObject) -> String {
+        return call("Between", vec![value, low, high]).unwrap().to_string();
+    }
+
+    #[test]
+    fn between_inside() {
+        assert_eq!(between(Int(5), Int(1), Int(10)), "TRUE");
+        assert_eq!(between(Float(2.5), Int(2), Int(3)), "TRUE");
+        assert_eq!(between(Char('m'), Char('a'), Char('z')), "TRUE");
+        assert_eq!(between(to_char_array("bob"), to_char_array("alice"), to_char_array("carol")), "TRUE");
+    }
+
+    #[test]
+    fn between_on_bounds() {
+        assert_eq!(between(Int(1), Int(1), Int(10)), "TRUE");
+        assert_eq!(between(Int(10), Int(1), Int(10)), "TRUE");
+        assert_eq!(between(Float(1.0), Int(1), Int(1)), "TRUE");
+    }
+
+    #[test]
+    fn between_outside() {
+        assert_eq!(between(Int(0), Int(1), Int(10)), "FALSE");
+        assert_eq!(between(Int(11), Int(1), Int(10)), "FALSE");
+        // a range with its bounds the wrong way around is empty
+        assert_eq!(between(Int(5), Int(10), Int(1)), "FALSE");
+        assert_eq!(between(Float(f32::NAN), Int(1), Int(10)), "FALSE");
+    }
+
+    #[test]
+    fn between_errors() {
+        let err = call("Between", vec![Int(5), Char('a'), Int(10)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.root().message_id(), Some("between-incomparable"));
+        let err = call("Between", vec![Int(5), Int(1)]).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("wrong-argument-count"));
+    }
+
+    #[test]
+    fn between_in_program() {
+        let src = "\
+[Chorus]
+Never gonna let x down
+Never gonna let low down
+Never gonna give low 1
+Never gonna let high down
+Never gonna give high 10
+Never gonna let inside down
+(Ooh give you x) Never gonna run ReadToken and desert you
+(Ooh give you inside) Never gonna run Between and desert x, low, high
+Inside we both know inside
+    Never gonna say x
+Your heart's been aching but you're too shy to say it
+";
+        run_and_expect(src, "10", "10\n");
+        run_and_expect(src, "11", "");
+    }
+
+    #[test]
+    fn repeat_string() {
+        let repeated = call("ArrayRepeat", vec![to_char_array("ab"), Int(3)]).unwrap();
+        assert_eq!(from_char_array(&repeated).unwrap(), "ababab");
+        run_and_expect(
+            "[Chorus]\nNever gonna let a down\nNever gonna give a \"-=\"\nNever gonna let n down\nNever gonna give n 4\n(Ooh give you a) Never gonna run ArrayRepeat and desert a, n\nNever gonna say a\n",
+            "",
+            "[-, =, -, =, -, =, -, =]\n",
+        );
+    }
+
+    #[test]
+    fn repeat_array() {
+        let repeated = call("ArrayRepeat", vec![array(vec![Int(1), to_char_array("x")]), Int(2)]).unwrap();
+        assert_eq!(repeated.to_string(), "[1, [x], 1, [x]]");
+        assert_eq!(call("ArrayRepeat", vec![ints(&[7]), Int(1)]).unwrap().to_string(), "[7]");
+    }
+
+    #[test]
+    fn repeat_zero_times() {
+        assert_eq!(call("ArrayRepeat", vec![ints(&[1, 2]), Int(0)]).unwrap().to_string(), "[]");
+        assert_eq!(call("ArrayRepeat", vec![array(Vec::new()), Int(5)]).unwrap().to_string(), "[]");
+    }
+
+    #[test]
+    fn repeat_errors() {
+        let err = call("ArrayRepeat", vec![ints(&[1, 2]), Int(-1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ValueError);
+        assert_eq!(err.root().message_id(), Some("repeat-negative"));
+        assert!(err.to_string().ends_with("ArrayRepeat count must not be negative, got -1"), "{}", err);
+        let err = call("ArrayRepeat", vec![ints(&[1, 2]), Int(i32::MAX)]).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("repeat-too-long"));
+        let err = call("ArrayRepeat", vec![Int(1), Int(2)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+    }
+
+    #[test]
+    fn sort_mixed_types() {
+        let err = call("ArraySort", vec![array(vec![Int(1), to_char_array("a")])]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.to_string(), "Type Error: ArraySort can't compare ARRAY \"a\" and INT 1");
+    }
+
+    #[test]
+    fn min_max_of_array() {
+        let values = ints(&[4, -2, 9, 0]);
+        assert_eq!(call("Min", vec![values.clone()]).unwrap().to_string(), "-2");
+        assert_eq!(call("Max", vec![values]).unwrap().to_string(), "9");
+        // the first of equal values is kept, so an INT and a FLOAT with the same value aren't swapped
+        let values = array(vec![Float(1.5), Int(3), Float(3.0)]);
+        assert_eq!(format!("{:?}", call("Max", vec![values]).unwrap()), "Int(3)");
+    }
+
+    #[test]
+    fn min_max_of_values() {
+        assert_eq!(call("Min", vec![Int(3), Int(7)]).unwrap().to_string(), "3");
+        assert_eq!(call("Max", vec![Int(3), Int(7)]).unwrap().to_string(), "7");
+        assert_eq!(call("Max", vec![Char('a'), Char('z'), Char('m')]).unwrap().to_string(), "z");
+    }
+
+    #[test]
+    fn min_max_errors() {
+        let err = call("Min", vec![array(Vec::new())]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::ValueError);
+        assert_eq!(err.message_id(), Some("empty-array"));
+        let err = call("Max", vec![array(vec![Int(1), Bool(true)])]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        assert_eq!(err.message_id(), Some("incomparable"));
+        let err = call("Max", vec![Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("Min", Vec::new()).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    // one value of every type, in the order of TYPE_NAMES
+    fn every_type() -> Vec<RickrollObject> {
+        return vec![Int(1), Float(1.5), Bool(false), ints(&[1]), Char('a'), Undefined];
+    }
+
+    #[test]
+    fn type_of() {
+        for (value, name) in every_type().into_iter().zip(TYPE_NAMES) {
+            let res = call("TypeOf", vec![value.clone()]).unwrap();
+            assert_eq!(from_char_array(&res).unwrap(), name, "{}", value);
+        }
+        // strings and nested arrays are arrays
+        let nested = array(vec![ints(&[1, 2]), to_char_array("hi")]);
+        assert_eq!(from_char_array(&call("TypeOf", vec![nested]).unwrap()).unwrap(), "ARRAY");
+        assert_eq!(from_char_array(&call("TypeOf", vec![to_char_array("hi")]).unwrap()).unwrap(), "ARRAY");
+        assert_eq!(from_char_array(&call("TypeOf", vec![array(Vec::new())]).unwrap()).unwrap(), "ARRAY");
+    }
+
+    #[test]
+    fn type_predicates() {
+        let predicates = ["IsInt", "IsFloat", "IsBool", "IsArray", "IsChar", "IsUndefined"];
+        for (ind, value) in every_type().into_iter().enumerate() {
+            for (pred_ind, predicate) in predicates.iter().enumerate() {
+                let res = call(predicate, vec![value.clone()]).unwrap();
+                assert_eq!(res.to_string(), if ind == pred_ind { "TRUE" } else { "FALSE" }, "{} {}", predicate, value);
+            }
+        }
+        let err = call("IsInt", Vec::new()).unwrap_err();
+        assert_eq!(err.message_id(), Some("wrong-argument-count"));
+    }
+
+    #[test]
+    fn type_of_matches_type_errors() {
+        for value in every_type().into_iter().skip(1) {
+            let name = from_char_array(&call("TypeOf", vec![value.clone()]).unwrap()).unwrap();
+            let err = call("ArrayRange", vec![value.clone(), Int(1)]).unwrap_err();
+            let message = err.to_string();
+            assert!(message.contains(&format!(", got {}", name)), "{}", message);
+        }
+    }
+
+    fn assert_message(a: RickrollObject, b: RickrollObject) -> String {
+        let err = call("AssertEqual", vec![a, b]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::AssertionError);
+        return err.to_string();
+    }
+
+    #[test]
+    fn assert_equal_passes() {
+        assert_eq!(call("AssertEqual", vec![ints(&[1, 2]), ints(&[1, 2])]).unwrap().to_string(), "UNDEFINED");
+        assert!(call("AssertEqual", vec![to_char_array("hi"), to_char_array("hi")]).is_ok());
+    }
+
+    #[test]
+    fn assert_equal_long_strings() {
+        let a = format!("{}x{}", "a".repeat(50), "b".repeat(50));
+        let b = format!("{}y{}", "a".repeat(50), "b".repeat(50));
+        // both values are cut off, and the difference is shown with the characters around it
+        assert_eq!(
+            assert_message(to_char_array(&a), to_char_array(&b)),
+            "Assertion Failed: \"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa...\" is not equal to \"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa...\" \
+             (strings differ at index 50: \"...aaaaaaaaaaxbbbbbbbbb...\" vs \"...aaaaaaaaaaybbbbbbbbb...\")"
+        );
+    }
+
+    #[test]
+    fn assert_equal_arrays() {
+        assert_eq!(
+            assert_message(ints(&[1, 2, 3]), ints(&[1, 2])),
+            "Assertion Failed: [1, 2, 3] is not equal to [1, 2] (lengths differ (3 vs 2 elements))"
+        );
+        assert_eq!(
+            assert_message(ints(&[1, 2, 3]), ints(&[1, 5, 3])),
+            "Assertion Failed: [1, 2, 3] is not equal to [1, 5, 3] (element 1 differs: 2 vs 5)"
+        );
+    }
+
+    // calls an input function the given number of times on the same input
+    fn read(func: &str, input: &str, times: usize) -> Vec<String> {
+        let mut reader = Cursor::new(input.as_bytes());
+        let mut res = Vec::new();
+        for _ in 0..times {
+            let value = BUILTIN_FUNCTIONS[func](Vec::new(), &mut Vec::new(), &mut reader).unwrap();
+            res.push(format!("{:?}", value));
+        }
+        return res;
+    }
+
+    #[test]
+    fn read_line_keeps_spaces() {
+        let lines = read("ReadLine", "  never gonna  give\r\nyou up\n", 3);
+        let expected: Vec<String> =
+            ["  never gonna  give", "you up", ""].iter().map(|line| format!("{:?}", to_char_array(line))).collect();
+        // the line ending is dropped, and the end of the input gives an empty string
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn read_token_stops_at_whitespace() {
+        let tokens = read("ReadToken", "  12 -3\t4.5\n\nup  ", 5);
+        let up = format!("{:?}", to_char_array("up"));
+        assert_eq!(tokens, ["Int(12)", "Int(-3)", "Float(4.5)", up.as_str(), "Undefined"]);
+    }
+
+    #[test]
+    fn read_key_sequence() {
+        let keys = read("ReadKey", "a\nñ😀", 5);
+        assert_eq!(keys, ["Char('a')", "Char('\\n')", "Char('ñ')", "Char('😀')", "Undefined"]);
+    }
+
+    // tokens read from the input are numbers exactly when they would be number literals
+    #[test]
+    fn read_token_matches_literals() {
+        let tokens = ["42", "3.25", "007", "0x1F", "1e3", "1.2.3", ".5", "12abc"];
+        let read = read("ReadToken", &tokens.join(" "), tokens.len());
+        for (token, value) in tokens.iter().zip(read) {
+            match run_source(&format!("[Chorus]\nNever gonna say {}\n", token), "") {
+                Ok(printed) => {
+                    let literal = parse_number(token).unwrap();
+                    assert_eq!(value, format!("{:?}", literal), "{}", token);
+                    assert_eq!(printed, format!("{}\n", literal), "{}", token);
+                }
+                // hex and scientific notation aren't literals, so they are read as strings
+                Err(_) => {
+                    assert!(parse_number(token).is_err(), "{}", token);
+                    assert_eq!(value, format!("{:?}", to_char_array(token)), "{}", token);
+                }
+            }
+        }
+        assert!(parse_number("0x1F").is_err());
+        assert!(parse_number("1e3").is_err());
+    }
+
+    fn csv(text: &str) -> Result<RickrollObject, Error> {
+        return call("ParseCsv", vec![to_char_array(text)]);
+    }
+
+    fn csv_rows(rows: Vec<Vec<RickrollObject>>) -> String {
+        return format!("{:?}", array(rows.into_iter().map(array).collect()));
+    }
+
+    #[test]
+    fn parse_csv_fields() {
+        let parsed = csv("name,age\n\"Astley, Rick\",61\nplain,1.5\n").unwrap();
+        assert_eq!(
+            format!("{:?}", parsed),
+            csv_rows(vec![
+                vec![to_char_array("name"), to_char_array("age")],
+                vec![to_char_array("Astley, Rick"), Int(61)],
+                vec![to_char_array("plain"), Float(1.5)],
+            ])
+        );
+        // quoted numbers stay strings, and quotes can hold quotes and newlines
+        let parsed = csv("\"61\",\"say \"\"hi\"\"\",\"a\nb\"").unwrap();
+        assert_eq!(
+            format!("{:?}", parsed),
+            csv_rows(vec![vec![to_char_array("61"), to_char_array("say \"hi\""), to_char_array("a\nb")]])
+        );
+        // empty fields, and another separator
+        let parsed = call("ParseCsv", vec![to_char_array("a;;\"\"\n1;2;3"), Char(';')]).unwrap();
+        assert_eq!(
+            format!("{:?}", parsed),
+            csv_rows(vec![
+                vec![to_char_array("a"), to_char_array(""), to_char_array("")],
+                vec![Int(1), Int(2), Int(3)],
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_csv_line_endings() {
+        let expected = csv_rows(vec![vec![Int(1), Int(2)], vec![Int(3), Int(4)]]);
+        assert_eq!(format!("{:?}", csv("1,2\n3,4").unwrap()), expected);
+        assert_eq!(format!("{:?}", csv("1,2\r\n3,4\r\n").unwrap()), expected);
+        // a CRLF inside quotes is kept
+        let parsed = csv("\"a\r\nb\",1\r\n").unwrap();
+        assert_eq!(format!("{:?}", parsed), csv_rows(vec![vec![to_char_array("a\r\nb"), Int(1)]]));
+        assert_eq!(format!("{:?}", csv("").unwrap()), csv_rows(Vec::new()));
+    }
+
+    #[test]
+    fn parse_csv_errors() {
+        let errors = [
+            ("a,b\n1,2,3\n", "csv-row-length", "Row 2 has 3 fields instead of the 2 of row 1"),
+            ("a,b\n1\n", "csv-row-length", "Row 2 has 1 fields instead of the 2 of row 1"),
+            ("a\n\"b\"c\n", "csv-after-quoted-field", "Row 2 has c after the end of a quoted field"),
+            ("a\"b\n", "csv-quote-in-field", "Row 1 has a quote in the middle of a field"),
+            ("a\n\"b\n", "csv-unclosed-quote", "Row 2 has a quoted field that is never closed"),
+        ];
+        for (text, id, message) in errors {
+            let err = csv(text).unwrap_err();
+            assert_eq!(err.kind(), ErrorType::ValueError);
+            assert_eq!(err.root().message_id(), Some(id));
+            assert_eq!(err.to_string(), format!("Value Error: {}", message));
+        }
+        let err = call("ParseCsv", vec![to_char_array("a"), Char('"')]).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("csv-bad-separator"));
+        let err = call("ParseCsv", vec![Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+    }
+
+    #[test]
+    fn parse_csv_in_program() {
+        let src = "\
+[Chorus]
+Never gonna let line down
+Never gonna let rows down
+(Ooh give you line) Never gonna run ReadLine and desert you
+(Ooh give you rows) Never gonna run ParseCsv and desert line
+Never gonna say rows:0:0
+Never gonna say rows:0:1 + 1
+";
+        run_and_expect(src, "\"Astley, Rick\",61\n", "[A, s, t, l, e, y, ,,  , R, i, c, k]\n62\n");
+        let err = run_source(src, "\"Astley, Rick\n").unwrap_err();
+        assert_eq!(err.root().message_id(), Some("csv-unclosed-quote"));
+    }
+
+    fn increment(target: &mut RickrollObject, args: Vec<RickrollObject>) -> Result<RickrollObject, Error> {
+        return IN_PLACE_FUNCTIONS["Increment"](target, args);
+    }
+
+    #[test]
+    fn increment_existing_element() {
+        let mut counts = array(vec![Int(1), Int(5)]);
+        assert_eq!(increment(&mut counts, vec![Int(1)]).unwrap().to_string(), "6");
+        assert_eq!(increment(&mut counts, vec![Int(0), Int(-3)]).unwrap().to_string(), "-2");
+        assert_eq!(counts.to_string(), "[-2, 6]");
+        let mut totals = array(vec![Float(0.5)]);
+        assert_eq!(increment(&mut totals, vec![Int(0), Float(1.25)]).unwrap().to_string(), "1.75");
+    }
+
+    #[test]
+    fn increment_undefined_element() {
+        // an UNDEFINED element starts counting from 0
+        let mut counts = array(vec![Undefined, Undefined]);
+        assert_eq!(increment(&mut counts, vec![Int(1)]).unwrap().to_string(), "1");
+        assert_eq!(increment(&mut counts, vec![Int(0), Float(2.5)]).unwrap().to_string(), "2.5");
+        assert_eq!(format!("{:?}", counts), format!("{:?}", array(vec![Float(2.5), Int(1)])));
+    }
+
+    #[test]
+    fn increment_errors() {
+        let mut counts = array(vec![Int(1), Bool(true), Undefined]);
+        let errors = [
+            (vec![Int(0), Float(1.0)], "Increment can't add FLOAT 1 to INT 1"),
+            (vec![Int(1)], "Increment can't add INT 1 to BOOL TRUE"),
+            (vec![Int(2), Char('a')], "Increment can't add CHAR 'a' to UNDEFINED"),
+        ];
+        for (args, message) in errors {
+            let err = increment(&mut counts, args).unwrap_err();
+            assert_eq!(err.kind(), ErrorType::TypeError);
+            assert_eq!(err.root().message_id(), Some("increment-type-mismatch"));
+            assert_eq!(err.to_string(), format!("Type Error: {}", message));
+        }
+        // nothing was changed by the failed calls
+        assert_eq!(counts.to_string(), "[1, TRUE, UNDEFINED]");
+        let err = increment(&mut counts, vec![Int(3)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IndexOutOfBoundsError);
+        let err = increment(&mut counts, vec![Int(-1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::IndexOutOfBoundsError);
+        let err = increment(&mut Int(1), vec![Int(0)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = increment(&mut counts, Vec::new()).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("wrong-argument-count"));
+    }
+
+    #[test]
+    fn increment_in_program() {
+        // counts the digits read until a negative number, starting from an array of UNDEFINED
+        let src = "\
+[Chorus]
+Never gonna let counts down
+Never gonna let copy down
+Never gonna let u down
+Never gonna let digit down
+Never gonna give counts ARRAY
+Never gonna run Push and desert counts, u
+Never gonna run Push and desert counts, u
+Never gonna run Push and desert counts, u
+Never gonna give copy counts
+(Ooh give you digit) Never gonna run ReadToken and desert you
+Inside we both know digit >= 0
+    Never gonna run Increment and desert counts, digit
+    (Ooh give you digit) Never gonna run ReadToken and desert you
+We know the game and we're gonna play it
+Never gonna say counts
+Never gonna say copy
+";
+        run_and_expect(src, "2 0 2 2 -1", "[1, UNDEFINED, 3]\n[UNDEFINED, UNDEFINED, UNDEFINED]\n");
+    }
+
+    fn format_number(args: Vec<RickrollObject>) -> String {
+        return from_char_array(&call("FormatNumber", args).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn format_number_sign() {
+        assert_eq!(format_number(vec![Int(42), Bool(true)]), "+42");
+        assert_eq!(format_number(vec![Float(2.5), Bool(true)]), "+2.5");
+        // zero and negative numbers get no +
+        assert_eq!(format_number(vec![Int(0), Bool(true)]), "0");
+        assert_eq!(format_number(vec![Int(-42), Bool(true)]), "-42");
+        assert_eq!(format_number(vec![Int(42), Bool(false)]), "42");
+        assert_eq!(format_number(vec![Int(42)]), "42");
+    }
+
+    #[test]
+    fn format_number_grouping() {
+        assert_eq!(format_number(vec![Int(1234567), Bool(false), Int(3)]), "1,234,567");
+        assert_eq!(format_number(vec![Int(123456), Bool(false), Int(3)]), "123,456");
+        assert_eq!(format_number(vec![Int(999), Bool(false), Int(3)]), "999");
+        assert_eq!(format_number(vec![Int(i32::MIN), Bool(true), Int(3)]), "-2,147,483,648");
+        assert_eq!(format_number(vec![Int(1234567), Bool(true), Int(4), Char(' ')]), "+123 4567");
+        // only the digits before the point are grouped
+        assert_eq!(format_number(vec![Float(-1234.5), Bool(true), Int(3), Char('.')]), "-1.234.5");
+        assert_eq!(format_number(vec![Int(1234567), Bool(false), Int(0)]), "1234567");
+        assert_eq!(format_number(vec![Float(f32::INFINITY), Bool(true), Int(3)]), "+inf");
+    }
+
+    #[test]
+    fn format_number_errors() {
+        let err = call("FormatNumber", vec![Int(1), Bool(false), Int(-1)]).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("negative-group-size"));
+        let err = call("FormatNumber", vec![Char('1')]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("FormatNumber", vec![Int(1), Int(1)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorType::TypeError);
+        let err = call("FormatNumber", Vec::new()).unwrap_err();
+        assert_eq!(err.root().message_id(), Some("wrong-argument-count"));
+    }
 }
@@ -0,0 +1,60 @@
+use crate::error::*;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::optimizer::Optimizer;
+use crate::parser::Parser;
+
+use std::io::Cursor;
+
+// runs a program the same way the command line does, with the given standard input
+// returns everything the program printed
+pub fn run_source(src: &str, input: &str) -> Result<String, Error> {
+    let tokens = Lexer::new(String::from(src)).parse()?;
+    let mut parsed = Parser::new(tokens).parse()?;
+    Optimizer::new().run(&mut parsed);
+    let mut interpreter = Interpreter::new(parsed);
+    let mut output: Vec<u8> = Vec::new();
+    interpreter.run(&mut output, &mut Cursor::new(input.as_bytes()))?;
+    return Ok(String::from_utf8_lossy(&output).into_owned());
+}
+
+// runs a program and panics unless it succeeds and prints exactly the expected output
+pub fn run_and_expect(src: &str, input: &str, expected: &str) {
+    match run_source(src, input) {
+        Err(err) => panic!("program failed:\n{}", err),
+        Ok(output) if output != expected => {
+            panic!("output differs\nexpected:\n{}\ngot:\n{}", expected, output)
+        }
+        Ok(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO: &str = "\
+[Chorus]
+Never gonna let line down
+(Ooh give you line) Never gonna run ReadLine and desert you
+Never gonna say line
+";
+
+    #[test]
+    fn passing_program() {
+        run_and_expect(ECHO, "hi\n", "[h, i]\n");
+        assert_eq!(run_source("[Chorus]\nNever gonna say 6 * 7\n", "").unwrap(), "42\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "output differs")]
+    fn different_output() {
+        run_and_expect(ECHO, "hi\n", "hi\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "program failed")]
+    fn failing_program() {
+        run_and_expect("[Chorus]\nNever gonna say 1 / 0\n", "", "");
+    }
+}
@@ -1,4 +1,8 @@
+use crate::error::*;
+
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 // collection of data types
 #[derive(Debug, Clone)]
@@ -6,22 +10,48 @@ pub enum RickrollObject {
     Int(i32),
     Float(f32),
     Bool(bool),
-    Array(Vec<RickrollObject>),
+    // copies of an array share its elements until one of them is changed
+    Array(Rc<Vec<RickrollObject>>),
     Char(char),
     Undefined,
 }
 
-impl std::fmt::Display for RickrollObject {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+// words used to print booleans and UNDEFINED
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintWords {
+    pub true_word: String,
+    pub false_word: String,
+    pub undefined_word: String,
+}
+
+impl Default for PrintWords {
+    fn default() -> Self {
+        PrintWords {
+            true_word: String::from("TRUE"),
+            false_word: String::from("FALSE"),
+            undefined_word: String::from("UNDEFINED"),
+        }
+    }
+}
+
+impl RickrollObject {
+    // formats the object, printing booleans and UNDEFINED with the given words
+    pub fn format_with(&self, words: &PrintWords) -> String {
         use RickrollObject::*;
-        let formatted = match self {
+        match self {
             Int(x) => x.to_string(),
-            Float(x) => x.to_string(),
-            Bool(x) => String::from(if *x { "TRUE" } else { "FALSE" }),
+            Float(x) => format_float(*x),
+            Bool(x) => {
+                if *x {
+                    words.true_word.clone()
+                } else {
+                    words.false_word.clone()
+                }
+            }
             Array(x) => {
                 let mut res = String::from("[");
                 for ind in 0..x.len() {
-                    res += &x[ind].to_string()[..];
+                    res += &x[ind].format_with(words)[..];
                     if ind != x.len() - 1 {
                         res += ", "
                     }
@@ -30,14 +60,324 @@ impl std::fmt::Display for RickrollObject {
                 res
             }
             Char(x) => x.to_string(),
-            Undefined => String::from("UNDEFINED"),
+            Undefined => words.undefined_word.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for RickrollObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format_with(&PrintWords::default()))
+    }
+}
+
+// names of all data types
+pub const TYPE_NAMES: [&str; 6] = ["INT", "FLOAT", "BOOL", "ARRAY", "CHAR", "UNDEFINED"];
+
+impl RickrollObject {
+    // gets the name of the object's data type
+    pub fn type_name(&self) -> &'static str {
+        use RickrollObject::*;
+        match self {
+            Int(_) => TYPE_NAMES[0],
+            Float(_) => TYPE_NAMES[1],
+            Bool(_) => TYPE_NAMES[2],
+            Array(_) => TYPE_NAMES[3],
+            Char(_) => TYPE_NAMES[4],
+            Undefined => TYPE_NAMES[5],
+        }
+    }
+
+    // checks if the object is an INT or a FLOAT
+    pub fn is_number(&self) -> bool {
+        matches!(self, RickrollObject::Int(_) | RickrollObject::Float(_))
+    }
+
+    // accessors for reading values from outside the interpreter
+    // each one only matches its own type, so an INT is never read as a FLOAT or the other way around
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            RickrollObject::Int(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            RickrollObject::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            RickrollObject::Bool(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> Option<char> {
+        match self {
+            RickrollObject::Char(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[RickrollObject]> {
+        match self {
+            RickrollObject::Array(x) => Some(&x[..]),
+            _ => None,
+        }
+    }
+
+    // gets the string held by an array of characters
+    pub fn as_string(&self) -> Option<String> {
+        from_char_array(self)
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, RickrollObject::Undefined)
+    }
+}
+
+// orders two objects, which every comparison of values goes through
+// INTs and FLOATs are compared by their numeric values, and other objects only with the same type
+// arrays (and so strings) are ordered element by element and then by length,
+// characters by code point, and FALSE comes before TRUE
+// returns None if the objects can't be compared, or if either is a NaN FLOAT
+pub fn compare(a: &RickrollObject, b: &RickrollObject) -> Option<Ordering> {
+    use RickrollObject::*;
+    match (a, b) {
+        (Int(x), Int(y)) => Some(x.cmp(y)),
+        (Float(x), Float(y)) => x.partial_cmp(y),
+        (Int(x), Float(y)) => (*x as f64).partial_cmp(&(*y as f64)),
+        (Float(x), Int(y)) => (*x as f64).partial_cmp(&(*y as f64)),
+        (Bool(x), Bool(y)) => Some(x.cmp(y)),
+        (Char(x), Char(y)) => Some(x.cmp(y)),
+        (Undefined, Undefined) => Some(Ordering::Equal),
+        (Array(x), Array(y)) => {
+            for (left, right) in x.iter().zip(y.iter()) {
+                match compare(left, right)? {
+                    Ordering::Equal => (),
+                    ord => return Some(ord),
+                }
+            }
+            Some(x.len().cmp(&y.len()))
+        }
+        _ => None,
+    }
+}
+
+// checks if two objects are equal
+// objects that can't be compared are never equal
+pub fn equals(a: &RickrollObject, b: &RickrollObject) -> bool {
+    compare(a, b) == Some(Ordering::Equal)
+}
+
+// characters that are easily mistaken for ASCII ones, often pasted from documents or websites
+// character, its Unicode name, and the ASCII character that was probably meant
+pub const CONFUSABLES: [(char, &str, char); 11] = [
+    ('\u{2018}', "LEFT SINGLE QUOTATION MARK", '\''),
+    ('\u{2019}', "RIGHT SINGLE QUOTATION MARK", '\''),
+    ('\u{201C}', "LEFT DOUBLE QUOTATION MARK", '"'),
+    ('\u{201D}', "RIGHT DOUBLE QUOTATION MARK", '"'),
+    ('\u{2013}', "EN DASH", '-'),
+    ('\u{2014}', "EM DASH", '-'),
+    ('\u{2212}', "MINUS SIGN", '-'),
+    ('\u{00D7}', "MULTIPLICATION SIGN", '*'),
+    ('\u{00F7}', "DIVISION SIGN", '/'),
+    ('\u{00A0}', "NO-BREAK SPACE", ' '),
+    ('\u{3000}', "IDEOGRAPHIC SPACE", ' '),
+];
+
+const DIGIT_NAMES: [&str; 10] = ["ZERO", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE"];
+
+// finds the name of a confusable character and the ASCII character that was probably meant
+// fullwidth digits are confusable too
+pub fn confusable(chr: char) -> Option<(String, char)> {
+    if let Some((_, name, ascii)) = CONFUSABLES.iter().find(|entry| entry.0 == chr) {
+        return Some((String::from(*name), *ascii));
+    }
+    if ('\u{FF10}'..='\u{FF19}').contains(&chr) {
+        let digit = chr as u32 - 0xFF10;
+        return Some((
+            format!("FULLWIDTH DIGIT {}", DIGIT_NAMES[digit as usize]),
+            std::char::from_digit(digit, 10).unwrap(),
+        ));
+    }
+    return None;
+}
+
+// replaces confusable characters with the ASCII characters that were probably meant
+// characters inside string literals are kept, since they may be meant as they are
+pub fn fix_confusables(source: &str) -> String {
+    let mut res = String::with_capacity(source.len());
+    // the quote the current string was opened with
+    let mut opener: Option<char> = None;
+    let mut escaped = false;
+    for chr in source.chars() {
+        let fixed = match confusable(chr) {
+            Some((_, ascii)) => ascii,
+            None => chr,
+        };
+        match opener {
+            Some(_) if escaped => escaped = false,
+            Some(_) if chr == '\\' => escaped = true,
+            Some('"') if chr == '"' => opener = None,
+            // a string opened with a smart quote is closed by one
+            Some(open) if open != '"' && fixed == '"' => {
+                opener = None;
+                res.push('"');
+                continue;
+            }
+            Some(_) => (),
+            None if fixed == '"' => opener = Some(chr),
+            None => (),
+        }
+        res.push(if opener.is_some() && fixed != '"' { chr } else { fixed });
+    }
+    return res;
+}
+
+// number of characters shown on each side of the first difference between strings
+pub const DIFF_CONTEXT: usize = 10;
+
+// renders a value, cutting it off after limit characters
+pub fn summarize(obj: &RickrollObject, limit: usize) -> String {
+    let res = obj.to_string();
+    if res.chars().count() <= limit {
+        return res;
+    }
+    let mut res: String = res.chars().take(limit).collect();
+    res += "...";
+    return res;
+}
+
+// number of characters of a value shown in error messages
+pub const ERROR_VALUE_LIMIT: usize = 40;
+
+// describes a value for an error message with its type and a short rendering of it
+// long values are cut off and say how long they are
+pub fn describe_value(obj: &RickrollObject) -> String {
+    match obj {
+        RickrollObject::Undefined => return String::from(obj.type_name()),
+        RickrollObject::Char(x) => return format!("{} '{}'", obj.type_name(), x),
+        RickrollObject::Array(x) if !x.is_empty() => {
+            // strings are shown in quotes, which are kept when the string is cut off
+            let (shown, unit, quote) = match from_char_array(obj) {
+                Some(string) => (string, "characters", "\""),
+                None => (obj.to_string(), "elements", ""),
+            };
+            if shown.chars().count() <= ERROR_VALUE_LIMIT {
+                return format!("{} {}{}{}", obj.type_name(), quote, shown, quote);
+            }
+            let shown: String = shown.chars().take(ERROR_VALUE_LIMIT).collect();
+            return format!("{} of {} {} {}{}...{}", obj.type_name(), x.len(), unit, quote, shown, quote);
+        }
+        _ => return format!("{} {}", obj.type_name(), obj),
+    }
+}
+
+// shows the characters around ind, marking where the string was cut off
+fn diff_window(chars: &[char], ind: usize) -> String {
+    let start = ind.saturating_sub(DIFF_CONTEXT);
+    let end = (ind + DIFF_CONTEXT).min(chars.len());
+    let mut res = String::from("\"");
+    if start > 0 {
+        res += "...";
+    }
+    res.extend(&chars[start..end]);
+    if end < chars.len() {
+        res += "...";
+    }
+    res += "\"";
+    return res;
+}
+
+// describes where two strings or arrays first differ
+// returns None for other values, or if there is no difference
+pub fn describe_difference(a: &RickrollObject, b: &RickrollObject, limit: usize) -> Option<String> {
+    if let (Some(x), Some(y)) = (from_char_array(a), from_char_array(b)) {
+        let x: Vec<char> = x.chars().collect();
+        let y: Vec<char> = y.chars().collect();
+        return match x.iter().zip(y.iter()).position(|(left, right)| left != right) {
+            Some(ind) => Some(render_message(
+                "strings-differ",
+                &[("index", &ind), ("left", &diff_window(&x, ind)), ("right", &diff_window(&y, ind))],
+            )),
+            None if x.len() != y.len() => Some(render_message(
+                "string-lengths-differ",
+                &[("left", &x.len()), ("right", &y.len()), ("index", &x.len().min(y.len()))],
+            )),
+            None => None,
+        };
+    }
+    if let (RickrollObject::Array(x), RickrollObject::Array(y)) = (a, b) {
+        for (ind, (left, right)) in x.iter().zip(y.iter()).enumerate() {
+            if !equals(left, right) {
+                return Some(render_message(
+                    "elements-differ",
+                    &[("index", &ind), ("left", &summarize(left, limit)), ("right", &summarize(right, limit))],
+                ));
+            }
+        }
+        if x.len() != y.len() {
+            return Some(render_message("array-lengths-differ", &[("left", &x.len()), ("right", &y.len())]));
+        }
+    }
+    return None;
+}
+
+// parses a number the way number literals are written: digits with at most one '.',
+// which makes it a FLOAT
+// negative numbers are written with the '~' operator, so there is no sign
+pub fn parse_number(raw: &str) -> Result<RickrollObject, Error> {
+    if let Some(chr) = raw.chars().find(|chr| !chr.is_ascii_digit() && *chr != '.') {
+        return Err(Error::from_message(
+            ErrorType::IllegalCharError,
+            "unknown-char",
+            &[("char", &chr)],
+            None,
+        ));
+    }
+    if !raw.starts_with(|chr: char| chr.is_ascii_digit()) || raw.matches('.').count() > 1 {
+        // only one '.' can exist in a number, and not at its start
+        return Err(Error::from_message(ErrorType::IllegalCharError, "unknown-char", &[("char", &'.')], None));
+    }
+    if raw.contains('.') {
+        return match raw.parse::<f32>() {
+            Ok(val) => Ok(RickrollObject::Float(val)),
+            Err(_) => Err(Error::from_message(
+                ErrorType::IllegalArgumentError,
+                "bad-float-literal",
+                &[],
+                None,
+            )),
         };
-        write!(f, "{}", formatted)
     }
+    return match raw.parse::<i32>() {
+        Ok(val) => Ok(RickrollObject::Int(val)),
+        Err(_) => Err(Error::from_message(
+            ErrorType::IllegalArgumentError,
+            "bad-int-literal",
+            &[],
+            None,
+        )),
+    };
+}
+
+// formats a float the same way on every platform
+// there are no grouping separators or exponents, "." is the decimal point,
+// whole numbers have no fractional part, and negative zero prints as 0
+pub fn format_float(x: f32) -> String {
+    if x == 0.0 {
+        return String::from("0");
+    }
+    x.to_string()
 }
 
 // operators
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Operator {
     ArrayAccess,
     Add,
@@ -58,6 +398,29 @@ pub enum Operator {
 }
 
 impl Operator {
+    // gets the operator as it is written in source code
+    pub fn symbol(&self) -> &'static str {
+        use Operator::*;
+        match self {
+            ArrayAccess => ":",
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Modulo => "%",
+            UnaryMinus => "~",
+            And => "&&",
+            Or => "||",
+            Not => "!",
+            Greater => ">",
+            Less => "<",
+            GreaterEquals => ">=",
+            LessEquals => "<=",
+            Equals => "==",
+            NotEquals => "!=",
+        }
+    }
+
     // checks if operator is unary
     pub fn is_unary(&self) -> bool {
         use Operator::*;
@@ -74,38 +437,78 @@ pub fn from_constant(constant: &String) -> Option<RickrollObject> {
         "TRUE" => Some(RickrollObject::Bool(true)),
         "FALSE" => Some(RickrollObject::Bool(false)),
         "UNDEFINED" => Some(RickrollObject::Undefined),
-        "ARRAY" => Some(RickrollObject::Array(Vec::new())),
+        "ARRAY" => Some(RickrollObject::Array(Rc::new(Vec::new()))),
         _ => None,
     }
 }
 
+// converts a string into an array of characters
+pub fn to_char_array(string: &str) -> RickrollObject {
+    RickrollObject::Array(Rc::new(string.chars().map(RickrollObject::Char).collect()))
+}
+
+// converts an array of characters back into a string
+pub fn from_char_array(obj: &RickrollObject) -> Option<String> {
+    let mut res = String::new();
+    if let RickrollObject::Array(arr) = obj {
+        for elem in arr.iter() {
+            match elem {
+                RickrollObject::Char(x) => res.push(*x),
+                _ => return None,
+            }
+        }
+        return Some(res);
+    }
+    return None;
+}
+
 // variable cache for a single block
+// variables are kept in the order they were declared
 #[derive(Debug, Clone)]
 pub struct Context {
-    vars: HashMap<String, RickrollObject>,
+    vars: Vec<(String, RickrollObject)>,
+    indices: HashMap<String, usize>,
 }
 
 impl Context {
     pub fn new() -> Context {
         Context {
-            vars: HashMap::new(),
+            vars: Vec::new(),
+            indices: HashMap::new(),
         }
     }
 
     pub fn set_var(&mut self, name: String, value: RickrollObject) {
-        self.vars.insert(name, value);
+        match self.indices.get(&name) {
+            Some(ind) => self.vars[*ind].1 = value,
+            None => {
+                self.indices.insert(name.clone(), self.vars.len());
+                self.vars.push((name, value));
+            }
+        }
     }
 
     pub fn get_var(&self, name: String) -> Option<RickrollObject> {
-        if self.vars.contains_key(&name) {
-            return Some(self.vars.get(&name).unwrap().clone());
+        if self.indices.contains_key(&name) {
+            return Some(self.vars[self.indices[&name]].1.clone());
         } else {
             return None;
         }
     }
 
+    // moves the value out of a variable, leaving UNDEFINED in its place
+    pub fn take_var(&mut self, name: String) -> Option<RickrollObject> {
+        let ind = *self.indices.get(&name)?;
+        return Some(std::mem::replace(&mut self.vars[ind].1, RickrollObject::Undefined));
+    }
+
     pub fn has_var(&self, name: String) -> bool {
-        self.vars.contains_key(&name)
+        self.indices.contains_key(&name)
+    }
+
+    // iterates over variables in declaration order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &RickrollObject)> {
+        self.vars.iter().map(|(name, value)| (name, value))
     }
 }
 
@@ -132,6 +535,11 @@ impl Scope {
         self.contexts.len()
     }
 
+    // removes contexts until only len remain
+    pub fn truncate(&mut self, len: usize) {
+        self.contexts.truncate(len);
+    }
+
     pub fn push(&mut self, context: Context) {
         self.contexts.push(context);
     }
@@ -185,6 +593,17 @@ impl Scope {
         return None;
     }
 
+    // moves the value out of a variable in the scope, leaving UNDEFINED in its place
+    // returns None if variable doesn't exist
+    pub fn take_var(&mut self, name: String) -> Option<RickrollObject> {
+        for context in self.contexts.iter_mut().rev() {
+            if context.has_var(name.clone()) {
+                return context.take_var(name);
+            }
+        }
+        return None;
+    }
+
     pub fn has_var(&self, name: String) -> bool {
         for context in self.contexts.iter() {
             if context.has_var(name.clone()) {
@@ -194,6 +613,28 @@ impl Scope {
         return false;
     }
 
+    // iterates over the variables of every context, starting from the outermost
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &RickrollObject)> {
+        self.contexts.iter().flat_map(|context| context.iter())
+    }
+
+    // iterates over the visible variables along with the depth of their context
+    // contexts go from the outermost (depth 0) to the innermost, each in declaration order
+    // variables shadowed by an inner context are skipped
+    pub fn iter_ordered(&self) -> impl Iterator<Item = (usize, &String, &RickrollObject)> {
+        let contexts = &self.contexts;
+        contexts.iter().enumerate().flat_map(move |(depth, context)| {
+            context
+                .iter()
+                .filter(move |(name, _)| {
+                    !contexts[(depth + 1)..]
+                        .iter()
+                        .any(|inner| inner.has_var((*name).clone()))
+                })
+                .map(move |(name, value)| (depth, name, value))
+        })
+    }
+
     pub fn add_var(&mut self, name: String) {
         self.contexts
             .last_mut()
@@ -201,3 +642,302 @@ impl Scope {
             .set_var(name, RickrollObject::Undefined);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorType;
+    use crate::expr::Expr;
+    use crate::interpreter::Interpreter;
+    use crate::stdlib::BUILTIN_FUNCTIONS;
+    use crate::testing::{run_and_expect, run_source};
+    use RickrollObject::*;
+
+    // enough names that a hash map would be unlikely to keep their order
+    const NAMES: [&str; 8] = ["zeta", "alpha", "mu", "beta", "omega", "chi", "kappa", "delta"];
+
+    #[test]
+    fn context_declaration_order() {
+        let mut context = Context::new();
+        for (ind, name) in NAMES.iter().enumerate() {
+            context.set_var(String::from(*name), Int(ind as i32));
+        }
+        // assigning again keeps the first position
+        context.set_var(String::from("mu"), Bool(true));
+        let names: Vec<&str> = context.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, NAMES);
+        assert_eq!(context.get_var(String::from("mu")).unwrap().to_string(), "TRUE");
+    }
+
+    #[test]
+    fn scope_declaration_order() {
+        let mut scope = Scope::new();
+        for name in &NAMES[..4] {
+            scope.add_var(String::from(*name));
+        }
+        scope.push(Context::new());
+        for name in &NAMES[4..] {
+            scope.add_var(String::from(*name));
+        }
+        let names: Vec<&str> = scope.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, NAMES);
+    }
+
+    // the variables of a function called from the chorus, while it runs an if statement
+    fn nested_scope() -> Scope {
+        let mut scope = Scope::new();
+        scope.add_var(String::from("limit"));
+        scope.add_var(String::from("count"));
+        scope.push(Context::new());
+        scope.add_var(String::from("total"));
+        scope.add_var(String::from("count"));
+        scope.set_var(String::from("count"), Int(2));
+        scope.push(Context::new());
+        scope.add_var(String::from("step"));
+        scope.add_var(String::from("limit"));
+        scope.set_var(String::from("limit"), Int(10));
+        return scope;
+    }
+
+    fn dump(scope: &Scope) -> Vec<String> {
+        return scope.iter_ordered().map(|(depth, name, value)| format!("{} {} {}", depth, name, value)).collect();
+    }
+
+    #[test]
+    fn ordered_nested_scopes() {
+        let scope = nested_scope();
+        // shadowed variables are left out
+        assert_eq!(dump(&scope), ["1 total UNDEFINED", "1 count 2", "2 step UNDEFINED", "2 limit 10"]);
+        assert_eq!(dump(&nested_scope()), dump(&scope));
+    }
+
+    #[test]
+    fn canonical_numbers() {
+        assert_eq!(Float(-0.0).to_string(), "0");
+        assert_eq!(Float(0.0).to_string(), "0");
+        assert_eq!(Int(i32::MAX).to_string(), "2147483647");
+        assert_eq!(Int(i32::MIN).to_string(), "-2147483648");
+        assert_eq!(Float(1e20).to_string(), "100000000000000000000");
+        assert_eq!(Float(-2.5).to_string(), "-2.5");
+        assert_eq!(Float(0.125).to_string(), "0.125");
+        assert_eq!(Float(3.0).to_string(), "3");
+        assert_eq!(Array(Rc::new(vec![Float(-0.0), Float(1.5)])).to_string(), "[0, 1.5]");
+    }
+
+    #[test]
+    fn canonical_numbers_printed() {
+        run_and_expect(
+            "[Chorus]\nNever gonna say 0.0 * (0.0 - 1.0)\nNever gonna say 1000000 * 1000\nNever gonna say 7.0 / 4.0\n",
+            "",
+            "0\n1000000000\n1.75\n",
+        );
+    }
+
+    // a global variable of every type, set by a program
+    const TYPED_GLOBALS: &str = "\
+[Intro]
+Never gonna let int down
+Never gonna let float down
+Never gonna let bool down
+Never gonna let char down
+Never gonna let string down
+Never gonna let array down
+Never gonna let nothing down
+[Chorus]
+Never gonna give int 6 * 7
+Never gonna give float 7.0 / 2.0
+Never gonna give bool int > 40
+Never gonna give char 'x'
+Never gonna give string \"hi\"
+(Ooh give you array) Never gonna run ArrayOf and desert int, float
+";
+
+    fn read_globals(src: &str) -> Context {
+        let tokens = crate::lexer::Lexer::new(String::from(src)).parse().unwrap();
+        let mut interpreter = Interpreter::new(crate::parser::Parser::new(tokens).parse().unwrap());
+        interpreter.run(&mut Vec::new(), &mut std::io::empty()).unwrap();
+        return interpreter.globals().clone();
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let globals = read_globals(TYPED_GLOBALS);
+        let get = |name: &str| globals.get_var(String::from(name)).unwrap();
+        assert_eq!(get("int").as_int(), Some(42));
+        assert_eq!(get("float").as_float(), Some(3.5));
+        assert_eq!(get("bool").as_bool(), Some(true));
+        assert_eq!(get("char").as_char(), Some('x'));
+        assert_eq!(get("string").as_string().as_deref(), Some("hi"));
+        let array = get("array");
+        let elements = array.as_array().unwrap();
+        assert_eq!((elements[0].as_int(), elements[1].as_float()), (Some(42), Some(3.5)));
+        assert!(get("nothing").is_undefined());
+    }
+
+    #[test]
+    fn typed_accessors_keep_types_apart() {
+        let globals = read_globals(TYPED_GLOBALS);
+        let get = |name: &str| globals.get_var(String::from(name)).unwrap();
+        // an INT is never read as a FLOAT, or the other way around
+        assert_eq!(get("int").as_float(), None);
+        assert_eq!(get("float").as_int(), None);
+        assert_eq!(get("int").as_bool(), None);
+        assert_eq!(get("char").as_string(), None);
+        assert!(get("string").as_array().is_some());
+        assert_eq!(get("array").as_string(), None);
+        assert!(!get("int").is_undefined());
+        assert_eq!(get("nothing").as_int(), None);
+    }
+
+    #[test]
+    fn value_descriptions() {
+        assert_eq!(describe_value(&Int(-3)), "INT -3");
+        assert_eq!(describe_value(&Float(2.5)), "FLOAT 2.5");
+        assert_eq!(describe_value(&Bool(true)), "BOOL TRUE");
+        assert_eq!(describe_value(&Char('x')), "CHAR 'x'");
+        assert_eq!(describe_value(&Undefined), "UNDEFINED");
+        assert_eq!(describe_value(&Array(Rc::new(Vec::new()))), "ARRAY []");
+        assert_eq!(describe_value(&to_char_array("hi there")), "ARRAY \"hi there\"");
+        assert_eq!(describe_value(&Array(Rc::new(vec![Int(1), Bool(false)]))), "ARRAY [1, FALSE]");
+    }
+
+    #[test]
+    fn long_value_descriptions() {
+        // exactly at the limit, nothing is cut off
+        let fits = "a".repeat(ERROR_VALUE_LIMIT);
+        assert_eq!(describe_value(&to_char_array(&fits)), format!("ARRAY \"{}\"", fits));
+        let long = format!("{}b", fits);
+        assert_eq!(describe_value(&to_char_array(&long)), format!("ARRAY of 41 characters \"{}...\"", fits));
+        // operators and built-in functions show the same summary
+        let numbers = Array(Rc::new((0..100).map(Int).collect()));
+        let described = describe_value(&numbers);
+        assert!(described.starts_with("ARRAY of 100 elements [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 1..."), "{}", described);
+        let err = run_source(&format!("[Chorus]\nNever gonna let a down\nNever gonna give a \"{}\"\nNever gonna say a + 1\n", long), "").unwrap_err();
+        assert!(err.to_string().contains(&format!("ARRAY of 41 characters \"{}...\" (variable a)", fits)), "{}", err);
+        let err = run_source(&format!("[Chorus]\nNever gonna let a down\nNever gonna give a \"{}\"\nYou know the rules and a is INT\n", long), "").unwrap_err();
+        assert_eq!(err.to_string(), format!("Type Error on line 4: Expected a to be INT but found ARRAY of 41 characters \"{}...\"", fits));
+    }
+
+    // values of every type, each comparable with the others of its type
+    // a new type of value needs samples here, and a row in comparable
+    fn samples() -> Vec<RickrollObject> {
+        return vec![
+            Int(-3),
+            Int(1),
+            Int(2),
+            Float(1.0),
+            Float(1.5),
+            Bool(false),
+            Bool(true),
+            Char('a'),
+            Char('b'),
+            Array(Rc::new(Vec::new())),
+            to_char_array("ab"),
+            to_char_array("abc"),
+            to_char_array("b"),
+            Undefined,
+        ];
+    }
+
+    // which types of values can be ordered against each other
+    fn comparable(a: &str, b: &str) -> bool {
+        return match (a, b) {
+            ("INT" | "FLOAT", "INT" | "FLOAT") => true,
+            ("BOOL", "BOOL") | ("CHAR", "CHAR") | ("ARRAY", "ARRAY") | ("UNDEFINED", "UNDEFINED") => true,
+            _ => false,
+        };
+    }
+
+    // evaluates a comparison operator the way the interpreter does
+    // operations keep their operands from the right one to the left one
+    fn operate(op: Operator, a: &RickrollObject, b: &RickrollObject) -> Result<RickrollObject, crate::error::Error> {
+        let expr = Expr::Operation(op, vec![Expr::Value(b.clone()), Expr::Value(a.clone())]);
+        return Interpreter::new(HashMap::new()).eval(&expr, &Scope::new());
+    }
+
+    fn sort(values: Vec<RickrollObject>) -> Result<RickrollObject, crate::error::Error> {
+        return BUILTIN_FUNCTIONS["ArraySort"](vec![Array(Rc::new(values))], &mut Vec::new(), &mut std::io::empty());
+    }
+
+    #[test]
+    fn samples_cover_every_type() {
+        let covered: Vec<&str> = TYPE_NAMES.iter().copied().filter(|name| samples().iter().any(|obj| obj.type_name() == *name)).collect();
+        assert_eq!(covered, TYPE_NAMES);
+    }
+
+    #[test]
+    fn comparison_matrix() {
+        for a in samples() {
+            for b in samples() {
+                let pair = format!("{:?} and {:?}", a, b);
+                let ord = compare(&a, &b);
+                assert_eq!(ord.is_some(), comparable(a.type_name(), b.type_name()), "{}", pair);
+                // antisymmetry
+                assert_eq!(ord, compare(&b, &a).map(Ordering::reverse), "{}", pair);
+                assert_eq!(equals(&a, &b), ord == Some(Ordering::Equal), "{}", pair);
+                // the operators agree with compare
+                let eq = operate(Operator::Equals, &a, &b).unwrap();
+                assert_eq!(eq.to_string(), Bool(equals(&a, &b)).to_string(), "{}", pair);
+                let ne = operate(Operator::NotEquals, &a, &b).unwrap();
+                assert_eq!(ne.to_string(), Bool(!equals(&a, &b)).to_string(), "{}", pair);
+                match (operate(Operator::Less, &a, &b), ord) {
+                    (Ok(less), Some(ord)) => assert_eq!(less.to_string(), Bool(ord == Ordering::Less).to_string(), "{}", pair),
+                    (Err(err), None) => assert_eq!(err.kind(), ErrorType::TypeError, "{}", pair),
+                    (res, ord) => panic!("{}: < gave {:?} but compare gave {:?}", pair, res, ord),
+                }
+                // ArraySort can sort exactly the values compare can order
+                assert_eq!(sort(vec![a.clone(), b.clone()]).is_ok(), ord.is_some(), "{}", pair);
+            }
+        }
+    }
+
+    #[test]
+    fn comparison_specifics() {
+        assert_eq!(compare(&Int(1), &Float(1.0)), Some(Ordering::Equal));
+        assert_eq!(compare(&Int(2), &Float(1.5)), Some(Ordering::Greater));
+        assert_eq!(compare(&Bool(false), &Bool(true)), Some(Ordering::Less));
+        // element by element, then by length
+        assert_eq!(compare(&to_char_array("ab"), &to_char_array("abc")), Some(Ordering::Less));
+        assert_eq!(compare(&to_char_array("abc"), &to_char_array("b")), Some(Ordering::Less));
+        // code points, so upper case comes first
+        assert_eq!(compare(&to_char_array("Zebra"), &to_char_array("apple")), Some(Ordering::Less));
+        // arrays are only comparable if their elements are
+        assert_eq!(compare(&to_char_array("a"), &Array(Rc::new(vec![Int(1)]))), None);
+        let nan = Float(f32::NAN);
+        assert_eq!(compare(&nan, &nan), None);
+        assert!(!equals(&nan, &nan));
+        assert_eq!(operate(Operator::Less, &nan, &Int(1)).unwrap().to_string(), "FALSE");
+        assert_eq!(operate(Operator::GreaterEquals, &nan, &nan).unwrap().to_string(), "FALSE");
+    }
+
+    #[test]
+    fn comparison_transitivity() {
+        let values = samples();
+        for a in &values {
+            for b in &values {
+                for c in &values {
+                    let le = |x: &RickrollObject, y: &RickrollObject| matches!(compare(x, y), Some(Ordering::Less | Ordering::Equal));
+                    if le(a, b) && le(b, c) {
+                        assert!(le(a, c), "{:?} <= {:?} <= {:?}", a, b, c);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sort_is_consistent_with_compare() {
+        let numbers = vec![Float(1.5), Int(2), Float(1.0), Int(-3), Int(1)];
+        // stable, so the INT 1 stays after the equal FLOAT 1.0
+        assert_eq!(sort(numbers).unwrap().to_string(), "[-3, 1, 1, 1.5, 2]");
+        let sorted = sort(vec![Float(1.0), Int(1)]).unwrap();
+        assert_eq!(format!("{:?}", sorted), format!("{:?}", Array(Rc::new(vec![Float(1.0), Int(1)]))));
+        let strings = vec![to_char_array("b"), to_char_array("abc"), Array(Rc::new(Vec::new())), to_char_array("ab")];
+        let sorted = sort(strings).unwrap();
+        let words: Vec<String> = match &sorted {
+            Array(arr) => arr.iter().map(|word| from_char_array(word).unwrap_or_default()).collect(),
+            _ => panic!("ArraySort returned {:?}", sorted),
+        };
+        assert_eq!(words, ["", "ab", "abc", "b"]);
+    }
+}
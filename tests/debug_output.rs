@@ -0,0 +1,14 @@
+use std::process::Command;
+
+// debugging information goes to stderr, so the output of a program run with --debug is its own
+#[test]
+fn debug_output_stays_on_stderr() {
+    let path = std::env::temp_dir().join(format!("rickroll-debug-{}.rr", std::process::id()));
+    std::fs::write(&path, "[Chorus]\nNever gonna let a down\nNever gonna give a 1\nNever gonna say a + 2\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rickroll")).arg("--debug").arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("declared variable a on line 2"), "{}", stderr);
+}
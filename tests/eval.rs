@@ -0,0 +1,35 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// runs rickroll --eval with the expression given on stdin
+fn eval(src: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rickroll"))
+        .arg("--eval")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(src.as_bytes()).unwrap();
+    return child.wait_with_output().unwrap();
+}
+
+#[test]
+fn eval_expression() {
+    let output = eval("2 + 3 * 4\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "14\n");
+    let output = eval("(1 < 2) && 'a' == 'a'");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "TRUE\n");
+}
+
+#[test]
+fn eval_bad_expression() {
+    let output = eval("2 + $ 4\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Illegal Character: Illegal character \"$\" in expression at column 5"), "{}", stderr);
+    // only the error is reported, without a traceback
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+}
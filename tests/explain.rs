@@ -0,0 +1,36 @@
+use std::process::{Command, Output};
+
+// runs a program, with the arguments given before its file
+fn run(name: &str, src: &str, args: &[&str]) -> Output {
+    let path = std::env::temp_dir().join(format!("rickroll-explain-{}-{}.rr", name, std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rickroll")).args(args).arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    return output;
+}
+
+const SRC: &str = "\
+[Chorus]
+Never gonna let a down
+Never gonna give a \"hi\"
+Never gonna say 1
+And if you ask me how 6 * 7 is feeling
+And if you ask me how a is feeling
+Never gonna say 2
+";
+
+#[test]
+fn explain_to_stdout() {
+    let output = run("stdout", SRC, &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n42 (INT)\n[h, i] (ARRAY)\n2\n");
+}
+
+#[test]
+fn explain_to_stderr() {
+    let output = run("stderr", SRC, &["--explain-to-stderr"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n2\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("42 (INT)\n[h, i] (ARRAY)\n"), "{}", stderr);
+}
@@ -0,0 +1,57 @@
+use std::process::{Command, Output};
+
+// runs a program with --summary, keeping its stdout and stderr apart
+fn run_with_summary(name: &str, src: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("rickroll-summary-{}-{}.rr", name, std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rickroll")).arg("--summary").arg(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    return output;
+}
+
+#[test]
+fn summary_of_finished_program() {
+    let src = "\
+[Intro]
+Never gonna let b down
+Never gonna give b 5
+Never gonna let a down
+
+[Chorus]
+Never gonna give a b * 2
+Never gonna say a
+Never gonna say b
+";
+    let output = run_with_summary("finished", src);
+    assert!(output.status.success());
+    // the summary is never part of the program's output
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "10\n5\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--- Summary ---"), "{}", stderr);
+    assert!(stderr.contains("Lines of output: 2\n"), "{}", stderr);
+    assert!(stderr.contains("Bytes of output: 5\n"), "{}", stderr);
+    assert!(stderr.contains("Wall time: "), "{}", stderr);
+    // the variables declared in the Intro are listed in declaration order
+    assert!(stderr.contains("Global variables:\n  b = 5\n  a = 10"), "{}", stderr);
+}
+
+#[test]
+fn summary_of_failed_program() {
+    let src = "\
+[Intro]
+Never gonna let a down
+
+[Chorus]
+Never gonna give a 3
+Never gonna say a
+Never gonna give a a / 0
+Never gonna say a
+";
+    let output = run_with_summary("failed", src);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--- Summary (program failed) ---"), "{}", stderr);
+    assert!(stderr.contains("Lines of output: 1\n"), "{}", stderr);
+    // the state when the program failed
+    assert!(stderr.contains("Global variables:\n  a = 3"), "{}", stderr);
+}